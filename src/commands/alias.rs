@@ -1,66 +1,93 @@
 use anyhow::Result;
 use console::Style;
+use serde_json::json;
 use std::collections::HashMap;
 
 use crate::config::Config;
+use crate::output::{self, Output};
 
-pub fn add(name: &str, query: &str) -> Result<()> {
+pub fn add(name: &str, query: &str, out: Output) -> Result<()> {
     let mut config = Config::load()?;
     let aliases = config.aliases.get_or_insert_with(HashMap::new);
     aliases.insert(name.to_string(), query.to_string());
     config.save()?;
 
-    let green = Style::new().green().bold();
-    let dim = Style::new().dim();
-    println!(
-        "  {} {} {} {}",
-        green.apply_to("✓"),
-        green.apply_to(name),
-        dim.apply_to("→"),
-        query,
-    );
+    match out {
+        Output::Json => output::print_json(&json!({ "name": name, "query": query })),
+        Output::Plain => println!("{name}\t{query}"),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            println!(
+                "  {} {} {} {}",
+                green.apply_to("✓"),
+                green.apply_to(name),
+                dim.apply_to("→"),
+                query,
+            );
+        }
+    }
     Ok(())
 }
 
-pub fn remove(name: &str) -> Result<()> {
+pub fn remove(name: &str, out: Output) -> Result<()> {
     let mut config = Config::load()?;
-    if let Some(aliases) = &mut config.aliases {
-        aliases.remove(name);
-    }
+    let removed = match &mut config.aliases {
+        Some(aliases) => aliases.remove(name).is_some(),
+        None => false,
+    };
     config.save()?;
 
-    let dim = Style::new().dim();
-    println!("  {} Removed alias \"{}\"", dim.apply_to("✓"), name);
+    match out {
+        Output::Json => output::print_json(&json!({ "name": name, "removed": removed })),
+        Output::Plain => println!("{name}"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            println!("  {} Removed alias \"{}\"", dim.apply_to("✓"), name);
+        }
+    }
     Ok(())
 }
 
-pub fn list() -> Result<()> {
+pub fn list(out: Output) -> Result<()> {
     let config = Config::load()?;
-    let green = Style::new().green().bold();
-    let dim = Style::new().dim();
+    let aliases = config.aliases.unwrap_or_default();
+    let mut sorted: Vec<_> = aliases.iter().collect();
+    sorted.sort_by_key(|(k, _)| *k);
 
-    match &config.aliases {
-        Some(aliases) if !aliases.is_empty() => {
-            println!("\n  {}\n", green.apply_to("Aliases"));
-            let mut sorted: Vec<_> = aliases.iter().collect();
-            sorted.sort_by_key(|(k, _)| k.clone());
+    match out {
+        Output::Json => output::print_json(&aliases),
+        Output::Plain => {
             for (name, query) in sorted {
-                println!(
-                    "  {} {} {}",
-                    green.apply_to(name),
-                    dim.apply_to("→"),
-                    query,
-                );
+                println!("{name}\t{query}");
             }
-            println!();
-        }
-        _ => {
-            println!("  No aliases configured.");
-            println!(
-                "  Add one with: {}",
-                dim.apply_to("spot alias add white-album \"The Beatles\"")
-            );
         }
+        Output::Pretty => print_list(&sorted),
     }
     Ok(())
 }
+
+fn print_list(sorted: &[(&String, &String)]) {
+    let green = Style::new().green().bold();
+    let dim = Style::new().dim();
+
+    if sorted.is_empty() {
+        println!("  No aliases configured.");
+        println!(
+            "  Add one with: {}",
+            dim.apply_to("spot alias add white-album \"The Beatles\"")
+        );
+        return;
+    }
+
+    println!("\n  {}\n", green.apply_to("Aliases"));
+    for (name, query) in sorted {
+        println!(
+            "  {} {} {}",
+            green.apply_to(name),
+            dim.apply_to("→"),
+            query,
+        );
+    }
+    println!();
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use chrono::{NaiveTime, Weekday};
+use console::Style;
+use serde_json::json;
+
+use crate::display;
+use crate::output::{self, Output};
+use crate::playback::PlayMode;
+use crate::schedule::{self, Alarm, Clock};
+use crate::spotify::player::Player;
+
+/// What to schedule, as given on the command line.
+pub struct Request {
+    pub time: NaiveTime,
+    pub days: Vec<Weekday>,
+    pub query: String,
+    pub mode: PlayMode,
+    pub volume: Option<i32>,
+    pub ramp: Option<std::time::Duration>,
+}
+
+pub fn add(request: Request, clock: &dyn Clock, out: Output) -> Result<()> {
+    let now = clock.now();
+    let date = request
+        .days
+        .is_empty()
+        .then(|| schedule::next_date(request.time, now));
+    let alarm = schedule::add(Alarm {
+        id: 0,
+        time: request.time,
+        days: request.days,
+        date,
+        query: request.query,
+        mode: request.mode,
+        volume: request.volume,
+        ramp_ms: request.ramp.map(|r| u64::try_from(r.as_millis()).unwrap_or(u64::MAX)),
+    })?;
+
+    match out {
+        Output::Json => output::print_json(&alarm),
+        Output::Plain => print_plain(&alarm),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            println!("  {} {}", green.apply_to("⏰"), describe(&alarm));
+            println!("  {}", dim.apply_to("Runs while spot at --serve is running."));
+        }
+    }
+    Ok(())
+}
+
+pub fn list(clock: &dyn Clock, out: Output) -> Result<()> {
+    let now = clock.now();
+    let mut alarms = schedule::load()?;
+    schedule::sort_by_next(&mut alarms, now);
+
+    match out {
+        Output::Json => {
+            let entries: Vec<_> = alarms
+                .iter()
+                .map(|a| json!({ "alarm": a, "next": a.next_after(now) }))
+                .collect();
+            output::print_json(&entries);
+        }
+        Output::Plain => alarms.iter().for_each(print_plain),
+        Output::Pretty => {
+            if alarms.is_empty() {
+                println!("  Nothing scheduled.");
+                return Ok(());
+            }
+            let dim = Style::new().dim();
+            for alarm in &alarms {
+                let next = match alarm.next_after(now) {
+                    Some(at) => format!("next {}", at.format("%a %d %b %H:%M")),
+                    None => "passed".to_string(),
+                };
+                println!("  {} {}", describe(alarm), dim.apply_to(format!("· {next}")));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn remove(id: u32, out: Output) -> Result<()> {
+    let alarm = schedule::remove(id)?;
+    match out {
+        Output::Json => output::print_json(&json!({ "removed": alarm })),
+        Output::Plain => print_plain(&alarm),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            println!("  {} Removed {}", dim.apply_to("✓"), describe(&alarm));
+        }
+    }
+    Ok(())
+}
+
+pub async fn serve(player: &Player, clock: &dyn Clock, out: Output) -> Result<()> {
+    if out == Output::Pretty {
+        let red = Style::new().red().bold();
+        let dim = Style::new().dim();
+        let count = schedule::load()?.len();
+        println!("  {} Serving {count} scheduled playbacks", red.apply_to("●"));
+        println!("  {}", dim.apply_to("Press ctrl-c to stop."));
+    }
+    schedule::serve(player, clock, out).await
+}
+
+fn print_plain(alarm: &Alarm) {
+    println!(
+        "{}\t{}\t{}\t{}",
+        alarm.id,
+        alarm.time.format("%H:%M"),
+        alarm.describe_days(),
+        alarm.query
+    );
+}
+
+/// `m:ss`, or seconds with a decimal when the ramp isn't whole seconds.
+fn describe_ramp(ms: u64) -> String {
+    if ms.is_multiple_of(1000) {
+        display::format_duration(i64::try_from(ms).unwrap_or(i64::MAX))
+    } else {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    }
+}
+
+fn describe(alarm: &Alarm) -> String {
+    let green = Style::new().green().bold();
+    let dim = Style::new().dim();
+    let mut extras = Vec::new();
+    if let Some(volume) = alarm.volume {
+        extras.push(format!("volume {volume}%"));
+    }
+    if let Some(ramp) = alarm.ramp_ms {
+        extras.push(format!("ramp {}", describe_ramp(ramp)));
+    }
+    let extras = if extras.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", extras.join(", "))
+    };
+    format!(
+        "{} {} {} {}{}",
+        dim.apply_to(format!("#{}", alarm.id)),
+        green.apply_to(alarm.time.format("%H:%M")),
+        dim.apply_to(alarm.describe_days()),
+        alarm.query,
+        dim.apply_to(extras),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schedule::FixedClock;
+    use crate::test_support::{ConfigHome, env_lock};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn keeps_sub_second_ramps() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let clock = FixedClock::at("2026-10-19T06:00:00".parse().unwrap());
+        for ramp in [500, 1500] {
+            let request = Request {
+                time: schedule::parse_time("07:30").unwrap(),
+                days: Vec::new(),
+                query: "morning".to_string(),
+                mode: PlayMode::Artist,
+                volume: None,
+                ramp: Some(Duration::from_millis(ramp)),
+            };
+            add(request, &clock, Output::Json).unwrap();
+        }
+        let ramps: Vec<_> = schedule::load().unwrap().iter().map(|a| a.ramp_ms).collect();
+        assert_eq!(ramps, vec![Some(500), Some(1500)]);
+        assert_eq!(describe_ramp(1500), "1.5s");
+        assert_eq!(describe_ramp(300_000), "5:00");
+    }
+}
@@ -1,50 +1,209 @@
 use anyhow::Result;
 use console::Style;
 use dialoguer::Input;
+use serde::Serialize;
+use serde_json::json;
 
 use crate::config::Config;
+use crate::output::{self, Output};
 use crate::spotify;
 
-pub async fn run(status: bool) -> Result<()> {
+pub async fn run(status: bool, out: Output) -> Result<()> {
     let config = Config::load()?;
 
     if status {
-        return show_status(&config).await;
+        return show_status(&config, out).await;
     }
 
     setup_credentials().await
 }
 
-async fn show_status(config: &Config) -> Result<()> {
+pub async fn login(out: Output) -> Result<()> {
+    let config = Config::load()?;
     let green = Style::new().green().bold();
-    let red = Style::new().red().bold();
     let dim = Style::new().dim();
 
-    if !config.has_credentials() {
-        println!("{} No credentials configured.", red.apply_to("✗"));
-        println!("  Run {} to set up.", green.apply_to("spot auth"));
-        return Ok(());
+    let redirect_uri = config
+        .redirect_uri
+        .as_deref()
+        .unwrap_or(spotify::auth::DEFAULT_REDIRECT_URI);
+    if out == Output::Pretty {
+        println!(
+            "\n  {} Logging in to Spotify\n",
+            green.apply_to("♫")
+        );
+        println!(
+            "  {}",
+            dim.apply_to(format!("Make sure {redirect_uri} is a Redirect URI of your app."))
+        );
+    }
+
+    let token = spotify::auth::login(&config).await?;
+    match out {
+        Output::Json => output::print_json(&json!({
+            "logged_in": true,
+            "scopes": token.scope.split_whitespace().collect::<Vec<_>>(),
+            "expires_at": token.expires_at,
+        })),
+        Output::Plain => println!("logged in"),
+        Output::Pretty => println!(
+            "\n  {} Logged in. Token expires {}.",
+            green.apply_to("✓"),
+            format_expiry(token.expires_at)
+        ),
+    }
+    Ok(())
+}
+
+pub fn logout(out: Output) -> Result<()> {
+    let removed = spotify::auth::remove_user_token()?;
+    match out {
+        Output::Json => output::print_json(&json!({ "logged_out": removed })),
+        Output::Plain => println!("{}", if removed { "logged out" } else { "not logged in" }),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            if removed {
+                println!("  {} Logged out", dim.apply_to("✓"));
+            } else {
+                println!("  Not logged in.");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn format_expiry(expires_at: u64) -> String {
+    let now = spotify::auth::now_secs();
+    if expires_at <= now {
+        let mins = (now - expires_at) / 60;
+        return format!("expired {mins} min ago");
+    }
+    let mins = (expires_at - now) / 60;
+    if mins >= 60 {
+        format!("in {}h {:02}m", mins / 60, mins % 60)
+    } else {
+        format!("in {mins} min")
+    }
+}
+
+#[derive(Serialize)]
+struct Status {
+    configured: bool,
+    client_id: Option<String>,
+    valid: bool,
+    error: Option<String>,
+    /// `authorization_code` after `spot auth login`, else `client_credentials`.
+    grant: Option<&'static str>,
+    scopes: Vec<String>,
+    expires_at: Option<u64>,
+}
+
+async fn collect_status(config: &Config) -> Status {
+    // A PKCE login needs only the client_id.
+    let logged_in = config.client_id.is_some() && spotify::auth::load_user_token().is_some();
+    let mut status = Status {
+        configured: config.has_credentials() || logged_in,
+        client_id: None,
+        valid: false,
+        error: None,
+        grant: None,
+        scopes: Vec::new(),
+        expires_at: None,
+    };
+    if !status.configured {
+        return status;
     }
 
     let client_id = config.client_id.as_deref().unwrap_or("");
-    let masked = if client_id.len() > 8 {
+    status.client_id = Some(if client_id.len() > 8 {
         format!("{}…{}", &client_id[..4], &client_id[client_id.len() - 4..])
     } else {
         "****".to_string()
-    };
+    });
 
-    println!("  Client ID: {}", dim.apply_to(&masked));
+    if let Err(e) = spotify::auth::get_token(config).await {
+        status.error = Some(e.to_string());
+        return status;
+    }
+    status.valid = true;
 
-    match spotify::auth::get_token(&config).await {
-        Ok(_) => println!("{} Credentials are valid.", green.apply_to("✓")),
-        Err(e) => println!(
+    // get_token falls back to client credentials when the login is unusable.
+    let user = spotify::auth::load_user_token().filter(|t| t.expires_at > spotify::auth::now_secs());
+    match user {
+        Some(token) => {
+            status.grant = Some("authorization_code");
+            status.scopes = token.scope.split_whitespace().map(String::from).collect();
+            status.expires_at = Some(token.expires_at);
+        }
+        None => {
+            status.grant = Some("client_credentials");
+            status.expires_at = spotify::auth::cached_token_expiry();
+        }
+    }
+    status
+}
+
+async fn show_status(config: &Config, out: Output) -> Result<()> {
+    let status = collect_status(config).await;
+    match out {
+        Output::Json => output::print_json(&status),
+        Output::Plain => {
+            println!("configured\t{}", status.configured);
+            println!("valid\t{}", status.valid);
+            if let Some(grant) = status.grant {
+                println!("grant\t{grant}");
+                println!("scopes\t{}", status.scopes.join(" "));
+            }
+            if let Some(expires_at) = status.expires_at {
+                println!("expires_at\t{expires_at}");
+            }
+        }
+        Output::Pretty => print_status(&status),
+    }
+    Ok(())
+}
+
+fn print_status(status: &Status) {
+    let green = Style::new().green().bold();
+    let red = Style::new().red().bold();
+    let dim = Style::new().dim();
+
+    if !status.configured {
+        println!("{} No credentials configured.", red.apply_to("✗"));
+        println!("  Run {} to set up.", green.apply_to("spot auth"));
+        return;
+    }
+
+    if let Some(client_id) = &status.client_id {
+        println!("  Client ID: {}", dim.apply_to(client_id));
+    }
+
+    if let Some(error) = &status.error {
+        println!(
             "{} Credentials are invalid: {}",
             red.apply_to("✗"),
-            e
-        ),
+            error
+        );
+        return;
     }
+    println!("{} Credentials are valid.", green.apply_to("✓"));
 
-    Ok(())
+    if status.grant == Some("authorization_code") {
+        println!("  Grant:     {}", dim.apply_to("authorization code (PKCE)"));
+        println!("  Scopes:    {}", dim.apply_to(status.scopes.join(", ")));
+    } else {
+        println!("  Grant:     {}", dim.apply_to("client credentials"));
+        println!("  Scopes:    {}", dim.apply_to("none (no user data or playback)"));
+    }
+    if let Some(expires_at) = status.expires_at {
+        println!("  Expires:   {}", dim.apply_to(format_expiry(expires_at)));
+    }
+    if status.grant != Some("authorization_code") {
+        println!(
+            "  Run {} for playback, library and queue access.",
+            green.apply_to("spot auth login")
+        );
+    }
 }
 
 async fn setup_credentials() -> Result<()> {
@@ -78,7 +237,7 @@ async fn setup_credentials() -> Result<()> {
 
     print!("\n  Validating credentials... ");
 
-    match spotify::auth::get_token(&config).await {
+    match spotify::auth::get_app_token(&config).await {
         Ok(_) => {
             config.save()?;
             println!("{}", green.apply_to("valid!"));
@@ -96,3 +255,32 @@ async fn setup_credentials() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ConfigHome, env_lock};
+
+    #[tokio::test]
+    async fn a_pkce_login_without_a_secret_is_configured() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        let config = Config {
+            client_id: Some("0123456789abcdef".to_string()),
+            ..Config::default()
+        };
+        assert!(!collect_status(&config).await.configured);
+
+        let expires_at = spotify::auth::now_secs() + 3600;
+        std::fs::write(
+            home.dir.join("user_token.json"),
+            format!(r#"{{"access_token":"t","refresh_token":"r","expires_at":{expires_at},"scope":"user-read-playback-state"}}"#),
+        )
+        .unwrap();
+        let status = collect_status(&config).await;
+        assert!(status.configured);
+        assert!(status.valid, "{:?}", status.error);
+        assert_eq!(status.grant, Some("authorization_code"));
+        assert_eq!(status.client_id.as_deref(), Some("0123…cdef"));
+    }
+}
@@ -1,108 +1,355 @@
 use anyhow::Result;
 use console::Style;
+use serde_json::json;
+use std::time::Duration;
 
-use crate::spotify::applescript;
+use crate::config::{Config, VolumeCurve};
+use crate::display;
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::spotify::player::{NowPlayingInfo, Player};
+use crate::volume;
 
-pub fn pause() -> Result<()> {
-    applescript::pause()?;
-    let dim = Style::new().dim();
-    println!("  {} Paused", dim.apply_to("⏸"));
-    Ok(())
+/// The fade for pause and resume: `--fade`, else `[volume] pause_fade_ms`.
+fn pause_fade(fade: Option<Duration>) -> Result<Option<(Duration, VolumeCurve)>> {
+    let settings = Config::load()?.volume_settings();
+    Ok(fade
+        .or(settings.pause_fade())
+        .filter(|f| !f.is_zero())
+        .map(|f| (f, settings.curve)))
+}
+
+/// Ramps down, pauses, then puts the volume back for next time.
+async fn fade_out(player: &Player, fade: Duration, curve: VolumeCurve) -> Result<()> {
+    let vol = player.get_volume().await?;
+    ramp_or_restore(player, vol, 0, fade, curve, vol).await?;
+    player.pause().await?;
+    player.set_volume(vol).await
+}
+
+async fn fade_in(player: &Player, fade: Duration, curve: VolumeCurve) -> Result<()> {
+    let vol = player.get_volume().await?;
+    player.set_volume(0).await?;
+    player.resume().await?;
+    ramp_or_restore(player, 0, vol, fade, curve, vol).await
+}
+
+/// Ramps from `from` to `to`, but on ctrl-c or SIGTERM sets `restore` and
+/// gives up, so an interrupted fade never leaves the volume half way.
+async fn ramp_or_restore(
+    player: &Player,
+    from: i32,
+    to: i32,
+    fade: Duration,
+    curve: VolumeCurve,
+    restore: i32,
+) -> Result<()> {
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        result = volume::ramp(player, from, to, fade, curve) => return result,
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+    player.set_volume(restore).await?;
+    Err(fail(ErrorKind::Other, "Fade interrupted; volume put back"))
+}
+
+async fn is_playing(player: &Player) -> bool {
+    player.get_now_playing().await.is_ok_and(|info| info.is_playing)
 }
 
-pub fn resume() -> Result<()> {
-    applescript::resume()?;
-    let dim = Style::new().dim();
-    println!("  {} Resumed", dim.apply_to("▶"));
+pub async fn pause(player: &Player, fade: Option<Duration>, out: Output) -> Result<()> {
+    match pause_fade(fade)? {
+        Some((fade, curve)) if is_playing(player).await => fade_out(player, fade, curve).await?,
+        _ => player.pause().await?,
+    }
+    match out {
+        Output::Json => output::print_json(&json!({ "is_playing": false })),
+        Output::Plain => println!("paused"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            println!("  {} Paused", dim.apply_to("⏸"));
+        }
+    }
     Ok(())
 }
 
-pub fn toggle() -> Result<()> {
-    applescript::toggle()?;
+pub async fn resume(player: &Player, fade: Option<Duration>, out: Output) -> Result<()> {
+    match pause_fade(fade)? {
+        Some((fade, curve)) if !is_playing(player).await => fade_in(player, fade, curve).await?,
+        _ => player.resume().await?,
+    }
+    match out {
+        Output::Json => output::print_json(&json!({ "is_playing": true })),
+        Output::Plain => println!("playing"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            println!("  {} Resumed", dim.apply_to("▶"));
+        }
+    }
     Ok(())
 }
 
-pub fn next() -> Result<()> {
-    applescript::next_track()?;
-    // Small delay so Spotify updates the current track
-    std::thread::sleep(std::time::Duration::from_millis(200));
-    if let Ok(info) = applescript::get_now_playing() {
-        let green = Style::new().green().bold();
-        let dim = Style::new().dim();
-        println!(
-            "  {} {} {}",
-            green.apply_to("⏭"),
-            info.track_name,
-            dim.apply_to(format!("— {}", info.artist))
-        );
+pub async fn toggle(player: &Player, fade: Option<Duration>, out: Output) -> Result<()> {
+    match pause_fade(fade)? {
+        Some((fade, curve)) if is_playing(player).await => fade_out(player, fade, curve).await?,
+        Some((fade, curve)) => fade_in(player, fade, curve).await?,
+        None => player.toggle().await?,
+    }
+    if out != Output::Pretty {
+        print_track_change(&current_track(player).await, "", out);
     }
     Ok(())
 }
 
-pub fn prev() -> Result<()> {
-    applescript::prev_track()?;
-    std::thread::sleep(std::time::Duration::from_millis(200));
-    if let Ok(info) = applescript::get_now_playing() {
-        let green = Style::new().green().bold();
-        let dim = Style::new().dim();
-        println!(
-            "  {} {} {}",
-            green.apply_to("⏮"),
-            info.track_name,
-            dim.apply_to(format!("— {}", info.artist))
-        );
+/// Re-reads the player after a track change, giving Spotify a moment first.
+async fn current_track(player: &Player) -> Option<NowPlayingInfo> {
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    player.get_now_playing().await.ok()
+}
+
+fn print_track_change(info: &Option<NowPlayingInfo>, icon: &str, out: Output) {
+    match out {
+        Output::Json => output::print_json(&json!({ "now_playing": info })),
+        Output::Plain => {
+            if let Some(info) = info {
+                let state = if info.is_playing { "playing" } else { "paused" };
+                println!("{}\t{}\t{state}", info.track_name, info.artist);
+            }
+        }
+        Output::Pretty => {
+            if let Some(info) = info {
+                let green = Style::new().green().bold();
+                let dim = Style::new().dim();
+                println!(
+                    "  {} {} {}",
+                    green.apply_to(icon),
+                    info.track_name,
+                    dim.apply_to(format!("— {}", info.artist))
+                );
+            }
+        }
     }
+}
+
+pub async fn next(player: &Player, out: Output) -> Result<()> {
+    player.next_track().await?;
+    print_track_change(&current_track(player).await, "⏭", out);
     Ok(())
 }
 
-pub fn volume(level: Option<u32>) -> Result<()> {
-    let dim = Style::new().dim();
-    match level {
-        Some(vol) => {
-            let vol = vol.min(100) as i32;
-            applescript::set_volume(vol)?;
-            println!("  {} Volume: {}%", dim.apply_to("🔊"), vol);
+pub async fn prev(player: &Player, out: Output) -> Result<()> {
+    player.prev_track().await?;
+    print_track_change(&current_track(player).await, "⏮", out);
+    Ok(())
+}
+
+/// Sets the volume to `level` (absolute or relative, see
+/// [`volume::parse_level`]), ramping over `over` if given.
+pub async fn volume(player: &Player, level: Option<String>, over: Option<Duration>, out: Output) -> Result<()> {
+    let current = player.get_volume().await?;
+    let vol = match level {
+        Some(level) => {
+            let settings = Config::load()?.volume_settings();
+            let vol = volume::parse_level(&level, current, settings.step())?;
+            volume::set(player, current, vol, over, settings.curve).await?;
+            // Choosing a level ends a mute.
+            volume::clear_muted();
+            vol
         }
-        None => {
-            let vol = applescript::get_volume()?;
+        None => current,
+    };
+    print_volume(vol, out);
+    Ok(())
+}
+
+fn print_volume(vol: i32, out: Output) {
+    match out {
+        Output::Json => output::print_json(&json!({ "volume": vol })),
+        Output::Plain => println!("{vol}"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
             println!("  {} Volume: {}%", dim.apply_to("🔊"), vol);
         }
     }
-    Ok(())
 }
 
-pub fn shuffle(state: Option<String>) -> Result<()> {
-    let dim = Style::new().dim();
-    match state {
-        Some(s) => {
-            let on = matches!(s.to_lowercase().as_str(), "on" | "true" | "1");
-            applescript::set_shuffle(on)?;
-            let label = if on { "on" } else { "off" };
-            println!("  {} Shuffle: {}", dim.apply_to("🔀"), label);
+pub async fn mute(player: &Player, over: Option<Duration>, out: Output) -> Result<()> {
+    let current = player.get_volume().await?;
+    // Muting twice keeps the level from the first time.
+    let restore = match volume::muted_volume() {
+        Some(saved) if current == 0 => saved,
+        _ => current,
+    };
+    if current > 0 {
+        volume::save_muted(current)?;
+        let curve = Config::load()?.volume_settings().curve;
+        volume::set(player, current, 0, over, curve).await?;
+    }
+    match out {
+        Output::Json => output::print_json(&json!({ "volume": 0, "muted": true, "restore": restore })),
+        Output::Plain => println!("muted\t{restore}"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            println!("  {} Muted {}", dim.apply_to("🔇"), dim.apply_to(format!("(was {restore}%)")));
         }
-        None => {
-            let on = applescript::get_shuffle()?;
-            let label = if on { "on" } else { "off" };
-            println!("  {} Shuffle: {}", dim.apply_to("🔀"), label);
+    }
+    Ok(())
+}
+
+pub async fn unmute(player: &Player, over: Option<Duration>, out: Output) -> Result<()> {
+    let Some(vol) = volume::muted_volume() else {
+        return Err(fail(ErrorKind::Usage, "Not muted. Set a level with: spot vol 50"));
+    };
+    let current = player.get_volume().await?;
+    let curve = Config::load()?.volume_settings().curve;
+    volume::set(player, current, vol, over, curve).await?;
+    volume::clear_muted();
+    print_volume(vol, out);
+    Ok(())
+}
+
+/// Moves to `target` in the current track: a position like `1:32` or
+/// `90s`, an offset like `+15s` or `-30`, or a percentage like `50%`.
+pub async fn seek(player: &Player, target: &str, out: Output) -> Result<()> {
+    let mut info = player.get_now_playing().await?;
+    info.position_ms = seek_target(target, info.position_ms, info.duration_ms)?;
+    player.seek(info.position_ms).await?;
+    print_position(&info, out);
+    Ok(())
+}
+
+/// Where `target` (`1:32`, `50%`, `+30s`, `-10s`) lands in a track of
+/// `duration_ms` played up to `position_ms`, kept within the track.
+fn seek_target(target: &str, position_ms: i64, duration_ms: i64) -> Result<i64> {
+    let position = if let Some(pct) = target.strip_suffix('%') {
+        let pct: f64 = pct
+            .parse()
+            .ok()
+            .filter(|pct: &f64| pct.is_finite())
+            .ok_or_else(|| fail(ErrorKind::Usage, format!("Invalid percentage \"{target}\"")))?;
+        // `as` saturates, and the clamp below does the rest.
+        (duration_ms as f64 * pct / 100.0) as i64
+    } else if let Some(offset) = target.strip_prefix('+') {
+        position_ms.saturating_add(display::parse_timestamp(offset)?)
+    } else if let Some(offset) = target.strip_prefix('-') {
+        position_ms.saturating_sub(display::parse_timestamp(offset)?)
+    } else {
+        display::parse_timestamp(target)?
+    };
+    Ok(position.clamp(0, duration_ms.max(0)))
+}
+
+/// Jumps back to the start of the current track.
+pub async fn restart(player: &Player, out: Output) -> Result<()> {
+    let mut info = player.get_now_playing().await?;
+    player.seek(0).await?;
+    info.position_ms = 0;
+    print_position(&info, out);
+    Ok(())
+}
+
+fn print_position(info: &NowPlayingInfo, out: Output) {
+    match out {
+        Output::Json => output::print_json(&json!({ "now_playing": info })),
+        Output::Plain => println!(
+            "{}\t{}",
+            display::format_duration(info.position_ms),
+            display::format_duration(info.duration_ms),
+        ),
+        Output::Pretty => println!("{}", display::now_playing_lines(info).1),
+    }
+}
+
+fn parse_switch(s: &str) -> bool {
+    matches!(s.to_lowercase().as_str(), "on" | "true" | "1")
+}
+
+fn print_switch(key: &str, label: &str, icon: &str, on: bool, out: Output) {
+    let state = if on { "on" } else { "off" };
+    match out {
+        Output::Json => output::print_json(&json!({ key: on })),
+        Output::Plain => println!("{state}"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            println!("  {} {}: {}", dim.apply_to(icon), label, state);
         }
     }
+}
+
+pub async fn shuffle(player: &Player, state: Option<String>, out: Output) -> Result<()> {
+    let on = match state {
+        Some(s) => {
+            let on = parse_switch(&s);
+            player.set_shuffle(on).await?;
+            on
+        }
+        None => player.get_shuffle().await?,
+    };
+    print_switch("shuffle", "Shuffle", "🔀", on, out);
     Ok(())
 }
 
-pub fn repeat(state: Option<String>) -> Result<()> {
-    let dim = Style::new().dim();
-    match state {
+pub async fn repeat(player: &Player, state: Option<String>, out: Output) -> Result<()> {
+    let on = match state {
         Some(s) => {
-            let on = matches!(s.to_lowercase().as_str(), "on" | "true" | "1");
-            applescript::set_repeat(on)?;
-            let label = if on { "on" } else { "off" };
-            println!("  {} Repeat: {}", dim.apply_to("🔁"), label);
+            let on = parse_switch(&s);
+            player.set_repeat(on).await?;
+            on
         }
-        None => {
-            let on = applescript::get_repeat()?;
-            let label = if on { "on" } else { "off" };
-            println!("  {} Repeat: {}", dim.apply_to("🔁"), label);
+        None => player.get_repeat().await?,
+    };
+    print_switch("repeat", "Repeat", "🔁", on, out);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::kind_of;
+
+    /// Four minutes in, of a five minute track.
+    fn target(target: &str) -> Result<i64> {
+        seek_target(target, 240_000, 300_000)
+    }
+
+    #[test]
+    fn seeks_to_a_timestamp() {
+        assert_eq!(target("1:32").unwrap(), 92_000);
+        assert_eq!(target("90s").unwrap(), 90_000);
+        assert_eq!(target("0").unwrap(), 0);
+    }
+
+    #[test]
+    fn seeks_to_a_percentage() {
+        assert_eq!(target("50%").unwrap(), 150_000);
+        assert_eq!(target("12.5%").unwrap(), 37_500);
+        assert_eq!(target("0%").unwrap(), 0);
+        assert_eq!(target("100%").unwrap(), 300_000);
+    }
+
+    #[test]
+    fn seeks_relative_to_the_position() {
+        assert_eq!(target("+30s").unwrap(), 270_000);
+        assert_eq!(target("-1:00").unwrap(), 180_000);
+    }
+
+    #[test]
+    fn stays_within_the_track() {
+        assert_eq!(target("+2m").unwrap(), 300_000);
+        assert_eq!(target("-5m").unwrap(), 0);
+        assert_eq!(target("9:00").unwrap(), 300_000);
+        assert_eq!(target("250%").unwrap(), 300_000);
+        assert_eq!(target("-50%").unwrap(), 0);
+        assert_eq!(target("1e300%").unwrap(), 300_000);
+        assert_eq!(seek_target("+153722867280912:00", i64::MAX - 1, i64::MAX).unwrap(), i64::MAX);
+    }
+
+    #[test]
+    fn rejects_bad_targets() {
+        for bad in ["", "half", "%", "nan%", "inf%", "+", "-x", "1:75"] {
+            let err = target(bad).unwrap_err();
+            assert_eq!(kind_of(&err), ErrorKind::Usage, "{bad:?}");
         }
     }
-    Ok(())
 }
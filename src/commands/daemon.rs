@@ -0,0 +1,77 @@
+use anyhow::Result;
+use console::Style;
+
+use crate::config::Config;
+use serde_json::json;
+
+use crate::daemon::{self, Request};
+use crate::output::{self, Output};
+use crate::spotify::player::Backend;
+
+pub async fn run(backend: Option<Backend>) -> Result<()> {
+    let config = Config::load()?;
+    let backend = Backend::resolve(backend, &config);
+
+    let green = Style::new().green().bold();
+    let dim = Style::new().dim();
+    println!(
+        "  {} spot daemon listening on {} {}",
+        green.apply_to("♫"),
+        daemon::socket_path()?.display(),
+        dim.apply_to(format!("({backend:?})")),
+    );
+
+    daemon::serve(backend, config).await
+}
+
+pub async fn stop(out: Output) -> Result<()> {
+    let stopped = match daemon::Client::connect() {
+        Some(client) => match client.try_call::<()>(Request::Shutdown).await {
+            Some(result) => result.map(|()| true)?,
+            None => false,
+        },
+        None => false,
+    };
+    match out {
+        Output::Json => output::print_json(&json!({ "stopped": stopped })),
+        Output::Plain => println!("{}", if stopped { "stopped" } else { "not running" }),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            if stopped {
+                println!("  {} Daemon stopped", dim.apply_to("✓"));
+            } else {
+                println!("  Daemon is not running.");
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn status(out: Output) -> Result<()> {
+    let running = match daemon::Client::connect() {
+        Some(client) => client.call::<()>(Request::Ping).await.is_ok(),
+        None => false,
+    };
+    let socket = daemon::socket_path()?;
+    match out {
+        Output::Json => output::print_json(&json!({
+            "running": running,
+            "socket": socket,
+        })),
+        Output::Plain => println!("{}", if running { "running" } else { "not running" }),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            if running {
+                println!(
+                    "  {} Daemon is running {}",
+                    green.apply_to("✓"),
+                    dim.apply_to(socket.display()),
+                );
+            } else {
+                println!("  Daemon is not running.");
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use console::Style;
+use serde_json::json;
+
+use crate::config::Config;
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::spotify::{auth, device};
+
+pub async fn list(out: Output) -> Result<()> {
+    let config = Config::load()?;
+    let token = auth::get_user_token(&config).await?;
+    let devices = device::list(&token).await?;
+
+    match out {
+        Output::Json => output::print_json(&devices),
+        Output::Plain => {
+            for d in &devices {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    d.id.as_deref().unwrap_or(""),
+                    d.name,
+                    d.kind,
+                    d.volume_percent.map(|v| v.to_string()).unwrap_or_default(),
+                    if d.is_active { "active" } else { "" },
+                );
+            }
+        }
+        Output::Pretty => {
+            if devices.is_empty() {
+                println!("  No devices found. Open Spotify on one first.");
+                return Ok(());
+            }
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            // Resolved against the whole list, so a name that matches
+            // several devices marks none of them.
+            let default = config.default_device.as_deref().map(|q| device::find(&devices, q));
+            for d in &devices {
+                let marker = if d.is_active { green.apply_to("▶") } else { dim.apply_to(" ") };
+                let name = if d.is_active { green.apply_to(&d.name) } else { Style::new().apply_to(&d.name) };
+                let mut details = vec![d.kind.clone()];
+                if let Some(volume) = d.volume_percent {
+                    details.push(format!("{volume}%"));
+                }
+                if matches!(&default, Some(Ok(target)) if std::ptr::eq(*target, d)) {
+                    details.push("default".to_string());
+                }
+                println!("  {marker} {name} {}", dim.apply_to(details.join(" · ")));
+            }
+            // A default that's offline is normal; one that's ambiguous isn't.
+            match &default {
+                Some(Err(err)) if output::kind_of(err) == ErrorKind::Usage => {
+                    println!("\n  {}", dim.apply_to(format!("default_device: {err}")));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves playback to the device `query` names, starting it with `play`.
+pub async fn use_device(query: &str, play: bool, out: Output) -> Result<()> {
+    let config = Config::load()?;
+    let token = auth::get_user_token(&config).await?;
+    let devices = device::list(&token).await?;
+    let target = device::find(&devices, query)?;
+    let Some(id) = &target.id else {
+        return Err(fail(
+            ErrorKind::Player,
+            format!("{} can't be controlled through the Web API", target.name),
+        ));
+    };
+    device::transfer(&token, id, play).await?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "device": target, "play": play })),
+        Output::Plain => println!("{id}\t{}", target.name),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            println!(
+                "  {} Playing on {} {}",
+                green.apply_to("⇄"),
+                green.apply_to(&target.name),
+                dim.apply_to(&target.kind),
+            );
+        }
+    }
+    Ok(())
+}
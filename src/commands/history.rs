@@ -0,0 +1,180 @@
+use anyhow::Result;
+use console::Style;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::display;
+use crate::history::{self, Filter, Play};
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::spotify::player::Player;
+use crate::undo;
+
+pub async fn record(player: &Player, interval: Duration, out: Output) -> Result<()> {
+    if out == Output::Pretty {
+        let red = Style::new().red().bold();
+        let dim = Style::new().dim();
+        println!(
+            "  {} Recording plays to {}",
+            red.apply_to("●"),
+            history::path()?.display()
+        );
+        println!("  {}", dim.apply_to("Press ctrl-c to stop."));
+    }
+    history::record(player, interval).await
+}
+
+/// Matching plays, newest first.
+fn recent(filter: &Filter, limit: usize) -> Result<Vec<Play>> {
+    let mut plays: Vec<Play> = history::load()?
+        .into_iter()
+        .filter(|p| filter.matches(p))
+        .collect();
+    plays.reverse();
+    plays.truncate(limit);
+    Ok(plays)
+}
+
+pub fn list(filter: &Filter, limit: usize, out: Output) -> Result<()> {
+    let plays = recent(filter, limit)?;
+    match out {
+        Output::Json => output::print_json(&plays),
+        Output::Plain => {
+            for play in &plays {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    play.started_at,
+                    play.track_id,
+                    play.track,
+                    play.artist,
+                    play.album,
+                    play.listened_ms
+                );
+            }
+        }
+        Output::Pretty => print_list(&plays),
+    }
+    Ok(())
+}
+
+fn print_list(plays: &[Play]) {
+    let green = Style::new().green().bold();
+    let dim = Style::new().dim();
+
+    if plays.is_empty() {
+        println!("  No plays recorded.");
+        println!(
+            "  Start recording with: {}",
+            dim.apply_to("spot history --record")
+        );
+        return;
+    }
+
+    println!("\n  {}\n", green.apply_to("Recent plays"));
+    for (i, play) in plays.iter().enumerate() {
+        println!(
+            "  {} {} {} {} {}",
+            dim.apply_to(format!("{:>2}.", i + 1)),
+            dim.apply_to(display::format_timestamp(play.started_at)),
+            play.track,
+            dim.apply_to(format!("— {}", play.artist)),
+            dim.apply_to(format!(
+                "({} of {})",
+                display::format_duration(play.listened_ms),
+                display::format_duration(play.duration_ms)
+            )),
+        );
+    }
+    println!();
+}
+
+/// Plays entry `index` of the listing `filter` produces (1 = most recent).
+pub async fn replay(player: &Player, filter: &Filter, index: usize, out: Output) -> Result<()> {
+    let plays = recent(filter, index)?;
+    let play = index
+        .checked_sub(1)
+        .and_then(|i| plays.get(i))
+        .ok_or_else(|| fail(ErrorKind::NotFound, format!("No history entry #{index}")))?;
+    undo::remember(player).await;
+    player.play_uri(&play.track_id).await?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "playing": play })),
+        Output::Plain => println!("{}\t{}\t{}", play.track_id, play.track, play.artist),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            println!(
+                "  {} {} {}",
+                green.apply_to("▶"),
+                green.apply_to(&play.track),
+                dim.apply_to(format!("— {}", play.artist)),
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn set_paused(paused: bool, out: Output) -> Result<()> {
+    let mut config = Config::load()?;
+    config.history.get_or_insert_with(Default::default).paused = paused;
+    config.save()?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "paused": paused })),
+        Output::Plain => println!("{}", if paused { "paused" } else { "recording" }),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            if paused {
+                println!("  {} History recording paused", dim.apply_to("⏸"));
+            } else {
+                println!("  {} History recording resumed", dim.apply_to("●"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets how many days plays are kept; 0 keeps them forever.
+pub fn set_retention(days: u32, out: Output) -> Result<()> {
+    let mut config = Config::load()?;
+    config.history.get_or_insert_with(Default::default).retention_days =
+        (days > 0).then_some(days);
+    config.save()?;
+    let removed = history::prune(&config)?;
+
+    match out {
+        Output::Json => output::print_json(&json!({
+            "retention_days": (days > 0).then_some(days),
+            "removed": removed,
+        })),
+        Output::Plain => println!("{days}\t{removed}"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            if days > 0 {
+                println!("  {} Keeping plays for {days} days", dim.apply_to("✓"));
+            } else {
+                println!("  {} Keeping plays forever", dim.apply_to("✓"));
+            }
+            if removed > 0 {
+                println!("  {} Removed {removed} older plays", dim.apply_to("✓"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the plays matching `filter`; an empty one matches everything.
+pub fn purge(filter: &Filter, out: Output) -> Result<()> {
+    let removed = history::purge(filter)?;
+    match out {
+        Output::Json => output::print_json(&json!({ "removed": removed })),
+        Output::Plain => println!("{removed}"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            let noun = if removed == 1 { "play" } else { "plays" };
+            println!("  {} Removed {removed} {noun}", dim.apply_to("✓"));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use anyhow::Result;
+use console::Style;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::hooks::{self, Event};
+use crate::output::{self, Output};
+use crate::spotify::player::Player;
+
+pub async fn run(player: &Player, interval: Duration, out: Output) -> Result<()> {
+    let config = Config::load()?;
+    if out == Output::Pretty {
+        let settings = config.hooks_settings();
+        let events: Vec<&str> = Event::ALL
+            .into_iter()
+            .filter(|&e| settings.command(e).is_some())
+            .map(Event::as_str)
+            .collect();
+        if !events.is_empty() {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            println!("  {} Watching for {}", green.apply_to("●"), events.join(", "));
+            println!("  {}", dim.apply_to("Press ctrl-c to stop."));
+        }
+    }
+    hooks::watch(player, &config, interval).await
+}
+
+pub fn list(out: Output) -> Result<()> {
+    let settings = Config::load()?.hooks_settings();
+    let configured: Vec<(Event, &str)> = Event::ALL
+        .into_iter()
+        .filter_map(|e| settings.command(e).map(|c| (e, c)))
+        .collect();
+
+    match out {
+        Output::Json => {
+            let map: serde_json::Map<String, serde_json::Value> = configured
+                .iter()
+                .map(|(e, c)| (e.as_str().to_string(), json!(c)))
+                .collect();
+            output::print_json(&map);
+        }
+        Output::Plain => {
+            for (event, command) in &configured {
+                println!("{}\t{command}", event.as_str());
+            }
+        }
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            if configured.is_empty() {
+                println!("  No hooks configured.");
+                println!(
+                    "  Add one to config.toml: {}",
+                    dim.apply_to("[hooks] track_changed = \"notify-send \\\"$SPOT_TRACK\\\"\"")
+                );
+                return Ok(());
+            }
+            println!("\n  {}\n", green.apply_to("Hooks"));
+            for (event, command) in &configured {
+                println!(
+                    "  {} {} {}",
+                    green.apply_to(format!("{:<16}", event.as_str())),
+                    dim.apply_to("→"),
+                    command
+                );
+            }
+            println!();
+        }
+    }
+    Ok(())
+}
+
+pub async fn test(player: &Player, event: Event, out: Output) -> Result<()> {
+    hooks::test(player, &Config::load()?, event).await?;
+    match out {
+        Output::Json => output::print_json(&json!({ "event": event, "ok": true })),
+        Output::Plain => println!("ok"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            println!("  {} {} hook ran", dim.apply_to("✓"), event.as_str());
+        }
+    }
+    Ok(())
+}
@@ -1,6 +1,19 @@
 pub mod alias;
+pub mod at;
 pub mod auth;
 pub mod controls;
+pub mod daemon;
+pub mod device;
+pub mod history;
+pub mod hooks;
+pub mod notify;
 pub mod now;
 pub mod play;
+pub mod queue;
+pub mod scrobble;
 pub mod search;
+pub mod sleep;
+pub mod snapshot;
+pub mod stats;
+pub mod tui;
+pub mod undo;
@@ -0,0 +1,221 @@
+use anyhow::Result;
+use console::Style;
+use crossterm::event::{self, DisableFocusChange, EnableFocusChange, Event, KeyCode, KeyModifiers};
+use crossterm::execute;
+use std::io::stdout;
+use std::time::{Duration, Instant};
+
+use super::now::{FRAME, RawMode};
+use crate::config::Config;
+use crate::notify::{self, Notification, Notifier};
+use crate::output::Output;
+use crate::spotify::player::{NowPlayingInfo, Player};
+use crate::template::Template;
+
+const DEFAULT_SUMMARY: &str = "{track}";
+const DEFAULT_BODY: &str = "{artist} — {album}";
+
+/// Asks the terminal to report focus changes, for `only_when_unfocused`.
+struct FocusReporting {
+    _raw: RawMode,
+}
+
+impl FocusReporting {
+    fn enable() -> Result<Self> {
+        let raw = RawMode::enable()?;
+        execute!(stdout(), EnableFocusChange)?;
+        Ok(Self { _raw: raw })
+    }
+}
+
+impl Drop for FocusReporting {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), DisableFocusChange);
+    }
+}
+
+struct Watcher {
+    notifier: Notifier,
+    last_track: Option<String>,
+    /// Whether our terminal has focus. Terminals that don't report focus
+    /// leave this false, so notifications still show.
+    focused: bool,
+}
+
+impl Watcher {
+    async fn check(&mut self, player: &Player) {
+        let Ok(info) = player.get_now_playing().await else {
+            return;
+        };
+        if self.last_track.as_deref() == Some(info.track_id.as_str()) {
+            return;
+        }
+        self.last_track = Some(info.track_id.clone());
+        // Re-read so changes to [notify] apply without a restart.
+        if let Err(e) = self.notify(&info, false).await {
+            eprint!("spot notify: {e:#}\r\n");
+        }
+    }
+
+    async fn notify(&mut self, info: &NowPlayingInfo, force: bool) -> Result<()> {
+        let config = Config::load()?;
+        let settings = config.notify_settings();
+        if !force && (!settings.enabled || (settings.only_when_unfocused && self.focused)) {
+            return Ok(());
+        }
+        let summary = Template::parse(settings.summary.as_deref().unwrap_or(DEFAULT_SUMMARY))?;
+        let body = Template::parse(settings.body.as_deref().unwrap_or(DEFAULT_BODY))?;
+        let image = if settings.album_art {
+            // A missing cover shouldn't cost the notification.
+            notify::album_art(&config, &info.track_id)
+                .await
+                .unwrap_or_else(|e| {
+                    eprint!("spot notify: {e:#}\r\n");
+                    None
+                })
+        } else {
+            None
+        };
+        let notification = Notification {
+            summary: summary.render(info),
+            body: body.render(info),
+            image,
+        };
+        self.notifier
+            .show(&notification, settings.timeout_ms.unwrap_or(-1))
+    }
+}
+
+/// Posts a notification for every new track until interrupted, or once for
+/// the current track with `once`.
+pub async fn run(player: &Player, interval: Duration, once: bool, out: Output) -> Result<()> {
+    let mut watcher = Watcher {
+        notifier: Notifier::new()?,
+        last_track: None,
+        focused: false,
+    };
+    if once {
+        let info = player.get_now_playing().await?;
+        return watcher.notify(&info, true).await;
+    }
+
+    let settings = Config::load()?.notify_settings();
+    let track_focus = settings.only_when_unfocused && console::user_attended();
+    if out == Output::Pretty {
+        let green = Style::new().green().bold();
+        let dim = Style::new().dim();
+        println!("  {} Notifying on track changes", green.apply_to("●"));
+        let hint = if track_focus { "Press q to stop." } else { "Press ctrl-c to stop." };
+        println!("  {}", dim.apply_to(hint));
+    }
+
+    if track_focus {
+        return watch_with_focus(player, &mut watcher, interval).await;
+    }
+    let mut ticker = tokio::time::interval(interval);
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => watcher.check(player).await,
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+    }
+    Ok(())
+}
+
+/// Like the plain loop, but in raw mode so focus events arrive as they happen.
+async fn watch_with_focus(player: &Player, watcher: &mut Watcher, interval: Duration) -> Result<()> {
+    let _focus = FocusReporting::enable()?;
+    let mut polled: Option<Instant> = None;
+    loop {
+        if polled.is_none_or(|p| p.elapsed() >= interval) {
+            watcher.check(player).await;
+            polled = Some(Instant::now());
+        }
+        if !event::poll(FRAME)? {
+            continue;
+        }
+        match event::read()? {
+            Event::FocusGained => watcher.focused = true,
+            Event::FocusLost => watcher.focused = false,
+            Event::Key(key)
+                if key.code == KeyCode::Char('q')
+                    || (key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL)) =>
+            {
+                break
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ConfigHome, NotificationServer, env_lock};
+
+    fn info(track: &str, is_playing: bool) -> NowPlayingInfo {
+        NowPlayingInfo {
+            track_name: track.to_string(),
+            artist: "Miles Davis".to_string(),
+            album: "Kind of Blue".to_string(),
+            track_id: format!("spotify:track:{track}"),
+            duration_ms: 300_000,
+            position_ms: 0,
+            is_playing,
+        }
+    }
+
+    #[tokio::test]
+    async fn renders_the_configured_templates() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        let server = NotificationServer::start();
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &server.bus.address);
+        std::fs::write(
+            home.dir.join("config.toml"),
+            "[notify]\nsummary = \"♫ {track}\"\nbody = \"{album:6}{?paused} (paused){/paused}\"\ntimeout_ms = 3000\n",
+        )
+        .unwrap();
+
+        let mut watcher = Watcher {
+            notifier: Notifier::new().unwrap(),
+            last_track: None,
+            focused: false,
+        };
+        watcher.notify(&info("So What", true), false).await.unwrap();
+        watcher.notify(&info("Blue in Green", false), false).await.unwrap();
+
+        let calls = server.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].summary, "♫ So What");
+        assert_eq!(calls[0].body, "Kind …");
+        assert_eq!(calls[0].expire_timeout, 3000);
+        assert_eq!(calls[1].summary, "♫ Blue in Green");
+        assert_eq!(calls[1].body, "Kind … (paused)");
+        assert_eq!(calls[1].replaces_id, 41);
+    }
+
+    #[tokio::test]
+    async fn defaults_to_track_artist_and_album() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let server = NotificationServer::start();
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &server.bus.address);
+
+        let mut watcher = Watcher {
+            notifier: Notifier::new().unwrap(),
+            last_track: None,
+            focused: false,
+        };
+        watcher.notify(&info("So What", true), false).await.unwrap();
+
+        let calls = server.calls();
+        assert_eq!(calls[0].summary, "So What");
+        assert_eq!(calls[0].body, "Miles Davis — Kind of Blue");
+    }
+}
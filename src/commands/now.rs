@@ -1,10 +1,250 @@
 use anyhow::Result;
+use console::Style;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, terminal};
+use std::io::{Write, stdout};
+use std::time::{Duration, Instant};
 
+use crate::config::Config;
 use crate::display;
-use crate::spotify::applescript;
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::spotify::player::{NowPlayingInfo, Player};
+use crate::template::Template;
+
+/// How often the progress line is redrawn between polls.
+pub const FRAME: Duration = Duration::from_millis(250);
+
+/// The template `--format` asks for, or `[formats] now` without one.
+/// `--format @name` refers to a template in `[formats]`.
+fn template(config: &Config, format: Option<&str>) -> Result<Option<Template>> {
+    let source = match format {
+        Some(f) => match f.strip_prefix('@') {
+            Some(name) => Some(config.format(name).ok_or_else(|| {
+                fail(
+                    ErrorKind::Usage,
+                    format!("No format named \"{name}\" in config.toml"),
+                )
+            })?),
+            None => Some(f),
+        },
+        None => config.format("now"),
+    };
+    source.map(Template::parse).transpose()
+}
+
+pub async fn run(player: &Player, format: Option<&str>, out: Output) -> Result<()> {
+    let template = template(&Config::load()?, format)?;
+
+    let info = player.get_now_playing().await?;
+    match (out, template) {
+        (Output::Json, _) => output::print_json(&info),
+        (_, Some(template)) => println!("{}", template.render(&info)),
+        (Output::Plain, None) => println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            info.track_name,
+            info.artist,
+            info.album,
+            display::format_duration(info.position_ms),
+            display::format_duration(info.duration_ms),
+            if info.is_playing { "playing" } else { "paused" },
+        ),
+        (Output::Pretty, None) => display::print_now_playing(&info),
+    }
+    Ok(())
+}
+
+/// Restores the terminal however the watch loop exits.
+pub struct RawMode;
+
+impl RawMode {
+    pub fn enable() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(stdout(), cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+enum Action {
+    Toggle,
+    Next,
+    Prev,
+    Volume(i32),
+    Quit,
+}
+
+fn action_for(key: KeyEvent, step: i32) -> Option<Action> {
+    if key.kind != KeyEventKind::Press {
+        return None;
+    }
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Some(Action::Quit),
+        KeyCode::Char(' ') => Some(Action::Toggle),
+        KeyCode::Char('n') => Some(Action::Next),
+        KeyCode::Char('p') => Some(Action::Prev),
+        KeyCode::Char('+') | KeyCode::Char('=') => Some(Action::Volume(step)),
+        KeyCode::Char('-') => Some(Action::Volume(-step)),
+        KeyCode::Char('q') | KeyCode::Esc => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+/// Live view: redraws in place, interpolating the position between polls and
+/// re-querying the player on `interval`, on track end and after key presses.
+pub async fn watch(player: &Player, interval: Duration) -> Result<()> {
+    let step = Config::load()?.volume_settings().step();
+    let _raw = RawMode::enable()?;
+    let mut out = stdout();
+    let dim = Style::new().dim();
+    let red = Style::new().red();
+    let help = dim
+        .apply_to("  space play/pause · n/p next/prev · +/- volume · q quit")
+        .to_string();
+
+    let mut snapshot: Option<(NowPlayingInfo, Instant)> = None;
+    let mut poll_error = String::new();
+    let mut status = String::new();
+    let mut refresh = true;
+    let mut drawn = false;
+
+    loop {
+        let stale = match &snapshot {
+            Some((info, fetched)) => {
+                let ended = info.is_playing
+                    && interpolate(info, *fetched).position_ms >= info.duration_ms
+                    && fetched.elapsed() >= Duration::from_secs(1);
+                fetched.elapsed() >= interval || ended
+            }
+            None => true,
+        };
+        if refresh || stale {
+            match player.get_now_playing().await {
+                Ok(info) => snapshot = Some((info, Instant::now())),
+                Err(e) => {
+                    snapshot = None;
+                    poll_error = e.to_string();
+                }
+            }
+            refresh = false;
+        }
+
+        let (track, progress) = match &snapshot {
+            Some((info, fetched)) => display::now_playing_lines(&interpolate(info, *fetched)),
+            None => (format!("  {}", dim.apply_to(&poll_error)), String::new()),
+        };
+        let footer = if status.is_empty() {
+            help.clone()
+        } else {
+            format!("  {}", red.apply_to(&status))
+        };
+        // Each line must fit on one row, or the wrapped remainder would be
+        // left behind when moving back up to redraw.
+        let width = terminal::size().map_or(80, |(cols, _)| cols as usize);
+        let fit = |line: &str| {
+            console::truncate_str(line, width.saturating_sub(1), "…").into_owned()
+        };
+        let (track, progress, footer) = (fit(&track), fit(&progress), fit(&footer));
+        if drawn {
+            queue!(out, cursor::MoveUp(3))?;
+        }
+        queue!(
+            out,
+            cursor::MoveToColumn(0),
+            terminal::Clear(terminal::ClearType::FromCursorDown)
+        )?;
+        write!(out, "{track}\r\n{progress}\r\n{footer}\r\n")?;
+        out.flush()?;
+        drawn = true;
+
+        if !event::poll(FRAME)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        let result = match action_for(key, step) {
+            Some(Action::Quit) => break,
+            Some(Action::Toggle) => player.toggle().await,
+            Some(Action::Next) => player.next_track().await,
+            Some(Action::Prev) => player.prev_track().await,
+            Some(Action::Volume(delta)) => match player.get_volume().await {
+                Ok(vol) => player.set_volume((vol + delta).clamp(0, 100)).await,
+                Err(e) => Err(e),
+            },
+            None => continue,
+        };
+        status = match result {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        };
+        // Give the player a moment to apply the change before re-reading it.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        refresh = true;
+    }
 
-pub fn run() -> Result<()> {
-    let info = applescript::get_now_playing()?;
-    display::print_now_playing(&info);
     Ok(())
 }
+
+/// Advances a snapshot by the time since it was taken, if playing.
+pub fn interpolate(info: &NowPlayingInfo, fetched: Instant) -> NowPlayingInfo {
+    let mut info = info.clone();
+    if info.is_playing {
+        let elapsed = fetched.elapsed().as_millis() as i64;
+        info.position_ms = (info.position_ms + elapsed).min(info.duration_ms);
+    }
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config() -> Config {
+        Config {
+            formats: Some(HashMap::from([
+                ("now".to_string(), "{track}".to_string()),
+                ("bar".to_string(), "{artist}".to_string()),
+            ])),
+            ..Config::default()
+        }
+    }
+
+    fn info() -> NowPlayingInfo {
+        NowPlayingInfo {
+            track_name: "So What".to_string(),
+            artist: "Miles Davis".to_string(),
+            album: "Kind of Blue".to_string(),
+            track_id: "spotify:track:a".to_string(),
+            duration_ms: 545_000,
+            position_ms: 0,
+            is_playing: true,
+        }
+    }
+
+    fn rendered(config: &Config, format: Option<&str>) -> Option<String> {
+        template(config, format).unwrap().map(|t| t.render(&info()))
+    }
+
+    #[test]
+    fn picks_the_template() {
+        assert_eq!(rendered(&config(), None).as_deref(), Some("So What"));
+        assert_eq!(rendered(&config(), Some("@bar")).as_deref(), Some("Miles Davis"));
+        assert_eq!(rendered(&config(), Some("{album}")).as_deref(), Some("Kind of Blue"));
+        assert_eq!(rendered(&Config::default(), None), None);
+    }
+
+    #[test]
+    fn format_mistakes_are_usage_errors() {
+        for format in ["@missing", "{nope}", "{track"] {
+            let err = template(&config(), Some(format)).unwrap_err();
+            assert_eq!(output::kind_of(&err), ErrorKind::Usage, "{format}");
+        }
+    }
+}
@@ -1,74 +1,40 @@
 use anyhow::Result;
-use console::Style;
 
+use super::search;
 use crate::config::Config;
-use crate::spotify::{api, applescript, auth};
-
-#[derive(Debug, Clone, Copy)]
-pub enum PlayMode {
-    Track,
-    Album,
-    Artist,
-    Playlist,
-}
+use crate::daemon;
+use crate::display;
+use crate::output::Output;
+use crate::playback::{self, PlayMode};
+use crate::spotify::player::Player;
+
+pub async fn run(
+    player: &Player,
+    query: &str,
+    mode: PlayMode,
+    interactive: bool,
+    limit: Option<u32>,
+    out: Output,
+) -> Result<()> {
+    // `#3` is the third result of the last `spot search`.
+    if let Some(n) = query.strip_prefix('#').and_then(|n| n.parse().ok()) {
+        let (search_type, result) = search::numbered(n)?;
+        return playback::play_result(player, &result, search_type, out).await;
+    }
+    if !interactive {
+        return playback::play_query(player, query, mode, out).await;
+    }
 
-pub async fn run(query: &str, mode: PlayMode) -> Result<()> {
-    // Check aliases first
     let config = Config::load()?;
-    let resolved = config
-        .aliases
-        .as_ref()
-        .and_then(|a| a.get(query))
-        .cloned();
-    let query = resolved.as_deref().unwrap_or(query);
-
-    let token = auth::get_token(&config).await?;
-
-    match mode {
-        PlayMode::Track => {
-            let results = api::search(&token, query, api::SearchType::Track, 5).await?;
-            let track = results.first().ok_or_else(|| anyhow::anyhow!("No track found for \"{}\"", query))?;
-            play_and_print(track)?;
-            applescript::set_repeat(false)?;
-            Ok(())
-        }
-        PlayMode::Album => {
-            let results = api::search(&token, query, api::SearchType::Album, 5).await?;
-            let album = results.first().ok_or_else(|| anyhow::anyhow!("No album found for \"{}\"", query))?;
-            play_and_print(album)?;
-            applescript::set_repeat(true)?;
-            let dim = Style::new().dim();
-            println!("  {} Repeat on", dim.apply_to("🔁"));
-            Ok(())
-        }
-        PlayMode::Artist => {
-            let results = api::search(&token, query, api::SearchType::Artist, 5).await?;
-            let artist = results.first().ok_or_else(|| anyhow::anyhow!("No artist found for \"{}\"", query))?;
-            play_and_print(artist)?;
-            applescript::set_repeat(false)?;
-            Ok(())
-        }
-        PlayMode::Playlist => {
-            let results = api::search(&token, query, api::SearchType::Playlist, 5).await?;
-            let playlist = results.first().ok_or_else(|| anyhow::anyhow!("No playlist found for \"{}\"", query))?;
-            play_and_print(playlist)?;
-            applescript::set_repeat(false)?;
-            Ok(())
-        }
+    let query = playback::resolve_alias(&config, query);
+    let search_type = mode.search_type();
+    // The picker is only useful with more than a handful of candidates.
+    let results = daemon::search(&config, query, search_type, limit.unwrap_or(20)).await?;
+    if results.is_empty() {
+        return Err(playback::not_found(query, search_type));
+    }
+    match display::pick_result(&results)? {
+        Some(result) => playback::play_result(player, result, search_type, out).await,
+        None => Ok(()),
     }
-}
-
-fn play_and_print(result: &api::SearchResult) -> Result<()> {
-    applescript::play_uri(&result.uri)?;
-
-    let green = Style::new().green().bold();
-    let dim = Style::new().dim();
-    println!(
-        "  {} {} {}",
-        green.apply_to("▶"),
-        green.apply_to(&result.name),
-        dim.apply_to(format!("— {}", result.detail)),
-    );
-
-    Ok(())
 }
@@ -0,0 +1,189 @@
+use anyhow::Result;
+use console::Style;
+use serde_json::json;
+
+use crate::config::Config;
+use crate::daemon;
+use crate::display;
+use crate::output::{self, Output};
+use crate::playback;
+use crate::spotify::api::{self, SearchResult};
+use crate::spotify::auth;
+
+/// `spot queue`: what's playing and what's up next.
+pub async fn show(out: Output) -> Result<()> {
+    let config = Config::load()?;
+    let token = auth::get_user_token(&config).await?;
+    let queue = api::get_queue(&token).await?;
+
+    match out {
+        Output::Json => output::print_json(&queue),
+        Output::Plain => {
+            for item in &queue.queue {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    item.uri,
+                    item.name,
+                    item.detail,
+                    display::format_duration(item.duration_ms)
+                );
+            }
+        }
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            if let Some(current) = &queue.currently_playing {
+                println!(
+                    "  {} {} {}",
+                    green.apply_to("▶"),
+                    green.apply_to(&current.name),
+                    dim.apply_to(format!("— {}", current.detail)),
+                );
+            }
+            if queue.queue.is_empty() {
+                println!("  {}", dim.apply_to("Nothing queued."));
+                return Ok(());
+            }
+            println!();
+            for (i, item) in queue.queue.iter().enumerate() {
+                println!(
+                    "  {} {} {} {}",
+                    dim.apply_to(format!("{:>2}", i + 1)),
+                    item.name,
+                    dim.apply_to(format!("— {}", item.detail)),
+                    dim.apply_to(display::format_duration(item.duration_ms)),
+                );
+            }
+            let total: i64 = queue.queue.iter().map(|i| i.duration_ms).sum();
+            println!(
+                "\n  {}",
+                dim.apply_to(format!("{} tracks · {}", queue.queue.len(), display::format_total(total)))
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `spot queue add`: queues the top track for `query`, or with `album`
+/// every track of the top album. `interactive` picks from the results.
+pub async fn add(query: &str, album: bool, interactive: bool, out: Output) -> Result<()> {
+    let config = Config::load()?;
+    let search_type = if album {
+        api::SearchType::Album
+    } else {
+        api::SearchType::Track
+    };
+    let limit = if interactive { 20 } else { 5 };
+    let results = daemon::search(&config, query, search_type, limit).await?;
+    if results.is_empty() {
+        return Err(playback::not_found(query, search_type));
+    }
+    let result = if interactive {
+        match display::pick_result(&results)? {
+            Some(result) => result,
+            None => return Ok(()),
+        }
+    } else {
+        &results[0]
+    };
+
+    let token = auth::get_user_token(&config).await?;
+    let tracks = if album {
+        api::album_tracks(&token, &result.uri).await?
+    } else {
+        vec![result.clone()]
+    };
+    queue_tracks(&token, &tracks).await?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "queued": tracks })),
+        Output::Plain => {
+            for track in &tracks {
+                println!("{}\t{}\t{}", track.uri, track.name, track.detail);
+            }
+        }
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            println!(
+                "  {} {} {}",
+                green.apply_to("+"),
+                result.name,
+                dim.apply_to(format!("— {}", result.detail)),
+            );
+            if album {
+                let noun = if tracks.len() == 1 { "track" } else { "tracks" };
+                println!("  {} Queued {} {noun}", dim.apply_to("✓"), tracks.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Queues `tracks` in order. If one fails, says how many made it first.
+async fn queue_tracks(token: &str, tracks: &[SearchResult]) -> Result<()> {
+    for (queued, track) in tracks.iter().enumerate() {
+        if let Err(e) = api::add_to_queue(token, &track.uri).await {
+            if queued == 0 {
+                return Err(e);
+            }
+            return Err(e.context(format!(
+                "Queued {queued} of {} tracks; \"{}\" and the rest were not",
+                tracks.len(),
+                track.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{ErrorKind, kind_of};
+    use crate::test_support::{Stub, env_lock, route};
+
+    fn track(id: &str) -> SearchResult {
+        SearchResult {
+            name: format!("Track {id}"),
+            uri: format!("spotify:track:{id}"),
+            detail: "Artist".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn queues_every_track_in_order() {
+        let _env = env_lock().await;
+        let stub = Stub::serve_api(vec![route("POST", "/me/player/queue", 204, "")]).await;
+
+        queue_tracks("t", &[track("a"), track("b")]).await.unwrap();
+        let paths: Vec<String> = stub.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/me/player/queue?uri=spotify%3Atrack%3Aa",
+                "/me/player/queue?uri=spotify%3Atrack%3Ab",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_how_many_were_queued_before_a_failure() {
+        let _env = env_lock().await;
+        let stub = Stub::serve_api(vec![
+            route("POST", "/me/player/queue?uri=spotify%3Atrack%3Ac", 404, "gone"),
+            route("POST", "/me/player/queue", 204, ""),
+        ])
+        .await;
+
+        let err = queue_tracks("t", &[track("a"), track("b"), track("c"), track("d")])
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Queued 2 of 4 tracks; \"Track c\" and the rest were not");
+        assert_eq!(kind_of(&err), ErrorKind::NoDevice);
+        assert_eq!(stub.requests().len(), 3);
+
+        let err = queue_tracks("t", &[track("c")]).await.unwrap_err();
+        assert!(err.to_string().starts_with("Queueing failed (404"));
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use console::Style;
+use dialoguer::{Input, Password};
+use serde_json::json;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::scrobble::{self, Service};
+use crate::spotify::player::Player;
+
+pub async fn run(player: &Player, interval: Duration, out: Output) -> Result<()> {
+    let config = Config::load()?;
+    let pretty = out == Output::Pretty;
+    if pretty {
+        let red = Style::new().red().bold();
+        let dim = Style::new().dim();
+        let labels: Vec<&str> = Service::configured(&config)
+            .into_iter()
+            .map(Service::label)
+            .collect();
+        if !labels.is_empty() {
+            println!("  {} Scrobbling to {}", red.apply_to("●"), labels.join(", "));
+            println!("  {}", dim.apply_to("Press ctrl-c to stop."));
+        }
+    }
+    scrobble::run(player, &config, interval, pretty).await
+}
+
+pub fn status(out: Output) -> Result<()> {
+    let config = Config::load()?;
+    let settings = config.scrobble_settings();
+    let lastfm_session = settings
+        .lastfm
+        .is_some()
+        .then(|| scrobble::lastfm_settings(&config).map(|l| l.session_key.is_some()))
+        .transpose()?;
+    let queued = scrobble::queued()?;
+
+    match out {
+        Output::Json => output::print_json(&json!({
+            "listenbrainz": settings.listenbrainz.is_some(),
+            "lastfm": settings.lastfm.is_some(),
+            "lastfm_logged_in": lastfm_session.unwrap_or(false),
+            "queued": queued,
+        })),
+        Output::Plain => {
+            println!("listenbrainz\t{}", settings.listenbrainz.is_some());
+            println!("lastfm\t{}", lastfm_session.unwrap_or(false));
+            println!("queued\t{queued}");
+        }
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            let yes = green.apply_to("✓");
+            if settings.listenbrainz.is_some() {
+                println!("  {yes} ListenBrainz");
+            } else {
+                println!("  {} ListenBrainz {}", dim.apply_to("✗"), dim.apply_to("not configured"));
+            }
+            match lastfm_session {
+                Some(true) => println!("  {yes} Last.fm"),
+                Some(false) => println!(
+                    "  {} Last.fm {}",
+                    dim.apply_to("✗"),
+                    dim.apply_to("run spot scrobble login")
+                ),
+                None => println!("  {} Last.fm {}", dim.apply_to("✗"), dim.apply_to("not configured")),
+            }
+            if queued > 0 {
+                println!("  {queued} listens waiting to be sent");
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn flush(out: Output) -> Result<()> {
+    let config = Config::load()?;
+    let sent = scrobble::flush(&config).await?;
+    let left = scrobble::queued()?;
+    match out {
+        Output::Json => output::print_json(&json!({ "sent": sent, "queued": left })),
+        Output::Plain => println!("{sent}\t{left}"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            println!("  {} Sent {sent} queued listens", dim.apply_to("✓"));
+            if left > 0 {
+                println!("  {left} still waiting");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Asks for the Last.fm username and password and stores a session key.
+pub async fn login(out: Output) -> Result<()> {
+    let config = Config::load()?;
+    let Some(settings) = config.scrobble_settings().lastfm else {
+        return Err(fail(
+            ErrorKind::Config,
+            "Add api_key and api_secret under [scrobble.lastfm] in config.toml first",
+        ));
+    };
+
+    let username: String = Input::new()
+        .with_prompt("  Last.fm username")
+        .interact_text()?;
+    let password = Password::new()
+        .with_prompt("  Last.fm password")
+        .interact()?;
+
+    let session_key = scrobble::lastfm_login(settings, username.trim(), &password).await?;
+    scrobble::save_lastfm_session(&session_key)?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "logged_in": true })),
+        Output::Plain => println!("logged in"),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            println!("\n  {} Logged in to Last.fm.", green.apply_to("✓"));
+        }
+    }
+    Ok(())
+}
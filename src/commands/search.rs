@@ -1,13 +1,307 @@
 use anyhow::Result;
+use console::Style;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-use crate::config::Config;
+use crate::config::{self, Config};
+use crate::daemon;
 use crate::display;
-use crate::spotify::{api, auth};
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::playback;
+use crate::spotify::api::{self, SearchPage, SearchResult, SearchType};
+use crate::spotify::auth;
+use crate::spotify::player::Player;
 
-pub async fn run(query: &str, search_type: api::SearchType) -> Result<()> {
+/// One line of the last search, so `spot play '#3'` can find it again.
+#[derive(Serialize, Deserialize)]
+struct Numbered {
+    #[serde(rename = "type")]
+    search_type: SearchType,
+    #[serde(flatten)]
+    result: SearchResult,
+}
+
+/// The last `spot search`, for `--next` and `spot play '#N'`.
+#[derive(Serialize, Deserialize)]
+struct LastSearch {
+    query: String,
+    types: Vec<SearchType>,
+    limit: u32,
+    offset: u32,
+    /// Whether any type had matches past this page.
+    more: bool,
+    /// In the order they were numbered on screen.
+    results: Vec<Numbered>,
+}
+
+/// Which slice of the matches to show.
+#[derive(Debug, Clone, Copy)]
+pub struct Window {
+    pub limit: u32,
+    pub offset: u32,
+}
+
+impl Window {
+    /// 20 results for one type, 5 per section when searching several.
+    pub fn default_limit(types: &[SearchType]) -> u32 {
+        if types.len() > 1 { 5 } else { 20 }
+    }
+
+    fn check(self) -> Result<Self> {
+        if self.offset.saturating_add(self.limit) > api::MAX_SEARCH_WINDOW {
+            return Err(fail(
+                ErrorKind::Usage,
+                format!(
+                    "Spotify only returns the first {} matches; lower --offset, --page or --limit",
+                    api::MAX_SEARCH_WINDOW
+                ),
+            ));
+        }
+        Ok(self)
+    }
+}
+
+pub async fn run(query: &str, types: &[SearchType], window: Window, out: Output) -> Result<()> {
+    let window = window.check()?;
     let config = Config::load()?;
-    let token = auth::get_token(&config).await?;
-    let results = api::search(&token, query, search_type, 20).await?;
-    display::print_search_results(&results, search_type);
+    let pages = daemon::search_types(&config, query, types, window.limit, window.offset).await?;
+    if let Err(e) = remember(query, types, window, &pages) {
+        eprintln!("spot: could not save search results: {e:#}");
+    }
+
+    match (out, pages.as_slice()) {
+        (Output::Json, [page]) => output::print_json(&json!({
+            "type": page.search_type,
+            "query": query,
+            "results": page.results,
+            "offset": page.offset,
+            "total": page.total,
+        })),
+        (Output::Json, _) => output::print_json(&json!({ "query": query, "sections": pages })),
+        (Output::Plain, [page]) => {
+            for result in &page.results {
+                println!("{}\t{}\t{}", result.uri, result.name, result.detail);
+            }
+        }
+        (Output::Plain, _) => {
+            for page in &pages {
+                for result in &page.results {
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        page.search_type.as_str(),
+                        result.uri,
+                        result.name,
+                        result.detail
+                    );
+                }
+            }
+        }
+        (Output::Pretty, _) => display::print_search_pages(&pages),
+    }
     Ok(())
 }
+
+/// `spot search --next`: the page after the last search's.
+pub async fn next(out: Output) -> Result<()> {
+    let last = load_last()?;
+    if !last.more {
+        return Err(fail(
+            ErrorKind::NotFound,
+            format!("No more results for \"{}\"", last.query),
+        ));
+    }
+    let window = Window {
+        limit: last.limit,
+        offset: last.offset + last.limit,
+    };
+    run(&last.query, &last.types, window, out).await
+}
+
+const LAST_FILE: &str = "last_search.json";
+
+fn remember(query: &str, types: &[SearchType], window: Window, pages: &[SearchPage]) -> Result<()> {
+    let last = LastSearch {
+        query: query.to_string(),
+        types: types.to_vec(),
+        limit: window.limit,
+        offset: window.offset,
+        more: pages.iter().any(SearchPage::has_more),
+        results: pages
+            .iter()
+            .flat_map(|page| {
+                page.results.iter().map(|result| Numbered {
+                    search_type: page.search_type,
+                    result: result.clone(),
+                })
+            })
+            .collect(),
+    };
+    config::save_json_atomic(LAST_FILE, &last)
+}
+
+fn load_last() -> Result<LastSearch> {
+    config::load_json(LAST_FILE)?.ok_or_else(|| {
+        fail(
+            ErrorKind::NotFound,
+            "No previous search. Run: spot search <query>",
+        )
+    })
+}
+
+/// Result `n` of the last `spot search`, counting from 1.
+pub fn numbered(n: usize) -> Result<(SearchType, SearchResult)> {
+    let mut results = load_last()?.results;
+    let count = results.len();
+    if n == 0 || n > count {
+        return Err(fail(
+            ErrorKind::NotFound,
+            format!("No result #{n}; the last search had {count}"),
+        ));
+    }
+    let picked = results.swap_remove(n - 1);
+    Ok((picked.search_type, picked.result))
+}
+
+/// One type's results in `window`, remembered like `spot search` does so
+/// `#N` and `--next` follow on from the picker too.
+async fn search_window(
+    config: &Config,
+    query: &str,
+    search_type: SearchType,
+    window: Window,
+) -> Result<Vec<SearchResult>> {
+    let window = window.check()?;
+    let types = [search_type];
+    let mut pages = daemon::search_types(config, query, &types, window.limit, window.offset).await?;
+    if let Err(e) = remember(query, &types, window, &pages) {
+        eprintln!("spot: could not save search results: {e:#}");
+    }
+    Ok(pages.pop().map(|page| page.results).unwrap_or_default())
+}
+
+/// `spot search -i`: fuzzy-pick one result and play it.
+pub async fn pick(
+    player: &Player,
+    query: &str,
+    search_type: SearchType,
+    window: Window,
+    out: Output,
+) -> Result<()> {
+    let config = Config::load()?;
+    let results = search_window(&config, query, search_type, window).await?;
+    if results.is_empty() {
+        return Err(playback::not_found(query, search_type));
+    }
+    match display::pick_result(&results)? {
+        Some(result) => playback::play_result(player, result, search_type, out).await,
+        None => Ok(()),
+    }
+}
+
+/// `spot search -q`: tick several tracks and add them to the playback queue.
+pub async fn queue(query: &str, search_type: SearchType, window: Window, out: Output) -> Result<()> {
+    if search_type != SearchType::Track {
+        return Err(fail(ErrorKind::Usage, "Only tracks can be queued"));
+    }
+    let config = Config::load()?;
+    let results = search_window(&config, query, search_type, window).await?;
+    if results.is_empty() {
+        return Err(playback::not_found(query, search_type));
+    }
+    let picked = display::pick_results(&results)?;
+    if picked.is_empty() {
+        return Ok(());
+    }
+
+    let token = auth::get_user_token(&config).await?;
+    // Stops at the first failure, so the queue keeps the picked order; the
+    // ones already in are still listed.
+    let mut failure = None;
+    let mut queued = 0;
+    for result in &picked {
+        if let Err(e) = api::add_to_queue(&token, &result.uri).await {
+            failure = Some(e);
+            break;
+        }
+        queued += 1;
+    }
+    let (picked, rest) = picked.split_at(queued);
+    if let Some(e) = failure {
+        // JSON gets just the error, which says how many went in; a second
+        // document on stdout would trip up whatever parses it.
+        if !picked.is_empty() && out != Output::Json {
+            print_queued(picked, out);
+        }
+        return Err(if picked.is_empty() {
+            e
+        } else {
+            e.context(format!(
+                "Queued {queued} of {} tracks; \"{}\" and the rest were not",
+                queued + rest.len(),
+                rest[0].name
+            ))
+        });
+    }
+    print_queued(picked, out);
+    Ok(())
+}
+
+fn print_queued(picked: &[&SearchResult], out: Output) {
+    match out {
+        Output::Json => output::print_json(&json!({ "queued": picked })),
+        Output::Plain => {
+            for result in picked {
+                println!("{}\t{}\t{}", result.uri, result.name, result.detail);
+            }
+        }
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            for result in picked {
+                println!(
+                    "  {} {} {}",
+                    green.apply_to("+"),
+                    result.name,
+                    dim.apply_to(format!("— {}", result.detail)),
+                );
+            }
+            let noun = if picked.len() == 1 { "track" } else { "tracks" };
+            println!("  {} Queued {} {noun}", dim.apply_to("✓"), picked.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ConfigHome, Stub, env_lock, route};
+
+    #[tokio::test]
+    async fn the_picker_numbers_its_results_too() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        let token = json!({ "access_token": "t", "expires_at": u64::MAX });
+        std::fs::write(home.dir.join("token.json"), token.to_string()).unwrap();
+        let body = json!({ "tracks": {
+            "items": [
+                { "name": "So What", "uri": "spotify:track:a",
+                  "artists": [{ "name": "Miles Davis" }], "album": { "name": "Kind of Blue" } },
+                { "name": "Blue in Green", "uri": "spotify:track:b",
+                  "artists": [{ "name": "Miles Davis" }], "album": { "name": "Kind of Blue" } },
+            ],
+            "total": 2,
+            "next": null,
+        }});
+        let _stub = Stub::serve_api(vec![route("GET", "/search", 200, body.to_string())]).await;
+
+        let window = Window { limit: 20, offset: 0 };
+        let results = search_window(&Config::default(), "kind of blue", SearchType::Track, window)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        let (search_type, result) = numbered(2).unwrap();
+        assert_eq!(search_type, SearchType::Track);
+        assert_eq!(result.uri, "spotify:track:b");
+        assert!(numbered(3).is_err());
+    }
+}
@@ -0,0 +1,428 @@
+use anyhow::Result;
+use console::Style;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::config::{self, Config};
+use crate::display;
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::spotify::api;
+use crate::spotify::auth::{self, now_secs};
+use crate::spotify::player::{NowPlayingInfo, Player};
+
+/// How often the timer checks for cancellation and steps the fade.
+const TICK: Duration = Duration::from_millis(250);
+
+/// How often the player is polled when counting tracks or albums.
+const POLL: Duration = Duration::from_secs(2);
+
+const DEFAULT_FADE: Duration = Duration::from_secs(30);
+
+/// When the timer goes off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "until", rename_all = "snake_case")]
+pub enum Until {
+    /// Unix seconds.
+    Time { ends_at: u64 },
+    EndOfAlbum { album: String },
+    /// Tracks left, counting the one playing.
+    Tracks { remaining: u32 },
+}
+
+/// The running timer, in `sleep.json` so other terminals can see or
+/// cancel it. Removing the file cancels the timer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct State {
+    pid: u32,
+    #[serde(flatten)]
+    until: Until,
+    fade_secs: u64,
+}
+
+const FILE: &str = "sleep.json";
+
+fn load_state() -> Option<State> {
+    config::load_json(FILE).ok().flatten()
+}
+
+/// Writes the new state of the timer `state` belongs to. Once the file is
+/// gone or names another timer, `--cancel` has already reported this one
+/// cancelled, so nothing is written and the next tick stops it.
+fn update_state(state: &State) -> Result<()> {
+    if load_state().is_some_and(|s| s.pid == state.pid) {
+        config::save_json_atomic(FILE, state)?;
+    }
+    Ok(())
+}
+
+/// Writes the state file for a new timer, failing when another is running.
+/// A file left by a timer that died is cleared and claimed once more.
+fn claim_state(state: &State) -> Result<()> {
+    for _ in 0..2 {
+        if config::create_json_atomic(FILE, state)? {
+            return Ok(());
+        }
+        if let Some(running) = active() {
+            return Err(already_running(&running));
+        }
+    }
+    Err(fail(
+        ErrorKind::Usage,
+        "A sleep timer is already starting. Cancel it with: spot sleep --cancel",
+    ))
+}
+
+fn already_running(state: &State) -> anyhow::Error {
+    fail(
+        ErrorKind::Usage,
+        format!("A sleep timer is already running (pid {}). Cancel it with: spot sleep --cancel", state.pid),
+    )
+}
+
+fn remove_state() {
+    if let Ok(path) = Config::state_path(FILE) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn is_alive(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// The timer from the state file, if its process is still running.
+fn active() -> Option<State> {
+    let state = load_state()?;
+    if is_alive(state.pid) {
+        Some(state)
+    } else {
+        remove_state();
+        None
+    }
+}
+
+/// The fade length: `--fade`, else `[sleep] fade_secs`, else 30 seconds.
+pub fn fade_duration(fade: Option<Duration>, config: &Config) -> Duration {
+    fade.or_else(|| config.sleep.as_ref()?.fade_secs.map(Duration::from_secs))
+        .unwrap_or(DEFAULT_FADE)
+}
+
+/// Counts down to `until`, fades the volume out, pauses and puts the volume
+/// back. Runs in the foreground; `spot sleep --cancel` stops it.
+pub async fn run(player: &Player, until: Until, fade: Duration, out: Output) -> Result<()> {
+    let mut state = State {
+        pid: std::process::id(),
+        until,
+        fade_secs: fade.as_secs(),
+    };
+    claim_state(&state)?;
+    if out == Output::Pretty {
+        print_state(&state);
+        let dim = Style::new().dim();
+        println!("  {}", dim.apply_to("Keep this running; spot sleep --cancel stops it."));
+    }
+
+    let result = count_down(player, &Config::load()?, &mut state, fade).await;
+    remove_state();
+    let outcome = result?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "slept": outcome == Outcome::Slept })),
+        Output::Plain => println!("{}", if outcome == Outcome::Slept { "paused" } else { "cancelled" }),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            match outcome {
+                Outcome::Slept => println!("  {} Good night", dim.apply_to("💤")),
+                Outcome::Cancelled => println!("  {} Sleep timer cancelled", dim.apply_to("✓")),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Slept,
+    Cancelled,
+}
+
+async fn count_down(player: &Player, config: &Config, state: &mut State, fade: Duration) -> Result<Outcome> {
+    let curve = config.volume_settings().curve;
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let mut ticker = tokio::time::interval(TICK);
+    let mut polled: Option<Instant> = None;
+    let mut track: Option<String> = None;
+    // For the end of an album: the last track seen and whether it ends it.
+    let mut album_end: Option<(String, bool)> = None;
+    // Set once the fade starts: when it started and the volume to restore.
+    let mut fading: Option<(Instant, i32)> = None;
+
+    loop {
+        let cancelled = tokio::select! {
+            _ = ticker.tick() => load_state().is_none_or(|s| s.pid != state.pid),
+            _ = tokio::signal::ctrl_c() => true,
+            _ = terminate.recv() => true,
+        };
+        if cancelled {
+            if let Some((_, volume)) = fading {
+                player.set_volume(volume).await?;
+            }
+            return Ok(Outcome::Cancelled);
+        }
+
+        if let Some((started, volume)) = fading {
+            let progress = started.elapsed().as_secs_f64() / fade.as_secs_f64().max(0.001);
+            if progress >= 1.0 {
+                // Put the volume back even if the pause didn't go through.
+                let paused = player.pause().await;
+                player.set_volume(volume).await?;
+                paused?;
+                return Ok(Outcome::Slept);
+            }
+            // A failed step is made up by the next one; ending here would
+            // leave the volume half faded.
+            let _ = player.set_volume(curve.level(volume, 0, progress)).await;
+            continue;
+        }
+
+        let due = match &mut state.until {
+            Until::Time { ends_at } => now_secs() + fade.as_secs() >= *ends_at,
+            _ if polled.is_some_and(|p| p.elapsed() < POLL) => false,
+            until => {
+                polled = Some(Instant::now());
+                match player.get_now_playing().await {
+                    Ok(info) => {
+                        let seen = album_end.as_ref().is_some_and(|(id, _)| *id == info.track_id);
+                        if matches!(until, Until::EndOfAlbum { .. }) && !seen {
+                            let last = ends_album(config, &info.track_id).await;
+                            album_end = Some((info.track_id.clone(), last));
+                        }
+                        let last = album_end.as_ref().is_some_and(|(_, last)| *last);
+                        let before = remaining(until);
+                        let due = track_due(until, &mut track, &info, fade, last);
+                        if remaining(until) != before {
+                            update_state(state)?;
+                        }
+                        due
+                    }
+                    Err(_) => false,
+                }
+            }
+        };
+        if due {
+            let volume = player.get_volume().await?;
+            fading = Some((Instant::now(), volume));
+        }
+    }
+}
+
+/// Tracks left, for timers that count them.
+fn remaining(until: &Until) -> Option<u32> {
+    match until {
+        Until::Tracks { remaining } => Some(*remaining),
+        _ => None,
+    }
+}
+
+/// Whether the Web API says `track_uri` ends its album. Without a token, or
+/// when it can't tell, the timer waits for the album to change instead.
+async fn ends_album(config: &Config, track_uri: &str) -> bool {
+    match auth::get_token(config).await {
+        Ok(token) => api::ends_album(&token, track_uri).await.unwrap_or(false),
+        Err(_) => false,
+    }
+}
+
+/// Advances the track count and reports whether the fade should start:
+/// when the last track is within the fade of its end, or already over.
+/// `last_of_album` says the playing track is known to end its album.
+fn track_due(
+    until: &mut Until,
+    track: &mut Option<String>,
+    info: &NowPlayingInfo,
+    fade: Duration,
+    last_of_album: bool,
+) -> bool {
+    let changed = track.as_deref().is_some_and(|t| t != info.track_id);
+    *track = Some(info.track_id.clone());
+    let ending = info.duration_ms - info.position_ms <= fade.as_millis() as i64;
+    match until {
+        Until::Tracks { remaining } => {
+            if changed {
+                *remaining = remaining.saturating_sub(1);
+            }
+            *remaining == 0 || (*remaining == 1 && ending)
+        }
+        Until::EndOfAlbum { album } => *album != info.album || (last_of_album && ending),
+        Until::Time { .. } => false,
+    }
+}
+
+pub fn status(out: Output) -> Result<()> {
+    let state = active();
+    match out {
+        Output::Json => output::print_json(&json!({ "active": state.is_some(), "timer": state })),
+        Output::Plain => match &state {
+            Some(state) => println!("{}", describe(&state.until)),
+            None => println!("inactive"),
+        },
+        Output::Pretty => match &state {
+            Some(state) => print_state(state),
+            None => println!("  No sleep timer running."),
+        },
+    }
+    Ok(())
+}
+
+pub fn cancel(out: Output) -> Result<()> {
+    let cancelled = active().is_some();
+    // The timer notices the missing file and restores the volume itself.
+    remove_state();
+    match out {
+        Output::Json => output::print_json(&json!({ "cancelled": cancelled })),
+        Output::Plain => println!("{}", if cancelled { "cancelled" } else { "inactive" }),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            if cancelled {
+                println!("  {} Sleep timer cancelled", dim.apply_to("✓"));
+            } else {
+                println!("  No sleep timer running.");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn describe(until: &Until) -> String {
+    match until {
+        Until::Time { ends_at } => {
+            let left = ends_at.saturating_sub(now_secs()) as i64 * 1000;
+            format!("in {}", display::format_duration(left))
+        }
+        Until::EndOfAlbum { album } => format!("at the end of {album}"),
+        Until::Tracks { remaining: 1 } => "after this track".to_string(),
+        Until::Tracks { remaining } => format!("after {remaining} tracks"),
+    }
+}
+
+fn print_state(state: &State) {
+    let dim = Style::new().dim();
+    println!(
+        "  {} Sleeping {} {}",
+        dim.apply_to("💤"),
+        describe(&state.until),
+        dim.apply_to(format!("(fade {}s)", state.fade_secs)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ConfigHome, env_lock};
+
+    const FADE: Duration = Duration::from_secs(30);
+
+    fn at(track_id: &str, album: &str, position_ms: i64) -> NowPlayingInfo {
+        NowPlayingInfo {
+            track_name: track_id.to_string(),
+            artist: "Artist".to_string(),
+            album: album.to_string(),
+            track_id: track_id.to_string(),
+            duration_ms: 200_000,
+            position_ms,
+            is_playing: true,
+        }
+    }
+
+    #[test]
+    fn counts_tracks_down() {
+        let mut until = Until::Tracks { remaining: 2 };
+        let mut track = None;
+        assert!(!track_due(&mut until, &mut track, &at("a", "LP", 10_000), FADE, false));
+        // Near the end of the first track, with another still to go.
+        assert!(!track_due(&mut until, &mut track, &at("a", "LP", 190_000), FADE, false));
+        assert!(!track_due(&mut until, &mut track, &at("b", "LP", 0), FADE, false));
+        assert!(matches!(until, Until::Tracks { remaining: 1 }));
+        assert!(!track_due(&mut until, &mut track, &at("b", "LP", 169_999), FADE, false));
+        assert!(track_due(&mut until, &mut track, &at("b", "LP", 170_000), FADE, false));
+    }
+
+    #[test]
+    fn a_missed_ending_stops_on_the_next_track() {
+        let mut until = Until::Tracks { remaining: 1 };
+        let mut track = None;
+        assert!(!track_due(&mut until, &mut track, &at("a", "LP", 0), FADE, false));
+        assert!(track_due(&mut until, &mut track, &at("b", "LP", 0), FADE, false));
+        assert!(matches!(until, Until::Tracks { remaining: 0 }));
+    }
+
+    #[test]
+    fn fades_over_the_last_track_of_an_album() {
+        let mut until = Until::EndOfAlbum { album: "LP".to_string() };
+        let mut track = None;
+        assert!(!track_due(&mut until, &mut track, &at("a", "LP", 190_000), FADE, false));
+        assert!(!track_due(&mut until, &mut track, &at("z", "LP", 100_000), FADE, true));
+        assert!(track_due(&mut until, &mut track, &at("z", "LP", 175_000), FADE, true));
+    }
+
+    #[test]
+    fn stops_when_the_album_changes_if_its_end_is_unknown() {
+        let mut until = Until::EndOfAlbum { album: "LP".to_string() };
+        let mut track = None;
+        assert!(!track_due(&mut until, &mut track, &at("z", "LP", 199_000), FADE, false));
+        assert!(track_due(&mut until, &mut track, &at("next", "EP", 0), FADE, false));
+    }
+
+    #[test]
+    fn timers_are_not_track_driven() {
+        let mut until = Until::Time { ends_at: 0 };
+        let mut track = None;
+        assert!(!track_due(&mut until, &mut track, &at("a", "LP", 199_000), FADE, true));
+    }
+
+    fn timer(remaining: u32) -> State {
+        State {
+            pid: std::process::id(),
+            until: Until::Tracks { remaining },
+            fade_secs: 30,
+        }
+    }
+
+    #[tokio::test]
+    async fn a_second_timer_is_refused() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        claim_state(&timer(3)).unwrap();
+        assert!(claim_state(&timer(1)).is_err());
+        assert!(matches!(load_state().unwrap().until, Until::Tracks { remaining: 3 }));
+    }
+
+    #[tokio::test]
+    async fn a_dead_timer_is_replaced() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let dead = State { pid: u32::MAX / 2, ..timer(3) };
+        config::save_json_atomic(FILE, &dead).unwrap();
+        claim_state(&timer(1)).unwrap();
+        assert_eq!(load_state().unwrap().pid, std::process::id());
+    }
+
+    #[tokio::test]
+    async fn updates_never_bring_back_a_cancelled_timer() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        claim_state(&timer(3)).unwrap();
+        update_state(&timer(2)).unwrap();
+        assert!(matches!(load_state().unwrap().until, Until::Tracks { remaining: 2 }));
+
+        remove_state();
+        update_state(&timer(1)).unwrap();
+        assert!(load_state().is_none());
+    }
+}
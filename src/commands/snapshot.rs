@@ -0,0 +1,141 @@
+use anyhow::Result;
+use console::Style;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::config;
+use crate::display;
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::snapshot::Snapshot;
+use crate::spotify::player::Player;
+use crate::undo;
+
+const FILE: &str = "snapshots.json";
+
+fn load() -> Result<BTreeMap<String, Snapshot>> {
+    Ok(config::load_json(FILE)?.unwrap_or_default())
+}
+
+fn save_all(snapshots: &BTreeMap<String, Snapshot>) -> Result<()> {
+    config::save_json_atomic(FILE, snapshots)
+}
+
+pub async fn save(player: &Player, name: &str, out: Output) -> Result<()> {
+    let snapshot = Snapshot::capture(player).await?;
+    let mut snapshots = load()?;
+    let replaced = snapshots.insert(name.to_string(), snapshot.clone()).is_some();
+    save_all(&snapshots)?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "name": name, "snapshot": snapshot, "replaced": replaced })),
+        Output::Plain => print_plain(name, &snapshot),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            println!("  {} Saved {}", green.apply_to("✓"), describe(name, &snapshot));
+            if snapshot.context.is_none() {
+                println!(
+                    "  {}",
+                    dim.apply_to("No album or playlist known; restore plays just this track.")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn restore(player: &Player, name: &str, out: Output) -> Result<()> {
+    let snapshots = load()?;
+    let snapshot = snapshots.get(name).ok_or_else(|| {
+        fail(
+            ErrorKind::NotFound,
+            format!("No saved state named \"{name}\". See: spot save --list"),
+        )
+    })?;
+    undo::remember(player).await;
+    snapshot.apply(player).await?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "name": name, "snapshot": snapshot })),
+        Output::Plain => print_plain(name, snapshot),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            println!("  {} Restored {}", green.apply_to("▶"), describe(name, snapshot));
+        }
+    }
+    Ok(())
+}
+
+pub fn list(out: Output) -> Result<()> {
+    let snapshots = load()?;
+    match out {
+        Output::Json => output::print_json(&snapshots),
+        Output::Plain => {
+            for (name, snapshot) in &snapshots {
+                print_plain(name, snapshot);
+            }
+        }
+        Output::Pretty => {
+            if snapshots.is_empty() {
+                println!("  No saved states. Save one with: spot save <name>");
+                return Ok(());
+            }
+            let dim = Style::new().dim();
+            for (name, snapshot) in &snapshots {
+                println!(
+                    "  {} {}",
+                    describe(name, snapshot),
+                    dim.apply_to(format!("· {}", display::format_timestamp(snapshot.saved_at as i64))),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn remove(name: &str, out: Output) -> Result<()> {
+    let mut snapshots = load()?;
+    if snapshots.remove(name).is_none() {
+        return Err(fail(
+            ErrorKind::NotFound,
+            format!("No saved state named \"{name}\""),
+        ));
+    }
+    save_all(&snapshots)?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "removed": name })),
+        Output::Plain => println!("{name}"),
+        Output::Pretty => {
+            let dim = Style::new().dim();
+            println!("  {} Removed {name}", dim.apply_to("✓"));
+        }
+    }
+    Ok(())
+}
+
+fn print_plain(name: &str, snapshot: &Snapshot) {
+    println!(
+        "{name}\t{}\t{}\t{}\t{}",
+        snapshot.track,
+        snapshot.artist,
+        display::format_duration(snapshot.position_ms),
+        snapshot.context.as_deref().unwrap_or(&snapshot.track_id),
+    );
+}
+
+fn describe(name: &str, snapshot: &Snapshot) -> String {
+    let green = Style::new().green().bold();
+    let dim = Style::new().dim();
+    format!(
+        "{} {} {}",
+        green.apply_to(name),
+        snapshot.track,
+        dim.apply_to(format!(
+            "— {} at {}/{}",
+            snapshot.artist,
+            display::format_duration(snapshot.position_ms),
+            display::format_duration(snapshot.duration_ms),
+        )),
+    )
+}
@@ -0,0 +1,400 @@
+use anyhow::Result;
+use chrono::{Local, NaiveDate, TimeZone, Timelike};
+use console::Style;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::display;
+use crate::history::{self, Filter, Play};
+use crate::output::{self, Output};
+use crate::spotify::auth::now_secs;
+
+/// Plays shorter than this count as skips.
+const SKIP_MS: i64 = 30_000;
+
+/// The daily sparkline shows at most this many days.
+const SPARKLINE_DAYS: usize = 60;
+
+#[derive(Debug, Clone, Serialize)]
+struct Ranked {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    artist: Option<String>,
+    plays: usize,
+    listened_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct Top {
+    by_plays: Vec<Ranked>,
+    by_time: Vec<Ranked>,
+}
+
+#[derive(Debug, Serialize)]
+struct Day {
+    date: NaiveDate,
+    listened_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    plays: usize,
+    listened_ms: i64,
+    skipped: usize,
+    skip_rate: f64,
+    top_artists: Top,
+    top_albums: Top,
+    top_tracks: Top,
+    per_day: Vec<Day>,
+    /// Listening time by local hour of day, 0–23.
+    per_hour: Vec<i64>,
+    current_streak: u32,
+    longest_streak: u32,
+}
+
+pub fn run(filter: &Filter, period: &str, top: usize, out: Output) -> Result<()> {
+    let plays: Vec<Play> = history::load()?
+        .into_iter()
+        .filter(|p| filter.matches(p))
+        .collect();
+    let today = local_date(now_secs() as i64).unwrap_or_default();
+    let stats = aggregate(&plays, top, today);
+    match out {
+        Output::Json => output::print_json(&stats),
+        Output::Plain => print_plain(&stats),
+        Output::Pretty => print_stats(&stats, period),
+    }
+    Ok(())
+}
+
+fn local_date(secs: i64) -> Option<NaiveDate> {
+    Local.timestamp_opt(secs, 0).single().map(|t| t.date_naive())
+}
+
+fn is_skip(play: &Play) -> bool {
+    play.listened_ms < SKIP_MS && play.listened_ms < play.duration_ms
+}
+
+/// Counts plays and time per key, then keeps the `top` entries both ways.
+fn rank<'a>(plays: &'a [Play], top: usize, key: impl Fn(&'a Play) -> (&'a str, Option<&'a str>)) -> Top {
+    let mut totals: HashMap<(&str, Option<&str>), (usize, i64)> = HashMap::new();
+    for play in plays {
+        let entry = totals.entry(key(play)).or_default();
+        entry.0 += 1;
+        entry.1 += play.listened_ms;
+    }
+    let mut ranked: Vec<Ranked> = totals
+        .into_iter()
+        .map(|((name, artist), (plays, listened_ms))| Ranked {
+            name: name.to_string(),
+            artist: artist.map(String::from),
+            plays,
+            listened_ms,
+        })
+        .collect();
+
+    let ordered = |ranked: &mut Vec<Ranked>, by_plays: bool| {
+        ranked.sort_by(|a, b| {
+            let primary = if by_plays {
+                b.plays.cmp(&a.plays).then(b.listened_ms.cmp(&a.listened_ms))
+            } else {
+                b.listened_ms.cmp(&a.listened_ms).then(b.plays.cmp(&a.plays))
+            };
+            primary.then_with(|| a.name.cmp(&b.name))
+        });
+        ranked.iter().take(top).cloned().collect()
+    };
+    Top {
+        by_plays: ordered(&mut ranked, true),
+        by_time: ordered(&mut ranked, false),
+    }
+}
+
+fn aggregate(plays: &[Play], top: usize, today: NaiveDate) -> Stats {
+    let listened_ms = plays.iter().map(|p| p.listened_ms).sum();
+    let skipped = plays.iter().filter(|p| is_skip(p)).count();
+
+    let mut days: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+    let mut per_hour = vec![0; 24];
+    for play in plays {
+        let Some(time) = Local.timestamp_opt(play.started_at, 0).single() else {
+            continue;
+        };
+        *days.entry(time.date_naive()).or_default() += play.listened_ms;
+        per_hour[time.hour() as usize] += play.listened_ms;
+    }
+
+    // Every day from the first play to the last, so gaps show as zeros.
+    let per_day = match (days.keys().next(), days.keys().next_back()) {
+        (Some(&first), Some(&last)) => first
+            .iter_days()
+            .take_while(|date| *date <= last)
+            .map(|date| Day {
+                date,
+                listened_ms: days.get(&date).copied().unwrap_or(0),
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let (current_streak, longest_streak) = streaks(&days, today);
+    Stats {
+        plays: plays.len(),
+        listened_ms,
+        skipped,
+        skip_rate: if plays.is_empty() {
+            0.0
+        } else {
+            skipped as f64 / plays.len() as f64
+        },
+        top_artists: rank(plays, top, |p| (p.artist.as_str(), None)),
+        top_albums: rank(plays, top, |p| (p.album.as_str(), Some(p.artist.as_str()))),
+        top_tracks: rank(plays, top, |p| (p.track.as_str(), Some(p.artist.as_str()))),
+        per_day,
+        per_hour,
+        current_streak,
+        longest_streak,
+    }
+}
+
+/// Runs of consecutive days with any listening. The current streak may end
+/// yesterday, so it doesn't reset before today's first play.
+fn streaks(days: &BTreeMap<NaiveDate, i64>, today: NaiveDate) -> (u32, u32) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for &date in days.keys() {
+        run = match previous {
+            Some(p) if p.succ_opt() == Some(date) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(date);
+    }
+
+    let current = match previous {
+        Some(last) if last == today || last.succ_opt() == Some(today) => run,
+        _ => 0,
+    };
+    (current, longest)
+}
+
+fn print_plain(stats: &Stats) {
+    println!("plays\t{}", stats.plays);
+    println!("listened_ms\t{}", stats.listened_ms);
+    println!("skipped\t{}", stats.skipped);
+    println!("current_streak\t{}", stats.current_streak);
+    println!("longest_streak\t{}", stats.longest_streak);
+    for (label, top) in [
+        ("artist", &stats.top_artists),
+        ("album", &stats.top_albums),
+        ("track", &stats.top_tracks),
+    ] {
+        for r in &top.by_plays {
+            println!(
+                "{label}\t{}\t{}\t{}\t{}",
+                r.name,
+                r.artist.as_deref().unwrap_or(""),
+                r.plays,
+                r.listened_ms
+            );
+        }
+    }
+}
+
+fn print_stats(stats: &Stats, period: &str) {
+    let green = Style::new().green().bold();
+    let dim = Style::new().dim();
+
+    if stats.plays == 0 {
+        println!("  No plays recorded ({period}).");
+        println!(
+            "  Start recording with: {}",
+            dim.apply_to("spot history --record")
+        );
+        return;
+    }
+
+    println!("\n  {} {}\n", green.apply_to("Listening stats"), dim.apply_to(period));
+    println!(
+        "  {} plays · {} · {:.0}% skipped · {} day streak {}",
+        stats.plays,
+        display::format_total(stats.listened_ms),
+        stats.skip_rate * 100.0,
+        stats.current_streak,
+        dim.apply_to(format!("(longest {})", stats.longest_streak)),
+    );
+
+    print_top("Top artists", &stats.top_artists);
+    print_top("Top albums", &stats.top_albums);
+    print_top("Top tracks", &stats.top_tracks);
+
+    let shown = &stats.per_day[stats.per_day.len().saturating_sub(SPARKLINE_DAYS)..];
+    if let (Some(first), Some(last)) = (shown.first(), shown.last()) {
+        let days: Vec<i64> = shown.iter().map(|d| d.listened_ms).collect();
+        println!("\n  {}", green.apply_to("Per day"));
+        println!("  {}", display::sparkline(&days));
+        println!(
+            "  {}",
+            dim.apply_to(format!("{} – {}", first.date.format("%d %b"), last.date.format("%d %b")))
+        );
+    }
+
+    println!("\n  {}", green.apply_to("Per hour"));
+    println!("  {}", display::heatmap(&stats.per_hour, 2));
+    println!("  {}", dim.apply_to("0     3     6     9     12    15    18    21"));
+    println!();
+}
+
+fn print_top(title: &str, top: &Top) {
+    let green = Style::new().green().bold();
+    let dim = Style::new().dim();
+
+    println!(
+        "\n  {} {}",
+        green.apply_to(format!("{title:<44}")),
+        dim.apply_to("plays    time")
+    );
+    for (i, r) in top.by_plays.iter().enumerate() {
+        let name = match &r.artist {
+            Some(artist) => format!("{} — {artist}", r.name),
+            None => r.name.clone(),
+        };
+        println!(
+            "  {} {:<40} {:>5} {:>7}",
+            dim.apply_to(format!("{:>2}.", i + 1)),
+            display::truncate(&name, 40),
+            r.plays,
+            display::format_total(r.listened_ms),
+        );
+    }
+
+    // Only worth a second list when time ranks them differently.
+    let same = top
+        .by_plays
+        .iter()
+        .zip(&top.by_time)
+        .all(|(a, b)| a.name == b.name && a.artist == b.artist);
+    if !same {
+        let names: Vec<&str> = top.by_time.iter().map(|r| r.name.as_str()).collect();
+        println!("  {}", dim.apply_to(format!("By time: {}", names.join(", "))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap()
+    }
+
+    /// A play starting at `hour` local time on March `day`.
+    fn play(artist: &str, album: &str, track: &str, day: u32, hour: i64, listened_ms: i64) -> Play {
+        Play {
+            track_id: format!("spotify:track:{track}"),
+            track: track.to_string(),
+            artist: artist.to_string(),
+            album: album.to_string(),
+            started_at: history::local_midnight(date(day)).unwrap() + hour * 3600,
+            listened_ms,
+            duration_ms: 200_000,
+        }
+    }
+
+    fn names(ranked: &[Ranked]) -> Vec<&str> {
+        ranked.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    fn days(days: &[u32]) -> BTreeMap<NaiveDate, i64> {
+        days.iter().map(|&d| (date(d), 60_000)).collect()
+    }
+
+    #[test]
+    fn ranks_by_plays_and_by_time() {
+        let plays = [
+            play("Miles Davis", "Kind of Blue", "So What", 1, 10, 100_000),
+            play("Miles Davis", "Kind of Blue", "So What", 1, 11, 100_000),
+            play("Miles Davis", "Kind of Blue", "Freddie Freeloader", 1, 12, 100_000),
+            play("John Coltrane", "Ballads", "Say It", 2, 10, 900_000),
+            play("Bill Evans", "Waltz for Debby", "My Foolish Heart", 2, 11, 100_000),
+        ];
+        let stats = aggregate(&plays, 2, date(2));
+        // Coltrane and Evans tie on plays; Coltrane listened longer.
+        assert_eq!(names(&stats.top_artists.by_plays), ["Miles Davis", "John Coltrane"]);
+        assert_eq!(names(&stats.top_artists.by_time), ["John Coltrane", "Miles Davis"]);
+        assert_eq!(names(&stats.top_tracks.by_plays), ["So What", "Say It"]);
+        let album = &stats.top_albums.by_plays[0];
+        assert_eq!((album.name.as_str(), album.artist.as_deref()), ("Kind of Blue", Some("Miles Davis")));
+        assert_eq!((album.plays, album.listened_ms), (3, 300_000));
+        assert_eq!(stats.top_artists.by_plays[0].artist, None);
+    }
+
+    #[test]
+    fn ties_break_on_the_other_measure_then_the_name() {
+        let plays = [
+            play("Bud Powell", "A", "a", 1, 10, 50_000),
+            play("Art Tatum", "B", "b", 1, 11, 50_000),
+            play("Zoot Sims", "C", "c", 1, 12, 90_000),
+        ];
+        let stats = aggregate(&plays, 3, date(1));
+        assert_eq!(names(&stats.top_artists.by_plays), ["Zoot Sims", "Art Tatum", "Bud Powell"]);
+        assert_eq!(names(&stats.top_artists.by_time), ["Zoot Sims", "Art Tatum", "Bud Powell"]);
+    }
+
+    #[test]
+    fn totals_skips_and_days() {
+        let plays = [
+            play("A", "A", "a", 1, 9, 120_000),
+            play("A", "A", "b", 1, 9, 10_000),
+            play("B", "B", "c", 3, 22, 200_000),
+        ];
+        let stats = aggregate(&plays, 5, date(3));
+        assert_eq!(stats.plays, 3);
+        assert_eq!(stats.listened_ms, 330_000);
+        assert_eq!(stats.skipped, 1);
+        assert!((stats.skip_rate - 1.0 / 3.0).abs() < 1e-9);
+        let per_day: Vec<(NaiveDate, i64)> = stats.per_day.iter().map(|d| (d.date, d.listened_ms)).collect();
+        assert_eq!(per_day, [(date(1), 130_000), (date(2), 0), (date(3), 200_000)]);
+        assert_eq!(stats.per_hour[9], 130_000);
+        assert_eq!(stats.per_hour[22], 200_000);
+        assert_eq!(stats.per_hour.iter().sum::<i64>(), 330_000);
+    }
+
+    #[test]
+    fn a_whole_track_under_thirty_seconds_is_not_a_skip() {
+        let mut short = play("A", "A", "a", 1, 9, 20_000);
+        short.duration_ms = 20_000;
+        assert!(!is_skip(&short));
+    }
+
+    #[test]
+    fn empty_history() {
+        let stats = aggregate(&[], 5, date(1));
+        assert_eq!((stats.plays, stats.listened_ms, stats.skipped), (0, 0, 0));
+        assert_eq!(stats.skip_rate, 0.0);
+        assert!(stats.top_artists.by_plays.is_empty());
+        assert!(stats.per_day.is_empty());
+        assert_eq!((stats.current_streak, stats.longest_streak), (0, 0));
+    }
+
+    #[test]
+    fn the_current_streak_may_end_today_or_yesterday() {
+        assert_eq!(streaks(&days(&[8, 9, 10]), date(10)), (3, 3));
+        assert_eq!(streaks(&days(&[8, 9, 10]), date(11)), (3, 3));
+        assert_eq!(streaks(&days(&[8, 9, 10]), date(12)), (0, 3));
+    }
+
+    #[test]
+    fn gaps_end_a_streak() {
+        assert_eq!(streaks(&days(&[1, 2, 3, 4, 6, 7]), date(7)), (2, 4));
+        assert_eq!(streaks(&days(&[1, 3, 5]), date(5)), (1, 1));
+        assert_eq!(streaks(&days(&[]), date(5)), (0, 0));
+        // Across a month end.
+        let days: BTreeMap<NaiveDate, i64> = [date(1), date(1).pred_opt().unwrap()]
+            .into_iter()
+            .map(|d| (d, 1))
+            .collect();
+        assert_eq!(streaks(&days, date(1)), (2, 2));
+    }
+}
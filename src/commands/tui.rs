@@ -0,0 +1,534 @@
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, LineGauge, List, ListItem, ListState, Paragraph, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+use std::time::{Duration, Instant};
+
+use super::now::{FRAME, interpolate};
+use crate::config::Config;
+use crate::daemon;
+use crate::display;
+use crate::output::{ErrorKind, fail};
+use crate::playback;
+use crate::spotify::api::{SearchResult, SearchType};
+use crate::spotify::player::{NowPlayingInfo, Player};
+
+const TABS: [SearchType; 4] = [
+    SearchType::Track,
+    SearchType::Album,
+    SearchType::Artist,
+    SearchType::Playlist,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Search,
+    Results,
+}
+
+struct App {
+    config: Config,
+    focus: Focus,
+    query: String,
+    tab: usize,
+    results: Vec<SearchResult>,
+    list: ListState,
+    now: Option<(NowPlayingInfo, Instant)>,
+    /// Why `now` is empty, e.g. nothing playing.
+    idle: String,
+    volume: Option<i32>,
+    shuffle: Option<bool>,
+    repeat: Option<bool>,
+    polled: Option<Instant>,
+    /// The last failed action, shown in place of the key help.
+    error: String,
+}
+
+/// Full-screen player: search box with type tabs, results list and a
+/// now-playing pane that polls the player every `interval`.
+pub async fn run(player: &Player, interval: Duration) -> Result<()> {
+    if !console::user_attended() {
+        return Err(fail(ErrorKind::Usage, "spot tui needs a terminal"));
+    }
+    let mut app = App::new(Config::load()?);
+
+    // try_init also installs a panic hook that restores the terminal.
+    let mut terminal = ratatui::try_init()?;
+    let result = app.event_loop(player, &mut terminal, interval).await;
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn new(config: Config) -> Self {
+        Self {
+            config,
+            focus: Focus::Search,
+            query: String::new(),
+            tab: 0,
+            results: Vec::new(),
+            list: ListState::default(),
+            now: None,
+            idle: String::new(),
+            volume: None,
+            shuffle: None,
+            repeat: None,
+            polled: None,
+            error: String::new(),
+        }
+    }
+
+    async fn event_loop(
+        &mut self,
+        player: &Player,
+        terminal: &mut DefaultTerminal,
+        interval: Duration,
+    ) -> Result<()> {
+        loop {
+            if self.stale(interval) {
+                self.poll(player).await;
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(FRAME)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                return Ok(());
+            }
+            let quit = match self.focus {
+                Focus::Search => {
+                    self.search_key(key).await;
+                    false
+                }
+                Focus::Results => self.results_key(player, key).await,
+            };
+            if quit {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Re-poll on `interval`, after an action, and when the track runs out.
+    fn stale(&self, interval: Duration) -> bool {
+        let Some(polled) = self.polled else {
+            return true;
+        };
+        let ended = self.now.as_ref().is_some_and(|(info, fetched)| {
+            info.is_playing
+                && interpolate(info, *fetched).position_ms >= info.duration_ms
+                && fetched.elapsed() >= Duration::from_secs(1)
+        });
+        polled.elapsed() >= interval || ended
+    }
+
+    async fn poll(&mut self, player: &Player) {
+        match player.get_now_playing().await {
+            Ok(info) => self.now = Some((info, Instant::now())),
+            Err(e) => {
+                self.now = None;
+                self.idle = e.to_string();
+            }
+        }
+        self.volume = player.get_volume().await.ok();
+        self.shuffle = player.get_shuffle().await.ok();
+        self.repeat = player.get_repeat().await.ok();
+        self.polled = Some(Instant::now());
+    }
+
+    async fn search_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter => {
+                self.search().await;
+                if !self.results.is_empty() {
+                    self.focus = Focus::Results;
+                }
+            }
+            KeyCode::Esc | KeyCode::Down => self.focus = Focus::Results,
+            KeyCode::Tab => self.switch_tab(1).await,
+            KeyCode::BackTab => self.switch_tab(TABS.len() - 1).await,
+            KeyCode::Backspace => {
+                self.query.pop();
+            }
+            KeyCode::Char(c) => self.query.push(c),
+            _ => {}
+        }
+    }
+
+    /// Handles a key while the results list has focus; true means quit.
+    async fn results_key(&mut self, player: &Player, key: KeyEvent) -> bool {
+        let result = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Char('/') => {
+                self.focus = Focus::Search;
+                return false;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.list.select_previous();
+                return false;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.list.select_next();
+                return false;
+            }
+            KeyCode::Home | KeyCode::Char('g') => {
+                self.list.select_first();
+                return false;
+            }
+            KeyCode::End | KeyCode::Char('G') => {
+                self.list.select_last();
+                return false;
+            }
+            KeyCode::Tab => {
+                self.switch_tab(1).await;
+                return false;
+            }
+            KeyCode::BackTab => {
+                self.switch_tab(TABS.len() - 1).await;
+                return false;
+            }
+            KeyCode::Enter => {
+                let selected = self.list.selected().and_then(|i| self.results.get(i));
+                match selected {
+                    Some(result) => playback::start(player, result, TABS[self.tab]).await.map(|_| ()),
+                    None => return false,
+                }
+            }
+            KeyCode::Char(' ') => player.toggle().await,
+            KeyCode::Char('n') => player.next_track().await,
+            KeyCode::Char('p') => player.prev_track().await,
+            KeyCode::Char('+') | KeyCode::Char('=') => self.nudge_volume(player, 1).await,
+            KeyCode::Char('-') => self.nudge_volume(player, -1).await,
+            KeyCode::Char('s') => player.set_shuffle(!self.shuffle.unwrap_or(false)).await,
+            KeyCode::Char('r') => player.set_repeat(!self.repeat.unwrap_or(false)).await,
+            _ => return false,
+        };
+        self.error = match result {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        };
+        // Give the player a moment to apply the change before re-reading it.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        self.polled = None;
+        false
+    }
+
+    /// Moves the volume one configured step in `direction`.
+    async fn nudge_volume(&self, player: &Player, direction: i32) -> Result<()> {
+        let vol = player.get_volume().await?;
+        let delta = direction * self.config.volume_settings().step();
+        player.set_volume((vol + delta).clamp(0, 100)).await
+    }
+
+    async fn switch_tab(&mut self, step: usize) {
+        self.tab = (self.tab + step) % TABS.len();
+        if !self.query.trim().is_empty() {
+            self.search().await;
+        }
+    }
+
+    async fn search(&mut self) {
+        let query = self.query.trim();
+        if query.is_empty() {
+            return;
+        }
+        let search_type = TABS[self.tab];
+        match daemon::search(&self.config, query, search_type, 20).await {
+            Ok(results) => {
+                self.error = if results.is_empty() {
+                    format!("No {} found.", search_type.label().to_lowercase())
+                } else {
+                    String::new()
+                };
+                self.list
+                    .select(if results.is_empty() { None } else { Some(0) });
+                self.results = results;
+            }
+            Err(e) => self.error = e.to_string(),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search, tabs, results, now, footer] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(6),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        self.draw_search(frame, search);
+        let titles = TABS.iter().map(|t| t.label());
+        frame.render_widget(
+            Tabs::new(titles)
+                .select(self.tab)
+                .style(dim())
+                .highlight_style(accent()),
+            tabs,
+        );
+        self.draw_results(frame, results);
+        self.draw_now_playing(frame, now);
+        self.draw_footer(frame, footer);
+    }
+
+    fn draw_search(&self, frame: &mut Frame, area: Rect) {
+        let focused = self.focus == Focus::Search;
+        let block = Block::bordered()
+            .title(" Search ")
+            .border_style(if focused { accent() } else { dim() });
+        frame.render_widget(Paragraph::new(self.query.as_str()).block(block), area);
+        if focused {
+            let x = area.x + 1 + self.query.chars().count() as u16;
+            frame.set_cursor_position((x.min(area.right().saturating_sub(2)), area.y + 1));
+        }
+    }
+
+    fn draw_results(&mut self, frame: &mut Frame, area: Rect) {
+        let focused = self.focus == Focus::Results;
+        let block = Block::bordered()
+            .title(format!(" {} ", TABS[self.tab].label()))
+            .border_style(if focused { accent() } else { dim() });
+        if self.results.is_empty() {
+            let hint = Paragraph::new(Span::styled("Type a query and press enter.", dim()));
+            frame.render_widget(hint.block(block), area);
+            return;
+        }
+
+        let items = self.results.iter().map(|r| {
+            ListItem::new(Line::from(vec![
+                Span::raw(r.name.as_str()),
+                Span::styled(format!(" — {}", r.detail), dim()),
+            ]))
+        });
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(accent().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(list, area, &mut self.list);
+    }
+
+    fn draw_now_playing(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title(" Now Playing ").border_style(dim());
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let [title, album, progress, status] = Layout::vertical([Constraint::Length(1); 4]).areas(inner);
+
+        match &self.now {
+            Some((info, fetched)) => {
+                let info = interpolate(info, *fetched);
+                let icon = if info.is_playing { "▶" } else { "⏸" };
+                frame.render_widget(
+                    Line::from(vec![
+                        Span::styled(format!("{icon} {}", info.track_name), accent()),
+                        Span::styled(format!(" — {}", info.artist), dim()),
+                    ]),
+                    title,
+                );
+                frame.render_widget(Span::styled(format!("  {}", info.album), dim()), album);
+                let ratio = if info.duration_ms > 0 {
+                    (info.position_ms as f64 / info.duration_ms as f64).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let label = format!(
+                    "{} / {}",
+                    display::format_duration(info.position_ms),
+                    display::format_duration(info.duration_ms)
+                );
+                frame.render_widget(
+                    LineGauge::default()
+                        .ratio(ratio)
+                        .label(label)
+                        .filled_style(Style::new().fg(Color::Green))
+                        .unfilled_style(dim()),
+                    progress,
+                );
+            }
+            None => frame.render_widget(Span::styled(self.idle.as_str(), dim()), title),
+        }
+
+        let switch = |on: Option<bool>| match on {
+            Some(true) => "on",
+            Some(false) => "off",
+            None => "?",
+        };
+        let volume = self.volume.map_or("?".to_string(), |v| format!("{v}%"));
+        frame.render_widget(
+            Span::styled(
+                format!(
+                    "🔊 {volume}   🔀 {}   🔁 {}",
+                    switch(self.shuffle),
+                    switch(self.repeat)
+                ),
+                dim(),
+            ),
+            status,
+        );
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let line = if !self.error.is_empty() {
+            Span::styled(self.error.as_str(), Style::new().fg(Color::Red))
+        } else if self.focus == Focus::Search {
+            Span::styled("enter search · tab type · esc results · ctrl-c quit", dim())
+        } else {
+            Span::styled(
+                "/ search · ↑↓ select · enter play · space pause · n/p skip · +/- volume · s shuffle · r repeat · tab type · q quit",
+                dim(),
+            )
+        };
+        frame.render_widget(line, area);
+    }
+}
+
+fn accent() -> Style {
+    Style::new().fg(Color::Green).add_modifier(Modifier::BOLD)
+}
+
+fn dim() -> Style {
+    Style::new().add_modifier(Modifier::DIM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spotify::connect::Connect;
+    use crate::test_support::{ConfigHome, Stub, env_lock, route};
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use ratatui::buffer::Buffer;
+    use serde_json::json;
+
+    /// A player that's never asked anything by the keys these tests press.
+    fn player() -> Player {
+        Player::Connect(Box::new(Connect::new(Config::default())))
+    }
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn render(app: &mut App, width: u16, height: u16) -> Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    /// The screen as text, one string per row.
+    fn rows(buffer: &Buffer) -> Vec<String> {
+        buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn focus_moves_between_the_search_box_and_results() {
+        let (mut app, player) = (App::new(Config::default()), player());
+        for c in "miles".chars() {
+            app.search_key(press(KeyCode::Char(c))).await;
+        }
+        app.search_key(press(KeyCode::Backspace)).await;
+        assert_eq!(app.query, "mile");
+        assert_eq!(app.focus, Focus::Search);
+
+        app.search_key(press(KeyCode::Esc)).await;
+        assert_eq!(app.focus, Focus::Results);
+        assert!(!app.results_key(&player, press(KeyCode::Char('/'))).await);
+        assert_eq!(app.focus, Focus::Search);
+        app.search_key(press(KeyCode::Down)).await;
+        assert_eq!(app.focus, Focus::Results);
+
+        // Keys that mean something in the results are typed in the search box.
+        app.results_key(&player, press(KeyCode::Char('/'))).await;
+        app.search_key(press(KeyCode::Char('q'))).await;
+        assert_eq!(app.query, "mileq");
+        app.search_key(press(KeyCode::Esc)).await;
+        assert!(app.results_key(&player, press(KeyCode::Char('q'))).await, "q quits from the results");
+    }
+
+    #[tokio::test]
+    async fn tabs_wrap_around_both_ways() {
+        let (mut app, player) = (App::new(Config::default()), player());
+        app.search_key(press(KeyCode::BackTab)).await;
+        assert_eq!(TABS[app.tab], SearchType::Playlist);
+        app.search_key(press(KeyCode::Tab)).await;
+        assert_eq!(TABS[app.tab], SearchType::Track);
+
+        app.focus = Focus::Results;
+        for expected in [SearchType::Album, SearchType::Artist, SearchType::Playlist, SearchType::Track] {
+            app.results_key(&player, press(KeyCode::Tab)).await;
+            assert_eq!(TABS[app.tab], expected);
+        }
+        app.results_key(&player, press(KeyCode::BackTab)).await;
+        assert_eq!(TABS[app.tab], SearchType::Playlist);
+        assert_eq!(app.focus, Focus::Results);
+    }
+
+    #[tokio::test]
+    async fn enter_searches_and_moves_to_the_results_only_when_there_are_some() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        let token = json!({ "access_token": "t", "expires_at": u64::MAX });
+        std::fs::write(home.dir.join("token.json"), token.to_string()).unwrap();
+        let empty = json!({ "tracks": { "items": [], "total": 0, "next": null } });
+        let found = json!({ "tracks": {
+            "items": [
+                { "name": "So What", "uri": "spotify:track:a",
+                  "artists": [{ "name": "Miles Davis" }], "album": { "name": "Kind of Blue" } },
+            ],
+            "total": 1,
+            "next": null,
+        }});
+        let _stub = Stub::serve_api(vec![
+            route("GET", "/search?q=nothing", 200, empty.to_string()),
+            route("GET", "/search", 200, found.to_string()),
+        ])
+        .await;
+
+        let mut app = App::new(Config::default());
+        app.query = "nothing".to_string();
+        app.search_key(press(KeyCode::Enter)).await;
+        assert_eq!(app.focus, Focus::Search);
+        assert_eq!(app.error, "No tracks found.");
+
+        app.query = "so what".to_string();
+        app.search_key(press(KeyCode::Enter)).await;
+        assert_eq!(app.focus, Focus::Results);
+        assert_eq!(app.results.len(), 1);
+        assert_eq!(app.list.selected(), Some(0));
+        assert!(app.error.is_empty());
+    }
+
+    #[test]
+    fn draws_the_search_box_tabs_results_and_help() {
+        let mut app = App::new(Config::default());
+        app.query = "kind of blue".to_string();
+        app.tab = 1;
+        app.idle = "Nothing playing".to_string();
+        let buffer = render(&mut app, 80, 16);
+        let screen = rows(&buffer);
+
+        assert_eq!(screen[0], format!("┌ Search {}┐", "─".repeat(70)));
+        assert_eq!(screen[1], format!("│{:<78}│", "kind of blue"));
+        assert_eq!(screen[3], format!("{:<80}", " Tracks │ Albums │ Artists │ Playlists"));
+        assert_eq!(buffer[(10, 3)].fg, Color::Green, "the selected tab stands out");
+        assert_eq!(buffer[(1, 3)].fg, Color::Reset);
+        assert!(screen[4].starts_with("┌ Albums ─"));
+        assert_eq!(screen[5], format!("│{:<78}│", "Type a query and press enter."));
+        assert!(screen[9].starts_with("┌ Now Playing ─"));
+        assert_eq!(screen[10], format!("│{:<78}│", "Nothing playing"));
+        assert_eq!(screen[15], format!("{:<80}", "enter search · tab type · esc results · ctrl-c quit"));
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use console::Style;
+use serde_json::json;
+
+use crate::display;
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::spotify::player::Player;
+use crate::undo;
+
+/// Goes back to what was playing before the last play command.
+pub async fn run(player: &Player, out: Output) -> Result<()> {
+    let stack = undo::load()?;
+    let Some(previous) = stack.last() else {
+        return Err(fail(ErrorKind::NotFound, "Nothing to go back to"));
+    };
+    previous.apply(player).await?;
+    // Only dropped once it's back, so a failed restore can be retried.
+    undo::pop()?;
+
+    match out {
+        Output::Json => output::print_json(&json!({ "restored": previous, "remaining": stack.len() - 1 })),
+        Output::Plain => println!(
+            "{}\t{}\t{}\t{}",
+            previous.track_id,
+            previous.track,
+            previous.artist,
+            display::format_duration(previous.position_ms),
+        ),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            println!(
+                "  {} {} {}",
+                green.apply_to("↶"),
+                green.apply_to(&previous.track),
+                dim.apply_to(format!(
+                    "— {} at {}",
+                    previous.artist,
+                    display::format_duration(previous.position_ms)
+                )),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The stack, newest first, numbered by how many undos reach each entry.
+pub fn list(out: Output) -> Result<()> {
+    let stack = undo::load()?;
+    match out {
+        Output::Json => output::print_json(&stack.iter().rev().collect::<Vec<_>>()),
+        Output::Plain => {
+            for (i, entry) in stack.iter().rev().enumerate() {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    i + 1,
+                    entry.track_id,
+                    entry.track,
+                    entry.artist,
+                    display::format_duration(entry.position_ms),
+                );
+            }
+        }
+        Output::Pretty => {
+            if stack.is_empty() {
+                println!("  Nothing to go back to.");
+                return Ok(());
+            }
+            let dim = Style::new().dim();
+            for (i, entry) in stack.iter().rev().enumerate() {
+                println!(
+                    "  {} {} {} {}",
+                    dim.apply_to(format!("{:>2}", i + 1)),
+                    entry.track,
+                    dim.apply_to(format!(
+                        "— {} at {}",
+                        entry.artist,
+                        display::format_duration(entry.position_ms)
+                    )),
+                    dim.apply_to(format!("· {}", display::format_timestamp(entry.saved_at as i64))),
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,10 +1,14 @@
 use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+use crate::spotify::player::Backend;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub client_id: Option<String>,
@@ -12,16 +16,178 @@ pub struct Config {
     pub client_secret: Option<String>,
     #[serde(default)]
     pub aliases: Option<HashMap<String, String>>,
+    /// Player backend; auto-detected from the platform when unset.
+    #[serde(default)]
+    pub backend: Option<Backend>,
+    /// Connect device (name or id) to play on when the desktop app isn't
+    /// running.
+    #[serde(default)]
+    pub default_device: Option<String>,
+    /// Redirect URI for `spot auth login`; must match the Spotify app settings.
+    #[serde(default)]
+    pub redirect_uri: Option<String>,
+    /// Named output templates; `now` is the default for `spot now`.
+    #[serde(default)]
+    pub formats: Option<HashMap<String, String>>,
+    /// Listening history settings for `spot history`.
+    #[serde(default)]
+    pub history: Option<HistorySettings>,
+    /// Scrobbling targets for `spot scrobble`.
+    #[serde(default)]
+    pub scrobble: Option<ScrobbleSettings>,
+    /// Shell commands run by `spot hooks run` on player events.
+    #[serde(default)]
+    pub hooks: Option<HooksSettings>,
+    /// Desktop notifications from `spot notify`.
+    #[serde(default)]
+    pub notify: Option<NotifySettings>,
+    /// Sleep timer settings for `spot sleep`.
+    #[serde(default)]
+    pub sleep: Option<SleepSettings>,
+    /// Volume steps and fades.
+    #[serde(default)]
+    pub volume: Option<VolumeSettings>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistorySettings {
+    /// Stop writing new plays without stopping the recorder.
+    #[serde(default)]
+    pub paused: bool,
+    /// Plays older than this many days are deleted.
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrobbleSettings {
+    #[serde(default)]
+    pub listenbrainz: Option<ListenBrainzSettings>,
+    #[serde(default)]
+    pub lastfm: Option<LastFmSettings>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListenBrainzSettings {
+    /// User token from https://listenbrainz.org/settings/
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastFmSettings {
+    /// API account from https://www.last.fm/api/account/create
+    pub api_key: String,
+    pub api_secret: String,
+    /// Used when `lastfm_session.json`, where `spot scrobble login` keeps
+    /// the session key, doesn't exist.
+    #[serde(default)]
+    pub session_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HooksSettings {
+    #[serde(default)]
+    pub track_changed: Option<String>,
+    #[serde(default)]
+    pub paused: Option<String>,
+    #[serde(default)]
+    pub resumed: Option<String>,
+    #[serde(default)]
+    pub volume_changed: Option<String>,
+    #[serde(default)]
+    pub playback_stopped: Option<String>,
+    /// Seconds before a hook is killed; 10 when unset.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotifySettings {
+    /// Set to false to silence `spot notify` without stopping it.
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    /// Skip notifications while the terminal running `spot notify` has focus.
+    #[serde(default)]
+    pub only_when_unfocused: bool,
+    /// Template for the title; `{track}` when unset.
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Template for the body; `{artist} — {album}` when unset.
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Fetch the album cover from the Web API and show it.
+    #[serde(default)]
+    pub album_art: bool,
+    /// Milliseconds on screen; the notification server decides when unset.
+    #[serde(default)]
+    pub timeout_ms: Option<i32>,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl Default for NotifySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            only_when_unfocused: false,
+            summary: None,
+            body: None,
+            album_art: false,
+            timeout_ms: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SleepSettings {
+    /// Seconds the volume takes to fade out; 30 when unset.
+    #[serde(default)]
+    pub fade_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VolumeSettings {
+    /// Step for `spot vol up`/`down` and the `+`/`-` keys, from 1 to 100; 5 when unset.
+    #[serde(default)]
+    pub step: Option<i32>,
+    /// Shape of `--over` ramps and fades.
+    #[serde(default)]
+    pub curve: VolumeCurve,
+    /// Fade pause, resume and toggle over this many milliseconds.
+    #[serde(default)]
+    pub pause_fade_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeCurve {
+    #[default]
+    Linear,
+    /// Eases in and out.
+    Smooth,
+    /// Changes slowly near silence, which sounds more even.
+    Exponential,
 }
 
 impl Config {
+    /// `SPOT_CONFIG_DIR` overrides the platform's config directory.
     pub fn config_dir() -> Result<PathBuf> {
+        if let Some(dir) = std::env::var_os("SPOT_CONFIG_DIR") {
+            return Ok(PathBuf::from(dir));
+        }
         let dir = dirs::config_dir()
             .context("Could not determine config directory")?
             .join("spot");
         Ok(dir)
     }
 
+    /// `name` in the config directory.
+    pub fn state_path(name: &str) -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join(name))
+    }
+
     fn config_path() -> Result<PathBuf> {
         Ok(Self::config_dir()?.join("config.toml"))
     }
@@ -49,7 +215,198 @@ impl Config {
         Ok(())
     }
 
+    /// Looks up a named template from `[formats]`.
+    pub fn format(&self, name: &str) -> Option<&str> {
+        self.formats.as_ref()?.get(name).map(String::as_str)
+    }
+
+    pub fn history_settings(&self) -> HistorySettings {
+        self.history.clone().unwrap_or_default()
+    }
+
+    pub fn scrobble_settings(&self) -> ScrobbleSettings {
+        self.scrobble.clone().unwrap_or_default()
+    }
+
+    pub fn hooks_settings(&self) -> HooksSettings {
+        self.hooks.clone().unwrap_or_default()
+    }
+
+    pub fn notify_settings(&self) -> NotifySettings {
+        self.notify.clone().unwrap_or_default()
+    }
+
+    pub fn volume_settings(&self) -> VolumeSettings {
+        self.volume.clone().unwrap_or_default()
+    }
+
     pub fn has_credentials(&self) -> bool {
         self.client_id.is_some() && self.client_secret.is_some()
     }
 }
+
+/// Reads `name` from the config directory; `None` if it doesn't exist.
+pub fn load_json<T: DeserializeOwned>(name: &str) -> Result<Option<T>> {
+    let path = Config::state_path(name)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .map(Some)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Writes `value` as `name` in the config directory. See [`write_atomic`].
+pub fn save_json_atomic<T: Serialize + ?Sized>(name: &str, value: &T) -> Result<()> {
+    let contents = serde_json::to_string_pretty(value)?;
+    write_atomic(&Config::state_path(name)?, contents.as_bytes())
+}
+
+/// Writes `value` as `name` in the config directory unless it already
+/// exists, in which case it returns false and leaves the file alone. The
+/// file appears with its full contents, like [`write_atomic`].
+pub fn create_json_atomic<T: Serialize + ?Sized>(name: &str, value: &T) -> Result<bool> {
+    let path = Config::state_path(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, serde_json::to_string_pretty(value)?)
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    // Unlike a rename, a link fails when the target exists.
+    let linked = fs::hard_link(&tmp, &path);
+    let _ = fs::remove_file(&tmp);
+    match linked {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to write {}", path.display())),
+    }
+}
+
+/// Reads one value per line from `name` in the config directory, skipping
+/// lines that don't parse; empty if it doesn't exist.
+pub fn load_jsonl<T: DeserializeOwned>(name: &str) -> Result<Vec<T>> {
+    let path = Config::state_path(name)?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Replaces `name` in the config directory with one value per line. See
+/// [`write_atomic`].
+pub fn save_jsonl_atomic<T: Serialize>(name: &str, values: &[T]) -> Result<()> {
+    let mut contents = String::new();
+    for value in values {
+        contents.push_str(&serde_json::to_string(value)?);
+        contents.push('\n');
+    }
+    write_atomic(&Config::state_path(name)?, contents.as_bytes())
+}
+
+/// Opens (creating if need be) the lock file `name` in the config directory.
+pub fn open_lock(name: &str) -> Result<(fs::File, PathBuf)> {
+    let path = Config::state_path(name)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok((file, path))
+}
+
+/// Waits for the lock file `name`; the lock is held until the returned file
+/// is dropped.
+pub fn lock(name: &str) -> Result<fs::File> {
+    let (file, path) = open_lock(name)?;
+    file.lock()
+        .with_context(|| format!("Failed to lock {}", path.display()))?;
+    Ok(file)
+}
+
+/// Writes through a temporary file next to `path` and renames it over, so a
+/// crash or a concurrent reader never sees the file half-written. The file
+/// is owner-only, since some of them hold credentials.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    // A leftover from an earlier crash would keep its old permissions.
+    let _ = fs::remove_file(&tmp);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(contents))
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ConfigHome, env_lock};
+
+    #[tokio::test]
+    async fn json_state_round_trips_through_the_config_dir() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        assert_eq!(load_json::<Vec<u32>>("state.json").unwrap(), None);
+
+        save_json_atomic("state.json", &[1, 2, 3]).unwrap();
+        assert_eq!(load_json::<Vec<u32>>("state.json").unwrap(), Some(vec![1, 2, 3]));
+        let files: Vec<_> = fs::read_dir(&home.dir).unwrap().collect();
+        assert_eq!(files.len(), 1, "no temporary file left behind");
+        let mode = fs::metadata(home.dir.join("state.json")).unwrap().permissions();
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&mode) & 0o777, 0o600);
+
+        fs::write(home.dir.join("state.json"), "{").unwrap();
+        assert!(load_json::<Vec<u32>>("state.json").is_err());
+    }
+
+    #[tokio::test]
+    async fn creating_json_state_never_replaces_it() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        assert!(create_json_atomic("state.json", &[1]).unwrap());
+        assert!(!create_json_atomic("state.json", &[2]).unwrap());
+        assert_eq!(load_json::<Vec<u32>>("state.json").unwrap(), Some(vec![1]));
+        let files: Vec<_> = fs::read_dir(&home.dir).unwrap().collect();
+        assert_eq!(files.len(), 1, "no temporary file left behind");
+    }
+
+    #[tokio::test]
+    async fn jsonl_state_skips_lines_that_do_not_parse() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        assert!(load_jsonl::<u32>("log.jsonl").unwrap().is_empty());
+
+        save_jsonl_atomic("log.jsonl", &[1, 2]).unwrap();
+        let path = home.dir.join("log.jsonl");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1\n2\n");
+        fs::write(&path, "1\nnot json\n3\n").unwrap();
+        assert_eq!(load_jsonl::<u32>("log.jsonl").unwrap(), vec![1, 3]);
+    }
+}
@@ -0,0 +1,602 @@
+//! Optional long-running `spot daemon`. It keeps the player backend, the
+//! access tokens and the HTTP connection pool warm and serves requests as
+//! newline-delimited JSON over a Unix socket in `run/` in the config
+//! directory. Both are owner-only, since whoever can reach the socket
+//! controls playback.
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::config::Config;
+use crate::output::{self, ErrorKind, fail};
+use crate::spotify::api::{self, SearchPage, SearchResult, SearchType};
+use crate::spotify::auth;
+use crate::spotify::player::{Backend, NowPlayingInfo, Player};
+
+/// How long a now-playing snapshot is served from memory, so status bars can
+/// poll without hitting the player every time.
+const NOW_PLAYING_TTL: Duration = Duration::from_millis(500);
+
+/// Clients that don't finish their request in time are dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a client waits for an answer before going to the backend
+/// itself. Long enough for the desktop app to launch.
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    Ping,
+    PlayUri { uri: String },
+    PlayInContext { context: String, track: String },
+    GetContext,
+    Pause,
+    Resume,
+    Toggle,
+    NextTrack,
+    PrevTrack,
+    GetVolume,
+    SetVolume { volume: i32 },
+    Seek { position_ms: i64 },
+    GetShuffle,
+    SetShuffle { on: bool },
+    GetRepeat,
+    SetRepeat { on: bool },
+    NowPlaying,
+    Search {
+        query: String,
+        search_type: SearchType,
+        limit: u32,
+    },
+    SearchTypes {
+        query: String,
+        types: Vec<SearchType>,
+        limit: u32,
+        offset: u32,
+    },
+    Shutdown,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Response {
+    Ok { value: serde_json::Value },
+    Error { kind: ErrorKind, message: String },
+}
+
+/// In a directory of its own that only the owner can enter, so nobody else
+/// can connect even before the socket's own mode is set.
+pub fn socket_path() -> Result<PathBuf> {
+    Ok(Config::config_dir()?.join("run").join("daemon.sock"))
+}
+
+/// Connection details for a running daemon. Each call opens its own stream.
+pub struct Client {
+    path: PathBuf,
+    timeout: Duration,
+}
+
+impl Client {
+    /// Returns a client if the daemon's socket exists. A socket left by a
+    /// daemon that died only fails the calls, which then go direct.
+    pub fn connect() -> Option<Self> {
+        let path = socket_path().ok()?;
+        if !path.exists() {
+            return None;
+        }
+        Some(Self {
+            path,
+            timeout: CALL_TIMEOUT,
+        })
+    }
+
+    #[cfg(test)]
+    fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Connects and writes `request`. Until this succeeds the daemon can't
+    /// have acted on it.
+    async fn send(&self, request: &Request) -> Result<UnixStream> {
+        let mut stream = UnixStream::connect(&self.path)
+            .await
+            .context("Lost connection to spot daemon")?;
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        stream.write_all(line.as_bytes()).await?;
+        Ok(stream)
+    }
+
+    async fn receive<T: DeserializeOwned>(
+        &self,
+        stream: UnixStream,
+        deadline: tokio::time::Instant,
+    ) -> Result<T> {
+        let mut reply = String::new();
+        tokio::time::timeout_at(deadline, BufReader::new(stream).read_line(&mut reply))
+            .await
+            .map_err(|_| self.timed_out())??;
+        if reply.is_empty() {
+            bail!("spot daemon closed the connection without replying");
+        }
+        serde_json::from_str::<Response>(&reply)
+            .context("Invalid reply from spot daemon")?
+            .into_result()
+    }
+
+    fn timed_out(&self) -> anyhow::Error {
+        anyhow!("spot daemon did not answer within {}s", self.timeout.as_secs())
+    }
+
+    pub async fn call<T: DeserializeOwned>(&self, request: Request) -> Result<T> {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        let stream = tokio::time::timeout_at(deadline, self.send(&request))
+            .await
+            .map_err(|_| self.timed_out())??;
+        self.receive(stream, deadline).await
+    }
+
+    /// Like [`Client::call`], but `None` when the request never reached the
+    /// daemon, so the caller can use the backend itself. Once it has been
+    /// sent, a slow or broken reply is an error rather than a `None`: the
+    /// daemon may have acted on it, and running it again could skip twice.
+    pub async fn try_call<T: DeserializeOwned>(&self, request: Request) -> Option<Result<T>> {
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        match tokio::time::timeout_at(deadline, self.send(&request)).await {
+            Ok(Ok(stream)) => Some(self.receive(stream, deadline).await),
+            _ => None,
+        }
+    }
+}
+
+impl Response {
+    fn into_result<T: DeserializeOwned>(self) -> Result<T> {
+        match self {
+            Response::Ok { value } => {
+                serde_json::from_value(value).context("Unexpected reply from spot daemon")
+            }
+            Response::Error { kind, message } => Err(fail(kind, message)),
+        }
+    }
+}
+
+/// Searches through the daemon when it is running, otherwise directly.
+pub async fn search(
+    config: &Config,
+    query: &str,
+    search_type: SearchType,
+    limit: u32,
+) -> Result<Vec<SearchResult>> {
+    if let Some(client) = Client::connect() {
+        let request = Request::Search {
+            query: query.to_string(),
+            search_type,
+            limit,
+        };
+        if let Some(result) = client.try_call(request).await {
+            return result;
+        }
+    }
+    let token = auth::get_token(config).await?;
+    api::search(&token, query, search_type, limit).await
+}
+
+/// Like [`search`], for several types at once and from any offset.
+pub async fn search_types(
+    config: &Config,
+    query: &str,
+    types: &[SearchType],
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<SearchPage>> {
+    if let Some(client) = Client::connect() {
+        let request = Request::SearchTypes {
+            query: query.to_string(),
+            types: types.to_vec(),
+            limit,
+            offset,
+        };
+        if let Some(result) = client.try_call(request).await {
+            return result;
+        }
+    }
+    let token = auth::get_token(config).await?;
+    api::search_types(&token, query, types, limit, offset).await
+}
+
+impl Request {
+    /// Whether it changes playback, which makes the now-playing snapshot stale.
+    fn is_mutation(&self) -> bool {
+        match self {
+            Request::Ping
+            | Request::Shutdown
+            | Request::NowPlaying
+            | Request::GetVolume
+            | Request::GetShuffle
+            | Request::GetRepeat
+            | Request::GetContext
+            | Request::Search { .. }
+            | Request::SearchTypes { .. } => false,
+            Request::PlayUri { .. }
+            | Request::PlayInContext { .. }
+            | Request::Pause
+            | Request::Resume
+            | Request::Toggle
+            | Request::NextTrack
+            | Request::PrevTrack
+            | Request::SetVolume { .. }
+            | Request::Seek { .. }
+            | Request::SetShuffle { .. }
+            | Request::SetRepeat { .. } => true,
+        }
+    }
+}
+
+/// Shared by every connection. The caches are only locked between awaits.
+/// Connect keeps its own user token for playback; this one is for search.
+struct State {
+    config: Config,
+    player: Player,
+    token: auth::TokenCache,
+    now_playing: Mutex<Option<(NowPlayingInfo, Instant)>>,
+}
+
+impl State {
+    async fn token(&self) -> Result<String> {
+        self.token.get(|| auth::get_token_expiring(&self.config)).await
+    }
+
+    async fn now_playing(&self) -> Result<NowPlayingInfo> {
+        if let Some((info, fetched)) = &*self.now_playing.lock().unwrap() {
+            if fetched.elapsed() < NOW_PLAYING_TTL {
+                return Ok(info.clone());
+            }
+        }
+        let info = self.player.get_now_playing().await?;
+        *self.now_playing.lock().unwrap() = Some((info.clone(), Instant::now()));
+        Ok(info)
+    }
+
+    async fn handle(&self, request: Request) -> Result<serde_json::Value> {
+        use serde_json::{Value, to_value};
+
+        if request.is_mutation() {
+            *self.now_playing.lock().unwrap() = None;
+        }
+        let value = match request {
+            Request::Ping | Request::Shutdown => Value::Null,
+            Request::NowPlaying => to_value(self.now_playing().await?)?,
+            Request::GetVolume => to_value(self.player.get_volume().await?)?,
+            Request::GetShuffle => to_value(self.player.get_shuffle().await?)?,
+            Request::GetRepeat => to_value(self.player.get_repeat().await?)?,
+            Request::GetContext => to_value(self.player.get_context().await?)?,
+            Request::Search {
+                query,
+                search_type,
+                limit,
+            } => {
+                let token = self.token().await?;
+                to_value(api::search(&token, &query, search_type, limit).await?)?
+            }
+            Request::SearchTypes {
+                query,
+                types,
+                limit,
+                offset,
+            } => {
+                let token = self.token().await?;
+                to_value(api::search_types(&token, &query, &types, limit, offset).await?)?
+            }
+            Request::PlayUri { uri } => to_value(self.player.play_uri(&uri).await?)?,
+            Request::PlayInContext { context, track } => {
+                to_value(self.player.play_in_context(&context, &track).await?)?
+            }
+            Request::Pause => to_value(self.player.pause().await?)?,
+            Request::Resume => to_value(self.player.resume().await?)?,
+            Request::Toggle => to_value(self.player.toggle().await?)?,
+            Request::NextTrack => to_value(self.player.next_track().await?)?,
+            Request::PrevTrack => to_value(self.player.prev_track().await?)?,
+            Request::SetVolume { volume } => to_value(self.player.set_volume(volume).await?)?,
+            Request::Seek { position_ms } => to_value(self.player.seek(position_ms).await?)?,
+            Request::SetShuffle { on } => to_value(self.player.set_shuffle(on).await?)?,
+            Request::SetRepeat { on } => to_value(self.player.set_repeat(on).await?)?,
+        };
+        Ok(value)
+    }
+}
+
+/// Reads one request from `stream`, answers it and reports whether the
+/// daemon was asked to shut down.
+async fn serve_connection(state: Arc<State>, stream: UnixStream) -> Result<bool> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    tokio::time::timeout(REQUEST_TIMEOUT, BufReader::new(reader).read_line(&mut line))
+        .await
+        .context("Client timed out")??;
+    if line.is_empty() {
+        // A liveness probe, e.g. from a second `spot daemon`.
+        return Ok(false);
+    }
+
+    let (response, shutdown) = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => {
+            let shutdown = matches!(request, Request::Shutdown);
+            let response = match state.handle(request).await {
+                Ok(value) => Response::Ok { value },
+                Err(e) => Response::Error {
+                    kind: output::kind_of(&e),
+                    message: format!("{e:#}"),
+                },
+            };
+            (response, shutdown)
+        }
+        Err(e) => (
+            Response::Error {
+                kind: ErrorKind::Usage,
+                message: format!("Invalid request: {e}"),
+            },
+            false,
+        ),
+    };
+
+    let mut reply = serde_json::to_string(&response)?;
+    reply.push('\n');
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(shutdown)
+}
+
+/// Runs the daemon in the foreground until it is stopped or interrupted.
+pub async fn serve(backend: Backend, config: Config) -> Result<()> {
+    let path = socket_path()?;
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            bail!("spot daemon is already running ({})", path.display());
+        }
+        // Left behind by a daemon that didn't shut down cleanly.
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        // One that already existed keeps the mode it was made with.
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict {}", dir.display()))?;
+    }
+
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to restrict {}", path.display()))?;
+    let state = Arc::new(State {
+        player: Player::open(backend, &config).await?,
+        config,
+        token: auth::TokenCache::default(),
+        now_playing: Mutex::new(None),
+    });
+    // Each connection runs as its own task, and the backends only block on
+    // the blocking pool, so a slow one doesn't hold up the others; the
+    // first to ask for a shutdown wakes this loop.
+    let shutdown = Arc::new(tokio::sync::Notify::new());
+
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let result = loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => break Err(e.into()),
+                };
+                let (state, shutdown) = (state.clone(), shutdown.clone());
+                tokio::spawn(async move {
+                    match serve_connection(state, stream).await {
+                        Ok(true) => shutdown.notify_one(),
+                        Ok(false) => {}
+                        Err(e) => eprintln!("spot daemon: {e:#}"),
+                    }
+                });
+            }
+            _ = shutdown.notified() => break Ok(()),
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+            _ = terminate.recv() => break Ok(()),
+        }
+    };
+
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ConfigHome, PrivateBus, Stub, env_lock, route};
+
+    const STATE: &str = r#"{ "device": { "volume_percent": 40 }, "progress_ms": 0, "is_playing": false, "item": null }"#;
+
+    /// Waits for `serve` to start listening.
+    async fn connect() -> Client {
+        for _ in 0..100 {
+            if let Some(client) = Client::connect() {
+                return client;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the daemon never started listening");
+    }
+
+    #[tokio::test]
+    async fn answers_players_and_stops_when_asked() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let _stub = Stub::serve_api(vec![route("GET", "/me/player", 200, STATE)]).await;
+        std::env::set_var("SPOT_ACCESS_TOKEN", "test-token");
+
+        let daemon = tokio::spawn(serve(Backend::Connect, Config::default()));
+        let client = connect().await;
+        let mode = std::fs::metadata(socket_path().unwrap()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let dir = socket_path().unwrap().parent().unwrap().to_path_buf();
+        assert_eq!(std::fs::metadata(dir).unwrap().permissions().mode() & 0o777, 0o700);
+        let player = Player::from_config(None, &Config::default()).await.unwrap();
+        assert!(matches!(player, Player::Remote(_)));
+        assert_eq!(player.get_volume().await.unwrap(), 40);
+
+        // A client that never sends its request doesn't hold up the rest.
+        let _idle = UnixStream::connect(socket_path().unwrap()).await.unwrap();
+        let started = Instant::now();
+        client.call::<()>(Request::Ping).await.unwrap();
+        assert!(started.elapsed() < REQUEST_TIMEOUT);
+
+        client.call::<()>(Request::Shutdown).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), daemon)
+            .await
+            .expect("the daemon stops")
+            .unwrap()
+            .unwrap();
+        assert!(!socket_path().unwrap().exists());
+        assert!(Client::connect().is_none());
+    }
+
+    /// An MPRIS player whose play/pause takes a while to answer.
+    struct SlowPlayer;
+
+    #[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl SlowPlayer {
+        fn play_pause(&self) {
+            std::thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    #[tokio::test]
+    async fn a_slow_backend_call_doesnt_stall_other_clients() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let bus = PrivateBus::start();
+        let _player = zbus::blocking::connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name("org.mpris.MediaPlayer2.spotify")
+            .unwrap()
+            .serve_at("/org/mpris/MediaPlayer2", SlowPlayer)
+            .unwrap()
+            .build()
+            .unwrap();
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+
+        let daemon = tokio::spawn(serve(Backend::Mpris, Config::default()));
+        let client = connect().await;
+        let toggle = tokio::spawn(async {
+            let client = Client::connect().unwrap();
+            client.call::<()>(Request::Toggle).await
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let started = Instant::now();
+        client.call::<()>(Request::Ping).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500), "ping waited for the toggle");
+        assert!(!toggle.is_finished());
+        toggle.await.unwrap().unwrap();
+
+        client.call::<()>(Request::Shutdown).await.unwrap();
+        daemon.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_backend_errors_with_their_kind() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let _stub = Stub::serve_api(vec![route("PUT", "/me/player/pause", 404, "no device")]).await;
+        std::env::set_var("SPOT_ACCESS_TOKEN", "test-token");
+
+        let daemon = tokio::spawn(serve(Backend::Connect, Config::default()));
+        let client = connect().await;
+        let err = client.call::<()>(Request::Pause).await.unwrap_err();
+        assert_eq!(output::kind_of(&err), ErrorKind::NoDevice);
+
+        client.call::<()>(Request::Shutdown).await.unwrap();
+        daemon.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_hung_daemon_fails_the_call_rather_than_repeating_it() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let stub = Stub::serve_api(vec![route("POST", "/me/player/next", 204, "")]).await;
+        std::env::set_var("SPOT_ACCESS_TOKEN", "test-token");
+
+        // Accepts requests and never answers them.
+        let path = socket_path().unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let listener = UnixListener::bind(path).unwrap();
+        let hung = tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+        let client = Client::connect().unwrap().with_timeout(Duration::from_millis(200));
+        let player = Player::remote(client, Backend::Connect, &Config::default());
+        let err = player.next_track().await.unwrap_err();
+        assert!(err.to_string().contains("did not answer"), "{err}");
+        assert!(stub.requests().is_empty(), "the skip was sent twice");
+        hung.abort();
+    }
+
+    #[tokio::test]
+    async fn a_stale_socket_goes_direct() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let stub = Stub::serve_api(vec![route("GET", "/me/player", 200, STATE)]).await;
+        std::env::set_var("SPOT_ACCESS_TOKEN", "test-token");
+        let path = socket_path().unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        drop(UnixListener::bind(path).unwrap());
+
+        let config = Config {
+            backend: Some(Backend::Connect),
+            ..Config::default()
+        };
+        let player = Player::from_config(None, &config).await.unwrap();
+        assert!(matches!(player, Player::Remote(_)));
+        assert_eq!(player.get_volume().await.unwrap(), 40);
+        // Once it has gone direct it stays direct.
+        assert_eq!(player.get_volume().await.unwrap(), 40);
+        assert_eq!(stub.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn playback_skips_the_daemon_for_the_default_device_when_the_app_is_closed() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let bus = PrivateBus::start();
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &bus.address);
+        let path = socket_path().unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let _listener = UnixListener::bind(path).unwrap();
+
+        let config = Config {
+            backend: Some(Backend::Mpris),
+            default_device: Some("Kitchen".to_string()),
+            ..Config::default()
+        };
+        let player = Player::for_playback(None, &config).await.unwrap();
+        assert!(matches!(player, Player::Connect(_)));
+
+        let config = Config {
+            default_device: None,
+            ..config
+        };
+        let player = Player::for_playback(None, &config).await.unwrap();
+        assert!(matches!(player, Player::Remote(_)));
+    }
+}
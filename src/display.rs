@@ -1,7 +1,12 @@
+use anyhow::Result;
+use chrono::{Local, TimeZone};
 use console::Style;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{FuzzySelect, MultiSelect};
 
-use crate::spotify::api::{SearchResult, SearchType};
-use crate::spotify::applescript::NowPlayingInfo;
+use crate::output::{ErrorKind, fail};
+use crate::spotify::api::{SearchPage, SearchResult};
+use crate::spotify::player::NowPlayingInfo;
 
 pub fn format_duration(ms: i64) -> String {
     let total_secs = ms / 1000;
@@ -10,6 +15,109 @@ pub fn format_duration(ms: i64) -> String {
     format!("{mins}:{secs:02}")
 }
 
+/// Parses a position as `format_duration` prints it (`1:32`, also
+/// `1:02:03`), or anything `parse_duration` takes. Returns milliseconds.
+pub fn parse_timestamp(s: &str) -> Result<i64> {
+    let invalid = || {
+        fail(
+            ErrorKind::Usage,
+            format!("Invalid position \"{s}\"; use e.g. 1:32, 90s or 50%"),
+        )
+    };
+    if !s.contains(':') {
+        return i64::try_from(parse_duration(s)?.as_millis()).map_err(|_| invalid());
+    }
+    let parts = s
+        .split(':')
+        .map(|p| p.parse::<i64>().ok().filter(|&n| n >= 0))
+        .collect::<Option<Vec<_>>>();
+    let secs = match parts.as_deref() {
+        Some(&[m, sec]) if sec < 60 => m.checked_mul(60).and_then(|s| s.checked_add(sec)),
+        Some(&[h, m, sec]) if m < 60 && sec < 60 => h
+            .checked_mul(3600)
+            .and_then(|s| s.checked_add(m * 60 + sec)),
+        _ => None,
+    };
+    secs.and_then(|secs| secs.checked_mul(1000)).ok_or_else(invalid)
+}
+
+/// Parses `500ms`, `90s`, `30m`, `1h30m` or a bare number of seconds.
+pub fn parse_duration(s: &str) -> Result<std::time::Duration> {
+    let usage = || {
+        fail(
+            ErrorKind::Usage,
+            format!("Invalid duration \"{s}\"; use e.g. 500ms, 90s, 30m or 1h30m"),
+        )
+    };
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(std::time::Duration::from_secs(secs));
+    }
+    if s.is_empty() {
+        return Err(usage());
+    }
+    let mut total_ms: u64 = 0;
+    let mut number = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let n: u64 = number.parse().map_err(|_| usage())?;
+        let unit = match c {
+            'm' if chars.next_if_eq(&'s').is_some() => 1,
+            's' => 1000,
+            'm' => 60 * 1000,
+            'h' => 60 * 60 * 1000,
+            _ => return Err(usage()),
+        };
+        total_ms = n
+            .checked_mul(unit)
+            .and_then(|ms| total_ms.checked_add(ms))
+            .ok_or_else(usage)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(usage());
+    }
+    Ok(std::time::Duration::from_millis(total_ms))
+}
+
+/// Cuts `s` to at most `width` characters, marking the cut with an ellipsis.
+pub fn truncate(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut cut: String = s.chars().take(width - 1).collect();
+    cut.push('…');
+    cut
+}
+
+/// A listening total such as `3h 05m` or `42m`.
+pub fn format_total(ms: i64) -> String {
+    let mins = ms / 60_000;
+    if mins >= 60 {
+        format!("{}h {:02}m", mins / 60, mins % 60)
+    } else {
+        format!("{mins}m")
+    }
+}
+
+/// Local time of a Unix timestamp: just the time for today, else with the date.
+pub fn format_timestamp(secs: i64) -> String {
+    let Some(time) = Local.timestamp_opt(secs, 0).single() else {
+        return secs.to_string();
+    };
+    if time.date_naive() == Local::now().date_naive() {
+        time.format("%H:%M").to_string()
+    } else {
+        time.format("%a %d %b %H:%M").to_string()
+    }
+}
+
 pub fn progress_bar(position_ms: i64, duration_ms: i64, width: usize) -> String {
     if duration_ms == 0 {
         return " ".repeat(width);
@@ -23,12 +131,49 @@ pub fn progress_bar(position_ms: i64, duration_ms: i64, width: usize) -> String
     )
 }
 
+/// One block character per value, scaled to the largest.
+pub fn sparkline(values: &[i64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.iter().copied().max().unwrap_or(0).max(1);
+    values
+        .iter()
+        .map(|&v| {
+            if v <= 0 {
+                ' '
+            } else {
+                BARS[((v * 7) / max) as usize]
+            }
+        })
+        .collect()
+}
+
+/// Shades each value from blank to solid, scaled to the largest. Each cell
+/// is `width` characters wide.
+pub fn heatmap(values: &[i64], width: usize) -> String {
+    const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+    let max = values.iter().copied().max().unwrap_or(0).max(1);
+    values
+        .iter()
+        .map(|&v| {
+            let shade = if v <= 0 { 0 } else { 1 + ((v * 3) / max) as usize };
+            SHADES[shade].to_string().repeat(width)
+        })
+        .collect()
+}
+
 pub fn print_now_playing(info: &NowPlayingInfo) {
+    let (track, progress) = now_playing_lines(info);
+    println!("{track}");
+    println!("{progress}");
+}
+
+/// The track line and the progress line shown by `spot now`.
+pub fn now_playing_lines(info: &NowPlayingInfo) -> (String, String) {
     let green = Style::new().green().bold();
     let dim = Style::new().dim();
     let status = if info.is_playing { "▶" } else { "⏸" };
 
-    println!(
+    let track = format!(
         "{} {} {}",
         green.apply_to(status),
         green.apply_to(&info.track_name),
@@ -38,31 +183,146 @@ pub fn print_now_playing(info: &NowPlayingInfo) {
     let bar = progress_bar(info.position_ms, info.duration_ms, 30);
     let pos = format_duration(info.position_ms);
     let dur = format_duration(info.duration_ms);
-    println!(
+    let progress = format!(
         "  {} {} {}",
         dim.apply_to(&pos),
         bar,
         dim.apply_to(&dur),
     );
+
+    (track, progress)
 }
 
-pub fn print_search_results(results: &[SearchResult], search_type: SearchType) {
+/// Prints each type under its own heading with where the page falls among
+/// all matches, numbered straight through so `spot play '#N'` can refer to
+/// any line.
+pub fn print_search_pages(pages: &[SearchPage]) {
     let green = Style::new().green().bold();
     let dim = Style::new().dim();
 
-    if results.is_empty() {
-        println!("No {} found.", search_type.label().to_lowercase());
+    if pages.iter().all(|page| page.results.is_empty()) {
+        match pages {
+            [page] if page.offset == 0 => println!("No {} found.", page.search_type.label().to_lowercase()),
+            _ if pages.iter().any(|page| page.offset > 0) => println!("No more results."),
+            _ => println!("No results found."),
+        }
         return;
     }
 
-    println!("{}\n", green.apply_to(format!("  {}", search_type.label())));
-
-    for (i, result) in results.iter().enumerate() {
+    let mut number = 0;
+    for page in pages.iter().filter(|page| !page.results.is_empty()) {
+        if number > 0 {
+            println!();
+        }
+        let first = page.offset + 1;
+        let last = page.offset + page.results.len() as u32;
         println!(
-            "  {} {} {}",
-            dim.apply_to(format!("{:>2}.", i + 1)),
-            result.name,
-            dim.apply_to(format!("— {}", result.detail)),
+            "{} {}\n",
+            green.apply_to(format!("  {}", page.search_type.label())),
+            dim.apply_to(format!(" {first}–{last} of {}", page.total)),
         );
+        for result in &page.results {
+            number += 1;
+            println!(
+                "  {} {} {}",
+                dim.apply_to(format!("{number:>2}.")),
+                result.name,
+                dim.apply_to(format!("— {}", result.detail)),
+            );
+        }
+    }
+}
+
+fn ensure_terminal() -> Result<()> {
+    if !console::user_attended_stderr() {
+        return Err(fail(
+            ErrorKind::Usage,
+            "Interactive mode needs a terminal",
+        ));
+    }
+    Ok(())
+}
+
+/// Lets the user fuzzy-find one result. `None` when they press Esc.
+pub fn pick_result(results: &[SearchResult]) -> Result<Option<&SearchResult>> {
+    ensure_terminal()?;
+    let picked = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Play")
+        .items(results)
+        .default(0)
+        .interact_opt()?;
+    Ok(picked.map(|i| &results[i]))
+}
+
+/// Lets the user tick several results with space. Empty when they press Esc.
+pub fn pick_results(results: &[SearchResult]) -> Result<Vec<&SearchResult>> {
+    ensure_terminal()?;
+    let picked = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Queue (space to select, enter to confirm)")
+        .items(results)
+        .interact_opt()?;
+    Ok(picked
+        .unwrap_or_default()
+        .into_iter()
+        .map(|i| &results[i])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(90 * 60));
+        assert_eq!(parse_duration("2m5s250ms").unwrap(), Duration::from_millis(125_250));
+        assert_eq!(parse_duration("0s").unwrap(), Duration::ZERO);
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for bad in ["", "s", "1x", "1h30", "-5s", "1.5h", "m5", "5 m"] {
+            let err = parse_duration(bad).unwrap_err();
+            assert_eq!(crate::output::kind_of(&err), ErrorKind::Usage, "{bad}");
+        }
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("1:32").unwrap(), 92_000);
+        assert_eq!(parse_timestamp("0:05").unwrap(), 5_000);
+        assert_eq!(parse_timestamp("75:00").unwrap(), 4_500_000);
+        assert_eq!(parse_timestamp("1:02:03").unwrap(), 3_723_000);
+        assert_eq!(parse_timestamp("90s").unwrap(), 90_000);
+        assert_eq!(parse_timestamp("1m30s").unwrap(), 90_000);
+        assert_eq!(parse_timestamp("500ms").unwrap(), 500);
+    }
+
+    #[test]
+    fn rejects_malformed_timestamps() {
+        for bad in ["", ":", "1:60", "1:60:00", "1:-5", "a:10", "1:2:3:4", "1:32x"] {
+            let err = parse_timestamp(bad).unwrap_err();
+            assert_eq!(crate::output::kind_of(&err), ErrorKind::Usage, "{bad}");
+        }
+    }
+
+    #[test]
+    fn rejects_timestamps_that_overflow() {
+        for bad in ["9223372036854775807:00", "153722867280912931:00", "9999999999999999:00:00", "18446744073709551h"] {
+            let err = parse_timestamp(bad).unwrap_err();
+            assert_eq!(crate::output::kind_of(&err), ErrorKind::Usage, "{bad}");
+        }
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        assert!(parse_duration("99999999999999999h").is_err());
+        assert!(parse_duration("18446744073709551615s").is_err());
+        assert!(parse_duration("18446744073709551615ms1ms").is_err());
     }
 }
@@ -0,0 +1,457 @@
+//! Local listening history: one JSON object per line in `history.jsonl` in
+//! the config directory, appended by `spot history --record`. Appends and
+//! rewrites are serialised through `history.lock` next to it.
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::config::{self, Config};
+use crate::output::{self, ErrorKind, fail};
+use crate::spotify::auth::now_secs;
+use crate::spotify::player::{NowPlayingInfo, Player};
+
+/// How often expired entries are dropped while recording.
+const PRUNE_EVERY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// One listen of one track.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Play {
+    pub track_id: String,
+    pub track: String,
+    pub artist: String,
+    pub album: String,
+    /// Unix seconds when the track started.
+    pub started_at: i64,
+    /// Time actually spent playing, excluding pauses.
+    pub listened_ms: i64,
+    pub duration_ms: i64,
+}
+
+/// Which entries `spot history` lists, purges or aggregates.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Unix seconds, inclusive.
+    pub since: Option<i64>,
+    /// Unix seconds, exclusive.
+    pub until: Option<i64>,
+    /// Case-insensitive substring of the artist.
+    pub artist: Option<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, play: &Play) -> bool {
+        if self.since.is_some_and(|since| play.started_at < since) {
+            return false;
+        }
+        if self.until.is_some_and(|until| play.started_at >= until) {
+            return false;
+        }
+        match &self.artist {
+            Some(artist) => play.artist.to_lowercase().contains(&artist.to_lowercase()),
+            None => true,
+        }
+    }
+}
+
+const FILE: &str = "history.jsonl";
+const LOCK_FILE: &str = "history.lock";
+
+pub fn path() -> Result<PathBuf> {
+    Config::state_path(FILE)
+}
+
+/// All recorded plays, oldest first. Lines that fail to parse are skipped.
+pub fn load() -> Result<Vec<Play>> {
+    config::load_jsonl(FILE)
+}
+
+/// Held while the history is appended to or rewritten, so a purge can't
+/// replace it with a copy missing a play the recorder just wrote.
+fn lock() -> Result<File> {
+    config::lock(LOCK_FILE)
+}
+
+fn append(play: &Play) -> Result<()> {
+    let _lock = lock()?;
+    let dir = Config::config_dir()?;
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create config directory {}", dir.display()))?;
+    let path = path()?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open history at {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(play)?)
+        .with_context(|| format!("Failed to write history to {}", path.display()))?;
+    Ok(())
+}
+
+fn rewrite(plays: &[Play]) -> Result<()> {
+    config::save_jsonl_atomic(FILE, plays)
+}
+
+/// Deletes the entries matching `filter`, returning how many went.
+pub fn purge(filter: &Filter) -> Result<usize> {
+    let _lock = lock()?;
+    let plays = load()?;
+    let before = plays.len();
+    let kept: Vec<Play> = plays.into_iter().filter(|p| !filter.matches(p)).collect();
+    let removed = before - kept.len();
+    if removed > 0 {
+        rewrite(&kept)?;
+    }
+    Ok(removed)
+}
+
+/// Drops entries older than the configured retention.
+pub fn prune(config: &Config) -> Result<usize> {
+    match config.history_settings().retention_days {
+        Some(days) => purge(&Filter {
+            until: Some(now_secs() as i64 - i64::from(days) * 24 * 60 * 60),
+            ..Filter::default()
+        }),
+        None => Ok(0),
+    }
+}
+
+/// Parses `7d`, `12h`, `30m`, `2w` (ago) or a `YYYY-MM-DD` date (local
+/// midnight) into Unix seconds.
+pub fn parse_since(s: &str) -> Result<i64> {
+    let usage = || {
+        fail(
+            ErrorKind::Usage,
+            format!("Invalid time \"{s}\"; use e.g. 7d, 12h, 2w or 2026-01-31"),
+        )
+    };
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return local_midnight(date).ok_or_else(usage);
+    }
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(usage)?;
+    let (n, unit) = s.split_at(split);
+    let n: i64 = n.parse().map_err(|_| usage())?;
+    let secs: i64 = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(usage()),
+    };
+    n.checked_mul(secs)
+        .and_then(|ago| (now_secs() as i64).checked_sub(ago))
+        .ok_or_else(usage)
+}
+
+/// The plays started in calendar year `year`, local time.
+pub fn year(year: i32) -> Result<Filter> {
+    let start = |y| NaiveDate::from_ymd_opt(y, 1, 1).and_then(local_midnight);
+    match (start(year), start(year + 1)) {
+        (Some(since), Some(until)) => Ok(Filter {
+            since: Some(since),
+            until: Some(until),
+            ..Filter::default()
+        }),
+        _ => Err(fail(ErrorKind::Usage, format!("Invalid year {year}"))),
+    }
+}
+
+pub fn local_midnight(date: NaiveDate) -> Option<i64> {
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+        .map(|t| t.timestamp())
+}
+
+/// A listen in progress, fed by successive player polls.
+pub struct Listen {
+    pub info: NowPlayingInfo,
+    /// Unix seconds when the track started.
+    pub started_at: i64,
+    pub listened_ms: i64,
+    polled: Instant,
+}
+
+impl Listen {
+    pub fn start(info: NowPlayingInfo) -> Self {
+        Self {
+            started_at: now_secs() as i64 - info.position_ms / 1000,
+            info,
+            listened_ms: 0,
+            polled: Instant::now(),
+        }
+    }
+
+    /// Counts the time since the last poll if the track was playing then.
+    pub fn tick(&mut self) {
+        if self.info.is_playing {
+            self.listened_ms += self.polled.elapsed().as_millis() as i64;
+        }
+        self.polled = Instant::now();
+    }
+
+    pub fn finish(self) -> Play {
+        Play {
+            listened_ms: self.listened_ms.min(self.info.duration_ms.max(0)),
+            track_id: self.info.track_id,
+            track: self.info.track_name,
+            artist: self.info.artist,
+            album: self.info.album,
+            started_at: self.started_at,
+            duration_ms: self.info.duration_ms,
+        }
+    }
+}
+
+/// Writes a finished listen unless recording is paused in the config, which
+/// is re-read each time so `spot history --pause` applies immediately.
+fn save(listen: Listen) -> Result<()> {
+    let play = listen.finish();
+    if play.listened_ms <= 0 || Config::load()?.history_settings().paused {
+        return Ok(());
+    }
+    append(&play)
+}
+
+/// Feeds one poll to the listen in progress. Returns the listen it ends,
+/// when the track changed or playback stopped.
+fn advance(current: &mut Option<Listen>, polled: Option<NowPlayingInfo>) -> Option<Listen> {
+    match (current.as_mut(), polled) {
+        (Some(listen), Some(info)) if listen.info.track_id == info.track_id => {
+            listen.info = info;
+            None
+        }
+        (_, polled) => std::mem::replace(current, polled.map(Listen::start)),
+    }
+}
+
+/// Polls the player every `interval` and appends a [`Play`] whenever the
+/// track changes or playback stops. Runs until interrupted.
+pub async fn record(player: &Player, interval: Duration) -> Result<()> {
+    prune(&Config::load()?)?;
+    let mut pruned = Instant::now();
+    let mut ticker = tokio::time::interval(interval);
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let mut current: Option<Listen> = None;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+
+        if let Some(listen) = &mut current {
+            listen.tick();
+        }
+        let polled = match player.get_now_playing().await {
+            Ok(info) => Some(info),
+            Err(e) if output::kind_of(&e) == ErrorKind::NothingPlaying => None,
+            Err(e) => {
+                // A failed poll isn't the end of the track; try again next tick.
+                eprintln!("spot history: {e:#}");
+                continue;
+            }
+        };
+        // Like a failed poll, a failed write shouldn't end the recording.
+        if let Some(listen) = advance(&mut current, polled) {
+            if let Err(e) = save(listen) {
+                eprintln!("spot history: {e:#}");
+            }
+        }
+
+        if pruned.elapsed() >= PRUNE_EVERY {
+            if let Err(e) = Config::load().and_then(|config| prune(&config)) {
+                eprintln!("spot history: {e:#}");
+            }
+            pruned = Instant::now();
+        }
+    }
+
+    if let Some(mut listen) = current {
+        listen.tick();
+        save(listen)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ConfigHome, env_lock};
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn play(artist: &str, started_at: i64) -> Play {
+        Play {
+            track_id: format!("spotify:track:{started_at}"),
+            track: "Track".to_string(),
+            artist: artist.to_string(),
+            album: "Album".to_string(),
+            started_at,
+            listened_ms: 60_000,
+            duration_ms: 200_000,
+        }
+    }
+
+    fn info(track_id: &str, is_playing: bool) -> NowPlayingInfo {
+        NowPlayingInfo {
+            track_name: format!("Track {track_id}"),
+            artist: "Artist".to_string(),
+            album: "Album".to_string(),
+            track_id: track_id.to_string(),
+            duration_ms: 200_000,
+            position_ms: 5_000,
+            is_playing,
+        }
+    }
+
+    fn started_ats(plays: &[Play]) -> Vec<i64> {
+        plays.iter().map(|p| p.started_at).collect()
+    }
+
+    #[test]
+    fn parses_relative_times() {
+        let now = now_secs() as i64;
+        for (arg, ago) in [("30m", 30 * 60), ("12h", 12 * 3600), ("7d", 7 * DAY), ("2w", 14 * DAY)] {
+            let since = parse_since(arg).unwrap();
+            assert!((now - ago..=now - ago + 2).contains(&since), "{arg}");
+        }
+    }
+
+    #[test]
+    fn parses_dates_as_local_midnight() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 31).unwrap();
+        assert_eq!(parse_since("2026-01-31").unwrap(), local_midnight(date).unwrap());
+    }
+
+    #[test]
+    fn rejects_malformed_and_overflowing_times() {
+        for bad in ["", "7", "d", "7y", "-7d", "7 d", "2026-13-01", "999999999999999w", "99999999999999999999d"] {
+            let err = parse_since(bad).unwrap_err();
+            assert_eq!(output::kind_of(&err), ErrorKind::Usage, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn filters_by_time_and_artist() {
+        let filter = Filter {
+            since: Some(100),
+            until: Some(200),
+            artist: Some("miles".to_string()),
+        };
+        assert!(filter.matches(&play("Miles Davis", 100)), "since is inclusive");
+        assert!(filter.matches(&play("MILES DAVIS", 199)));
+        assert!(!filter.matches(&play("Miles Davis", 200)), "until is exclusive");
+        assert!(!filter.matches(&play("Miles Davis", 99)));
+        assert!(!filter.matches(&play("John Coltrane", 150)));
+        assert!(Filter::default().matches(&play("Anyone", 0)));
+    }
+
+    #[test]
+    fn years_run_from_local_new_year() {
+        let filter = year(2025).unwrap();
+        let start = |y| local_midnight(NaiveDate::from_ymd_opt(y, 1, 1).unwrap());
+        assert_eq!(filter.since, start(2025));
+        assert_eq!(filter.until, start(2026));
+    }
+
+    #[tokio::test]
+    async fn purges_only_matching_plays() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        for p in [play("Miles Davis", 100), play("John Coltrane", 200), play("Miles Davis", 300)] {
+            append(&p).unwrap();
+        }
+
+        let filter = Filter {
+            artist: Some("miles".to_string()),
+            ..Filter::default()
+        };
+        assert_eq!(purge(&filter).unwrap(), 2);
+        assert_eq!(started_ats(&load().unwrap()), [200]);
+        assert_eq!(purge(&filter).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn prunes_plays_past_the_retention() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        let now = now_secs() as i64;
+        for p in [play("A", now - 40 * DAY), play("B", now - 20 * DAY), play("C", now - DAY)] {
+            append(&p).unwrap();
+        }
+
+        assert_eq!(prune(&Config::default()).unwrap(), 0, "kept forever by default");
+        fs::write(home.dir.join("config.toml"), "[history]\nretention_days = 30\n").unwrap();
+        assert_eq!(prune(&Config::load().unwrap()).unwrap(), 1);
+        assert_eq!(started_ats(&load().unwrap()), [now - 20 * DAY, now - DAY]);
+    }
+
+    #[tokio::test]
+    async fn saves_listens_unless_paused_or_unheard() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        let mut listen = Listen::start(info("a", true));
+        listen.listened_ms = 90_000;
+        save(listen).unwrap();
+        save(Listen::start(info("b", true))).unwrap();
+        let plays = load().unwrap();
+        assert_eq!(plays.len(), 1, "nothing heard, nothing saved");
+        assert_eq!(plays[0].track_id, "a");
+        assert_eq!(plays[0].listened_ms, 90_000);
+        assert!((now_secs() as i64 - 5 - plays[0].started_at).abs() <= 2);
+
+        fs::write(home.dir.join("config.toml"), "[history]\npaused = true\n").unwrap();
+        let mut listen = Listen::start(info("c", true));
+        listen.listened_ms = 90_000;
+        save(listen).unwrap();
+        assert_eq!(load().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn appends_wait_for_a_purge_in_progress() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        append(&play("A", 100)).unwrap();
+
+        let held = lock().unwrap();
+        let appending = std::thread::spawn(|| append(&play("B", 200)));
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!appending.is_finished());
+        // What a purge would write back while holding the lock.
+        rewrite(&load().unwrap()).unwrap();
+        drop(held);
+        appending.join().unwrap().unwrap();
+        assert_eq!(started_ats(&load().unwrap()), [100, 200]);
+    }
+
+    #[test]
+    fn listens_end_when_the_track_changes_or_stops() {
+        let mut current = None;
+        assert!(advance(&mut current, Some(info("a", true))).is_none());
+        assert!(advance(&mut current, Some(info("a", false))).is_none());
+        assert!(!current.as_ref().unwrap().info.is_playing, "keeps the latest poll");
+
+        let ended = advance(&mut current, Some(info("b", true))).unwrap();
+        assert_eq!(ended.info.track_id, "a");
+        assert_eq!(current.as_ref().unwrap().info.track_id, "b");
+
+        let ended = advance(&mut current, None).unwrap();
+        assert_eq!(ended.info.track_id, "b");
+        assert!(current.is_none());
+        assert!(advance(&mut current, None).is_none());
+    }
+
+    #[test]
+    fn listened_time_never_exceeds_the_track() {
+        let mut listen = Listen::start(info("a", true));
+        listen.listened_ms = 500_000;
+        assert_eq!(listen.finish().listened_ms, 200_000);
+    }
+}
@@ -0,0 +1,451 @@
+//! User commands run on player events, configured under `[hooks]`.
+//! `spot hooks run` polls the player and diffs successive snapshots; each
+//! hook runs through `sh -c` in the background with the track in its
+//! environment and a JSON payload on stdin.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::{Config, HooksSettings};
+use crate::output::{self, ErrorKind, fail};
+use crate::spotify::player::{NowPlayingInfo, Player};
+
+/// Hooks still running after this long are killed.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    TrackChanged,
+    Paused,
+    Resumed,
+    VolumeChanged,
+    PlaybackStopped,
+}
+
+impl Event {
+    pub const ALL: [Event; 5] = [
+        Event::TrackChanged,
+        Event::Paused,
+        Event::Resumed,
+        Event::VolumeChanged,
+        Event::PlaybackStopped,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::TrackChanged => "track_changed",
+            Self::Paused => "paused",
+            Self::Resumed => "resumed",
+            Self::VolumeChanged => "volume_changed",
+            Self::PlaybackStopped => "playback_stopped",
+        }
+    }
+}
+
+impl HooksSettings {
+    pub fn command(&self, event: Event) -> Option<&str> {
+        match event {
+            Event::TrackChanged => self.track_changed.as_deref(),
+            Event::Paused => self.paused.as_deref(),
+            Event::Resumed => self.resumed.as_deref(),
+            Event::VolumeChanged => self.volume_changed.as_deref(),
+            Event::PlaybackStopped => self.playback_stopped.as_deref(),
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout_secs.map_or(DEFAULT_TIMEOUT, Duration::from_secs)
+    }
+}
+
+/// What one poll saw.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Snapshot {
+    pub now_playing: Option<NowPlayingInfo>,
+    pub volume: Option<i32>,
+}
+
+/// The events between two successive snapshots.
+pub fn diff(prev: &Snapshot, next: &Snapshot) -> Vec<Event> {
+    let mut events = Vec::new();
+    match (&prev.now_playing, &next.now_playing) {
+        (Some(_), None) => events.push(Event::PlaybackStopped),
+        (None, Some(_)) => events.push(Event::TrackChanged),
+        (Some(a), Some(b)) if a.track_id != b.track_id => events.push(Event::TrackChanged),
+        (Some(a), Some(b)) if a.is_playing && !b.is_playing => events.push(Event::Paused),
+        (Some(a), Some(b)) if !a.is_playing && b.is_playing => events.push(Event::Resumed),
+        _ => {}
+    }
+    if let (Some(a), Some(b)) = (prev.volume, next.volume) {
+        if a != b {
+            events.push(Event::VolumeChanged);
+        }
+    }
+    events
+}
+
+fn env_vars(event: Event, snapshot: &Snapshot) -> Vec<(&'static str, String)> {
+    let mut vars = vec![("SPOT_EVENT", event.as_str().to_string())];
+    if let Some(info) = &snapshot.now_playing {
+        vars.extend([
+            ("SPOT_TRACK", info.track_name.clone()),
+            ("SPOT_ARTIST", info.artist.clone()),
+            ("SPOT_ALBUM", info.album.clone()),
+            ("SPOT_TRACK_ID", info.track_id.clone()),
+            ("SPOT_DURATION_MS", info.duration_ms.to_string()),
+            ("SPOT_POSITION_MS", info.position_ms.to_string()),
+            ("SPOT_IS_PLAYING", info.is_playing.to_string()),
+        ]);
+    }
+    if let Some(volume) = snapshot.volume {
+        vars.push(("SPOT_VOLUME", volume.to_string()));
+    }
+    vars
+}
+
+/// Runs `command` for `event`, killing it after `timeout` along with
+/// anything it started. Failures are returned as a message for the log.
+pub async fn run_hook(
+    command: &str,
+    event: Event,
+    prev: &Snapshot,
+    next: &Snapshot,
+    timeout: Duration,
+) -> Result<(), String> {
+    let payload = json!({
+        "event": event,
+        "now_playing": next.now_playing,
+        "previous": prev.now_playing,
+        "volume": next.volume,
+    });
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env_vars(event, next))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        // Its own process group, so a timeout reaches what it backgrounds
+        // or pipes into too.
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to start: {e}"))?;
+    let group = child.id();
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that ignores stdin may exit before reading it.
+        let _ = stdin.write_all(payload.to_string().as_bytes()).await;
+    }
+
+    let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => output.map_err(|e| e.to_string())?,
+        Err(_) => {
+            if let Some(group) = group {
+                kill_group(group);
+            }
+            return Err(format!("timed out after {}s", timeout.as_secs()));
+        }
+    };
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(format!("{} {}", output.status, stderr.trim()).trim().to_string())
+}
+
+fn kill_group(group: u32) {
+    let _ = std::process::Command::new("kill")
+        .args(["-KILL", "--", &format!("-{group}")])
+        .stderr(Stdio::null())
+        .status();
+}
+
+/// Starts the hook for `event` in the background, if one is configured.
+fn fire(settings: &HooksSettings, event: Event, prev: &Snapshot, next: &Snapshot) {
+    let Some(command) = settings.command(event) else {
+        return;
+    };
+    let command = command.to_string();
+    let (prev, next) = (prev.clone(), next.clone());
+    let timeout = settings.timeout();
+    tokio::spawn(async move {
+        if let Err(e) = run_hook(&command, event, &prev, &next, timeout).await {
+            eprintln!("spot hooks: {}: {e}", event.as_str());
+        }
+    });
+}
+
+/// Reads the player. Only the backend saying nothing is playing counts as
+/// stopped; any other failure is returned, so a passing hiccup doesn't look
+/// like playback stopping and then the same track starting again.
+async fn snapshot(player: &Player, with_volume: bool) -> Result<Snapshot> {
+    let now_playing = match player.get_now_playing().await {
+        Ok(info) => Some(info),
+        Err(e) if output::kind_of(&e) == ErrorKind::NothingPlaying => None,
+        Err(e) => return Err(e),
+    };
+    Ok(Snapshot {
+        now_playing,
+        volume: if with_volume {
+            player.get_volume().await.ok()
+        } else {
+            None
+        },
+    })
+}
+
+/// Polls the player every `interval` and fires hooks on transitions until
+/// interrupted. The first poll only sets the baseline.
+pub async fn watch(player: &Player, config: &Config, interval: Duration) -> Result<()> {
+    let settings = config.hooks_settings();
+    if Event::ALL.iter().all(|&e| settings.command(e).is_none()) {
+        return Err(fail(
+            ErrorKind::Config,
+            "No hooks configured. Add commands under [hooks] in config.toml",
+        ));
+    }
+    // Reading the volume costs a call per poll, so only when it's hooked.
+    let with_volume = settings.volume_changed.is_some();
+
+    let mut ticker = tokio::time::interval(interval);
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let mut prev: Option<Snapshot> = None;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+        let next = match snapshot(player, with_volume).await {
+            Ok(next) => next,
+            Err(e) => {
+                // Compare the next good poll against the last good one.
+                eprintln!("spot hooks: skipping a poll: {e:#}");
+                continue;
+            }
+        };
+        if let Some(prev) = &prev {
+            for event in diff(prev, &next) {
+                fire(&settings, event, prev, &next);
+            }
+        }
+        prev = Some(next);
+    }
+    Ok(())
+}
+
+/// Runs the hook for `event` once against the current player state.
+pub async fn test(player: &Player, config: &Config, event: Event) -> Result<()> {
+    let settings = config.hooks_settings();
+    let command = settings.command(event).ok_or_else(|| {
+        fail(
+            ErrorKind::Config,
+            format!("No hook configured for {}", event.as_str()),
+        )
+    })?;
+    let next = snapshot(player, true).await?;
+    run_hook(command, event, &Snapshot::default(), &next, settings.timeout())
+        .await
+        .map_err(|e| fail(ErrorKind::Other, format!("{} hook failed: {e}", event.as_str())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spotify::connect::Connect;
+    use crate::test_support::{Stub, env_lock, route};
+
+    fn playing(track_id: &str, is_playing: bool, volume: i32) -> Snapshot {
+        Snapshot {
+            now_playing: Some(NowPlayingInfo {
+                track_name: format!("Track {track_id}"),
+                artist: "Artist".to_string(),
+                album: "Album".to_string(),
+                track_id: format!("spotify:track:{track_id}"),
+                duration_ms: 200_000,
+                position_ms: 1_000,
+                is_playing,
+            }),
+            volume: Some(volume),
+        }
+    }
+
+    fn stopped() -> Snapshot {
+        Snapshot::default()
+    }
+
+    #[test]
+    fn nothing_changed() {
+        assert_eq!(diff(&playing("a", true, 50), &playing("a", true, 50)), vec![]);
+        assert_eq!(diff(&stopped(), &stopped()), vec![]);
+    }
+
+    #[test]
+    fn track_changes() {
+        let events = diff(&playing("a", true, 50), &playing("b", true, 50));
+        assert_eq!(events, vec![Event::TrackChanged]);
+        let events = diff(&stopped(), &playing("a", true, 50));
+        assert_eq!(events, vec![Event::TrackChanged]);
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let events = diff(&playing("a", true, 50), &playing("a", false, 50));
+        assert_eq!(events, vec![Event::Paused]);
+        let events = diff(&playing("a", false, 50), &playing("a", true, 50));
+        assert_eq!(events, vec![Event::Resumed]);
+    }
+
+    #[test]
+    fn volume_changes() {
+        let events = diff(&playing("a", true, 50), &playing("a", true, 40));
+        assert_eq!(events, vec![Event::VolumeChanged]);
+    }
+
+    #[test]
+    fn unknown_volume_is_not_a_change() {
+        let mut next = playing("a", true, 50);
+        next.volume = None;
+        assert_eq!(diff(&playing("a", true, 50), &next), vec![]);
+        assert_eq!(diff(&next, &playing("a", true, 70)), vec![]);
+    }
+
+    #[test]
+    fn playback_stops() {
+        let events = diff(&playing("a", true, 50), &stopped());
+        assert_eq!(events, vec![Event::PlaybackStopped]);
+    }
+
+    #[test]
+    fn simultaneous_changes() {
+        let events = diff(&playing("a", true, 50), &playing("a", false, 30));
+        assert_eq!(events, vec![Event::Paused, Event::VolumeChanged]);
+        let events = diff(&playing("a", false, 50), &playing("b", true, 60));
+        assert_eq!(events, vec![Event::TrackChanged, Event::VolumeChanged]);
+        // A new track that starts paused is a track change, not a pause.
+        let events = diff(&playing("a", true, 50), &playing("b", false, 50));
+        assert_eq!(events, vec![Event::TrackChanged]);
+    }
+
+    /// A fresh file path for a hook to write to.
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("spot-hooks-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("out")
+    }
+
+    #[tokio::test]
+    async fn hooks_get_the_track_in_their_environment() {
+        let out = scratch("env");
+        let command = format!(
+            "printf '%s\\n' \"$SPOT_EVENT\" \"$SPOT_TRACK\" \"$SPOT_ARTIST\" \"$SPOT_ALBUM\" \
+             \"$SPOT_TRACK_ID\" \"$SPOT_DURATION_MS\" \"$SPOT_POSITION_MS\" \"$SPOT_IS_PLAYING\" \
+             \"$SPOT_VOLUME\" > '{}'",
+            out.display()
+        );
+        let next = playing("b", false, 35);
+        run_hook(&command, Event::Paused, &stopped(), &next, DEFAULT_TIMEOUT)
+            .await
+            .unwrap();
+        let lines = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(
+            lines.lines().collect::<Vec<_>>(),
+            [
+                "paused",
+                "Track b",
+                "Artist",
+                "Album",
+                "spotify:track:b",
+                "200000",
+                "1000",
+                "false",
+                "35",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn hooks_without_a_track_only_get_the_event() {
+        let out = scratch("stopped");
+        let command = format!(
+            "printf '%s|%s|%s' \"$SPOT_EVENT\" \"${{SPOT_TRACK-unset}}\" \"${{SPOT_VOLUME-unset}}\" > '{}'",
+            out.display()
+        );
+        run_hook(&command, Event::PlaybackStopped, &playing("a", true, 50), &stopped(), DEFAULT_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "playback_stopped|unset|unset");
+    }
+
+    #[tokio::test]
+    async fn hooks_get_a_json_payload_on_stdin() {
+        let out = scratch("stdin");
+        let command = format!("cat > '{}'", out.display());
+        let (prev, next) = (playing("a", true, 50), playing("b", true, 60));
+        run_hook(&command, Event::TrackChanged, &prev, &next, DEFAULT_TIMEOUT)
+            .await
+            .unwrap();
+        let payload: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(payload["event"], "track_changed");
+        assert_eq!(payload["now_playing"]["track_id"], "spotify:track:b");
+        assert_eq!(payload["previous"]["track_id"], "spotify:track:a");
+        assert_eq!(payload["volume"], 60);
+    }
+
+    #[tokio::test]
+    async fn failures_report_the_status_and_stderr() {
+        let err = run_hook("echo oops >&2; exit 3", Event::Resumed, &stopped(), &stopped(), DEFAULT_TIMEOUT)
+            .await
+            .unwrap_err();
+        assert_eq!(err, "exit status: 3 oops");
+    }
+
+    #[tokio::test]
+    async fn slow_hooks_are_killed_with_what_they_started() {
+        let (shell, background) = (scratch("slow-shell"), scratch("slow-background"));
+        // Each file appears only if the process that writes it survived.
+        let command = format!(
+            "(sleep 2; touch '{}') & sleep 2; touch '{}'",
+            background.display(),
+            shell.display()
+        );
+        let started = std::time::Instant::now();
+        let err = run_hook(&command, Event::Resumed, &stopped(), &stopped(), Duration::from_secs(1))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "timed out after 1s");
+        let deadline = started + Duration::from_secs(3);
+        tokio::time::sleep_until(deadline.into()).await;
+        assert!(!shell.exists(), "the hook kept running after its timeout");
+        assert!(!background.exists(), "its background job kept running");
+    }
+
+    #[tokio::test]
+    async fn only_nothing_playing_counts_as_stopped() {
+        let _env = env_lock().await;
+        let _stub = Stub::serve_api(vec![
+            route("GET", "/me/player/currently-playing", 204, ""),
+            route("GET", "/me/player", 500, "oops"),
+        ])
+        .await;
+        std::env::set_var("SPOT_ACCESS_TOKEN", "test-token");
+        let player = Player::Connect(Box::new(Connect::new(Config::default())));
+        let snap = snapshot(&player, false).await.unwrap();
+        assert!(snap.now_playing.is_none());
+
+        let _stub = Stub::serve_api(vec![route("GET", "/me/player", 502, "down")]).await;
+        let player = Player::Connect(Box::new(Connect::new(Config::default())));
+        assert!(snapshot(&player, false).await.is_err());
+    }
+}
@@ -1,17 +1,43 @@
 mod commands;
 mod config;
+mod daemon;
 mod display;
+mod history;
+mod hooks;
+mod notify;
+mod output;
+mod playback;
+mod schedule;
+mod scrobble;
+mod snapshot;
 mod spotify;
+mod template;
+#[cfg(test)]
+mod test_support;
+mod undo;
+mod volume;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
-use commands::play::PlayMode;
+use playback::PlayMode;
+use config::Config;
+use output::{ErrorKind, Output, fail};
 use spotify::api::SearchType;
+use spotify::player::{Backend, Player};
+use spotify::query::Filters;
 
 #[derive(Parser)]
-#[command(name = "spot", about = "Spotify CLI for macOS", version)]
+#[command(name = "spot", about = "Spotify CLI for macOS and Linux", version)]
 struct Cli {
+    /// Playback backend to use for this invocation
+    #[arg(long, global = true, value_enum, env = "SPOT_BACKEND")]
+    backend: Option<Backend>,
+
+    /// Output format
+    #[arg(short, long, global = true, value_enum, default_value_t, env = "SPOT_OUTPUT")]
+    output: Output,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -25,28 +51,99 @@ enum Command {
 
         /// Search albums
         #[arg(short = 'a', long)]
-        album: bool,
+        albums: bool,
 
         /// Search artists
         #[arg(short = 'r', long)]
-        artist: bool,
+        artists: bool,
 
         /// Search playlists
         #[arg(short = 'p', long)]
         playlist: bool,
+
+        /// Search tracks, albums, artists and playlists at once
+        #[arg(long, conflicts_with_all = ["albums", "artists", "playlist", "types"])]
+        all: bool,
+
+        /// Types to search, e.g. track,album; --limit applies to each
+        #[arg(short = 't', long = "type", value_delimiter = ',', conflicts_with_all = ["albums", "artists", "playlist"])]
+        types: Vec<SearchType>,
+
+        /// Pick a result in a fuzzy finder and play it
+        #[arg(short, long, conflicts_with_all = ["all", "types"])]
+        interactive: bool,
+
+        /// Pick several tracks and add them to the queue
+        #[arg(short, long, conflicts_with_all = ["albums", "artists", "playlist", "interactive", "all", "types"])]
+        queue: bool,
+
+        /// Results to show, per type [default: 20, or 5 per type with several]
+        #[arg(short = 'n', long, value_parser = clap::value_parser!(u32).range(1..=1000))]
+        limit: Option<u32>,
+
+        /// Skip this many results first
+        #[arg(long, default_value_t = 0, conflicts_with = "page")]
+        offset: u32,
+
+        /// Show page N, counting from 1, of --limit results each
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        page: Option<u32>,
+
+        /// Show the page after the last search's
+        #[arg(long, conflicts_with_all = ["query", "albums", "artists", "playlist", "all", "types", "interactive", "queue", "limit", "offset", "page", "artist", "album", "track", "year", "genre", "isrc", "upc", "tag", "exclude"])]
+        next: bool,
+
+        #[command(flatten)]
+        filters: Filters,
+
+        /// Show the query sent to Spotify
+        #[arg(short, long)]
+        verbose: bool,
     },
 
     /// Show currently playing track
-    Now,
+    Now {
+        /// Keep the view open and update it live (space, n/p, +/-, q)
+        #[arg(short, long)]
+        watch: bool,
+
+        /// Seconds between player polls in watch mode
+        #[arg(long, default_value_t = 5, requires = "watch")]
+        interval: u64,
+
+        /// Plain-text template, e.g. '{artist} – {track} [{position}/{duration}]',
+        /// or @name for a template from [formats] in config.toml
+        #[arg(short, long, conflicts_with = "watch")]
+        format: Option<String>,
+    },
+
+    /// Full-screen player with search, results and now playing
+    Tui {
+        /// Seconds between player polls
+        #[arg(long, default_value_t = 5)]
+        interval: u64,
+    },
 
     /// Pause playback
-    Pause,
+    Pause {
+        /// Fade out first, e.g. 2s; [volume] pause_fade_ms in config.toml
+        #[arg(long)]
+        fade: Option<String>,
+    },
 
     /// Resume playback
-    Resume,
+    Resume {
+        /// Fade in, e.g. 2s; [volume] pause_fade_ms in config.toml
+        #[arg(long)]
+        fade: Option<String>,
+    },
 
     /// Toggle play/pause
-    Toggle,
+    Toggle {
+        /// Fade out or in, e.g. 2s; [volume] pause_fade_ms in config.toml
+        #[arg(long)]
+        fade: Option<String>,
+    },
 
     /// Skip to next track
     Next,
@@ -54,12 +151,41 @@ enum Command {
     /// Skip to previous track
     Prev,
 
-    /// Get or set volume (0-100)
+    /// Get or set volume: 30, +10, -5, up, down
     Vol {
-        /// Volume level (0-100)
-        level: Option<u32>,
+        /// Volume level (0-100), or a change like +10 or -5
+        #[arg(allow_hyphen_values = true)]
+        level: Option<String>,
+
+        /// Ramp to the new level over this long, e.g. 5s
+        #[arg(long, requires = "level")]
+        over: Option<String>,
+    },
+
+    /// Turn the volume down to 0, remembering the level
+    Mute {
+        /// Fade out over this long, e.g. 2s
+        #[arg(long)]
+        over: Option<String>,
     },
 
+    /// Return to the level before spot mute
+    Unmute {
+        /// Fade in over this long, e.g. 2s
+        #[arg(long)]
+        over: Option<String>,
+    },
+
+    /// Jump within the track: 1:32, +15s, -30, 50%
+    Seek {
+        /// Position, offset (+/-) or percentage
+        #[arg(allow_hyphen_values = true)]
+        position: String,
+    },
+
+    /// Jump to the start of the track
+    Restart,
+
     /// Get or set shuffle mode
     Shuffle {
         /// on/off
@@ -77,6 +203,233 @@ enum Command {
         /// Check credential status
         #[arg(long)]
         status: bool,
+
+        #[command(subcommand)]
+        action: Option<AuthAction>,
+    },
+
+    /// Run a background daemon that other spot commands use when available
+    Daemon {
+        #[command(subcommand)]
+        action: Option<DaemonAction>,
+    },
+
+    /// Show, record and manage local listening history
+    #[command(group(clap::ArgGroup::new("action").multiple(false)))]
+    History {
+        /// Record plays until interrupted
+        #[arg(long, group = "action")]
+        record: bool,
+
+        /// Seconds between player polls while recording
+        #[arg(long, default_value_t = 5, requires = "record")]
+        interval: u64,
+
+        /// Only plays since 7d, 12h, 2w or a date like 2026-01-31
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only plays by artists matching this
+        #[arg(long)]
+        artist: Option<String>,
+
+        /// Number of plays to list
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// Play entry N of the list again (1 = most recent)
+        #[arg(long, value_name = "N", group = "action")]
+        replay: Option<usize>,
+
+        /// Stop recording new plays
+        #[arg(long, group = "action")]
+        pause: bool,
+
+        /// Record new plays again
+        #[arg(long, group = "action")]
+        resume: bool,
+
+        /// Delete the plays matching --since/--artist (or --all of them)
+        #[arg(long, group = "action")]
+        purge: bool,
+
+        /// With --purge, delete the whole history
+        #[arg(long, requires = "purge", conflicts_with_all = ["since", "artist"])]
+        all: bool,
+
+        /// Keep plays for this many days (0 keeps them forever)
+        #[arg(long, value_name = "DAYS", group = "action")]
+        retention: Option<u32>,
+    },
+
+    /// Listening statistics from the local history
+    Stats {
+        /// Only plays since 7d, 12h, 2w or a date like 2026-01-31
+        #[arg(long, conflicts_with = "year")]
+        since: Option<String>,
+
+        /// Only plays in this calendar year
+        #[arg(long)]
+        year: Option<i32>,
+
+        /// Entries per top list
+        #[arg(short = 'n', long, default_value_t = 5)]
+        top: usize,
+    },
+
+    /// Scrobble to ListenBrainz and Last.fm while running
+    Scrobble {
+        /// Seconds between player polls
+        #[arg(long, default_value_t = 5)]
+        interval: u64,
+
+        #[command(subcommand)]
+        action: Option<ScrobbleAction>,
+    },
+
+    /// Run commands from [hooks] in config.toml on player events
+    Hooks {
+        #[command(subcommand)]
+        action: HooksAction,
+    },
+
+    /// Show a desktop notification on every track change
+    Notify {
+        /// Seconds between player polls
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+
+        /// Notify about the current track once and exit
+        #[arg(long)]
+        once: bool,
+    },
+
+    /// Fade out and pause after a while (spot sleep 30m, --after 3 tracks)
+    #[command(group(clap::ArgGroup::new("until").multiple(false)))]
+    Sleep {
+        /// How long until playback stops: 90s, 30m, 1h30m
+        #[arg(conflicts_with_all = ["end_of_album", "status", "cancel"])]
+        duration: Option<String>,
+
+        /// Stop when the current album ends
+        #[arg(long, group = "until")]
+        end_of_album: bool,
+
+        /// Stop after this many tracks, counting the one playing
+        #[arg(long, value_name = "N", group = "until")]
+        after: Option<u32>,
+
+        /// Fade length, e.g. 45s; [sleep] fade_secs in config.toml or 30s
+        #[arg(long, conflicts_with_all = ["status", "cancel"])]
+        fade: Option<String>,
+
+        /// Show the running timer
+        #[arg(long, group = "until")]
+        status: bool,
+
+        /// Stop the running timer and put the volume back
+        #[arg(long, group = "until")]
+        cancel: bool,
+    },
+
+    /// Schedule playback (spot at 07:30 --weekdays "morning focus")
+    #[command(group(clap::ArgGroup::new("repeat").multiple(false)))]
+    #[command(group(clap::ArgGroup::new("manage").multiple(false).conflicts_with("time")))]
+    At {
+        /// Time of day, 24-hour HH:MM
+        time: Option<String>,
+
+        /// What to play: a search, an alias or a spotify: URI
+        query: Vec<String>,
+
+        /// Play an album
+        #[arg(short = 'a', long)]
+        album: bool,
+
+        /// Play a song
+        #[arg(short = 's', long)]
+        song: bool,
+
+        /// Play a playlist
+        #[arg(short = 'p', long)]
+        playlist: bool,
+
+        /// Every day
+        #[arg(long, group = "repeat")]
+        daily: bool,
+
+        /// Monday to Friday
+        #[arg(long, group = "repeat")]
+        weekdays: bool,
+
+        /// Saturday and Sunday
+        #[arg(long, group = "repeat")]
+        weekends: bool,
+
+        /// These days, e.g. mon,wed,fri
+        #[arg(long, group = "repeat")]
+        days: Option<String>,
+
+        /// Volume to play at (0-100)
+        #[arg(long)]
+        volume: Option<u32>,
+
+        /// Fade in from silence over this long, e.g. 5m
+        #[arg(long)]
+        ramp: Option<String>,
+
+        /// Show scheduled playback
+        #[arg(long, group = "manage")]
+        list: bool,
+
+        /// Remove a scheduled playback by id
+        #[arg(long, value_name = "ID", group = "manage")]
+        rm: Option<u32>,
+
+        /// Play scheduled items as they come due
+        #[arg(long, group = "manage")]
+        serve: bool,
+    },
+
+    /// Save the playback state under a name (spot save lunch)
+    #[command(group(clap::ArgGroup::new("manage").multiple(false).conflicts_with("name")))]
+    Save {
+        /// Name to save as
+        name: Option<String>,
+
+        /// Show saved states
+        #[arg(long, group = "manage")]
+        list: bool,
+
+        /// Delete a saved state
+        #[arg(long, value_name = "NAME", group = "manage")]
+        rm: Option<String>,
+    },
+
+    /// Put back a state from spot save: track, position, volume, shuffle, repeat
+    Restore {
+        /// Name it was saved as
+        name: String,
+    },
+
+    /// Go back to what was playing before the last play command
+    #[command(visible_alias = "back")]
+    Undo {
+        /// Show what undo would go back through, newest first
+        #[arg(long)]
+        list: bool,
+    },
+
+    /// Show the playback queue, or add to it with spot queue add
+    Queue {
+        #[command(subcommand)]
+        action: Option<QueueAction>,
+    },
+
+    /// List Spotify Connect devices or move playback to one
+    Device {
+        #[command(subcommand)]
+        action: DeviceAction,
     },
 
     /// Manage aliases (e.g. spot alias white-album "The Beatles")
@@ -89,12 +442,27 @@ enum Command {
     Play {
         /// Play as album
         #[arg(short = 'a')]
-        album: bool,
+        as_album: bool,
 
         /// Play as song/track
         #[arg(short = 's')]
         song: bool,
 
+        /// Pick from the search results instead of playing the top one
+        #[arg(short, long)]
+        interactive: bool,
+
+        /// Results to pick from with -i [default: 20]
+        #[arg(short = 'n', long, requires = "interactive", value_parser = clap::value_parser!(u32).range(1..=1000))]
+        limit: Option<u32>,
+
+        #[command(flatten)]
+        filters: Filters,
+
+        /// Show the query sent to Spotify
+        #[arg(short, long)]
+        verbose: bool,
+
         /// Search query
         query: Vec<String>,
     },
@@ -104,6 +472,85 @@ enum Command {
     External(Vec<String>),
 }
 
+#[derive(Subcommand)]
+enum AuthAction {
+    /// Log in with your Spotify account (needed for playback and library access)
+    Login,
+    /// Forget the stored Spotify login
+    Logout,
+}
+
+#[derive(Subcommand)]
+enum DaemonAction {
+    /// Stop the running daemon
+    Stop,
+    /// Check whether the daemon is running
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ScrobbleAction {
+    /// Show configured services and queued listens
+    Status,
+    /// Send queued listens now
+    Flush,
+    /// Log in to Last.fm
+    Login,
+}
+
+#[derive(Subcommand)]
+enum QueueAction {
+    /// Queue the top track for a search (spot queue add -a <album> for a whole album)
+    Add {
+        /// Search query
+        #[arg(required = true)]
+        query: Vec<String>,
+
+        /// Queue every track of the top album
+        #[arg(short = 'a', long)]
+        album: bool,
+
+        /// Pick the result in a fuzzy finder
+        #[arg(short, long)]
+        interactive: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum DeviceAction {
+    /// List devices with type, volume and which is active
+    Ls,
+    /// Move playback to a device (spot device use desk)
+    Use {
+        /// Device name (matched loosely) or id
+        #[arg(required = true)]
+        name: Vec<String>,
+
+        /// Start playing on it
+        #[arg(long)]
+        play: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum HooksAction {
+    /// Watch the player and run hooks until interrupted
+    Run {
+        /// Seconds between player polls
+        // Shorter than the 5 of the watchers that only follow tracks: a pause
+        // and resume, or a volume nudge and back, between two polls is lost.
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+    },
+    /// List configured hooks
+    Ls,
+    /// Run one hook now against the current track
+    Test {
+        #[arg(value_enum)]
+        event: hooks::Event,
+    },
+}
+
 #[derive(Subcommand)]
 enum AliasAction {
     /// Add an alias: spot alias add <name> <query>
@@ -117,6 +564,17 @@ enum AliasAction {
     Ls,
 }
 
+/// Applies `--artist`, `--year` and friends to the free-text query,
+/// printing the result to stderr with `--verbose`.
+fn compile_query(text: &str, filters: &Filters, verbose: bool) -> Result<String> {
+    let query = filters.compile(text)?;
+    if verbose {
+        let dim = console::Style::new().dim();
+        eprintln!("  {} {query}", dim.apply_to("query:"));
+    }
+    Ok(query)
+}
+
 fn resolve_search_type(album: bool, artist: bool, playlist: bool) -> SearchType {
     if album {
         SearchType::Album
@@ -133,7 +591,7 @@ fn preprocess_args() -> Vec<String> {
     let mut args: Vec<String> = std::env::args().collect();
     if args.len() > 1 {
         match args[1].as_str() {
-            "-a" | "-s" => {
+            "-a" | "-s" | "-i" => {
                 args.insert(1, "play".to_string());
             }
             _ => {}
@@ -142,72 +600,432 @@ fn preprocess_args() -> Vec<String> {
     args
 }
 
+/// Whether the raw arguments ask for JSON, for errors clap raises before
+/// `Cli` exists.
+fn wants_json(args: &[String]) -> bool {
+    let flag = args.iter().enumerate().any(|(i, arg)| {
+        arg == "--output=json"
+            || arg == "-ojson"
+            || ((arg == "--output" || arg == "-o") && args.get(i + 1).map(String::as_str) == Some("json"))
+    });
+    flag || std::env::var("SPOT_OUTPUT").as_deref() == Ok("json")
+}
+
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
-    let cli = Cli::parse_from(preprocess_args());
+async fn main() {
+    let args = preprocess_args();
+    let cli = match Cli::try_parse_from(&args) {
+        Ok(cli) => cli,
+        Err(e) if e.use_stderr() && wants_json(&args) => {
+            let rendered = e.render().to_string();
+            let message = rendered.lines().next().unwrap_or_default();
+            output::print_error(&fail(
+                ErrorKind::Usage,
+                message.trim_start_matches("error: "),
+            ));
+            std::process::exit(2);
+        }
+        Err(e) => e.exit(),
+    };
+
+    let out = cli.output;
+    if let Err(e) = run(cli).await {
+        if out.is_json() {
+            output::print_error(&e);
+        } else {
+            eprintln!("Error: {e:?}");
+        }
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let out = cli.output;
+    let backend = cli.backend;
+    let player = || async move { Player::from_config(backend, &Config::load()?).await };
+    // Commands that start playback may switch to `default_device`.
+    let play_player = || async move { Player::for_playback(backend, &Config::load()?).await };
 
     match cli.command {
         Command::Play {
-            album,
+            as_album,
             song,
+            interactive,
+            limit,
+            filters,
+            verbose,
             query,
         } => {
-            let mode = if album {
+            let mode = if as_album {
                 PlayMode::Album
             } else if song {
                 PlayMode::Track
             } else {
                 PlayMode::Artist
             };
-            let query_str = query.join(" ");
+            let query_str = compile_query(&query.join(" "), &filters, verbose)?;
             if query_str.is_empty() {
-                commands::controls::resume()?;
+                commands::controls::resume(&play_player().await?, None, out).await?;
             } else {
-                commands::play::run(&query_str, mode).await?;
+                commands::play::run(&play_player().await?, &query_str, mode, interactive, limit, out).await?;
             }
         }
         Command::External(args) => {
             let query = args.join(" ");
             if query.is_empty() {
-                commands::controls::resume()?;
+                commands::controls::resume(&play_player().await?, None, out).await?;
             } else {
-                commands::play::run(&query, PlayMode::Artist).await?;
+                commands::play::run(&play_player().await?, &query, PlayMode::Artist, false, None, out).await?;
             }
         }
 
         Command::Search {
             query,
-            album,
-            artist,
+            albums,
+            artists,
             playlist,
+            all,
+            types,
+            interactive,
+            queue,
+            limit,
+            offset,
+            page,
+            next,
+            filters,
+            verbose,
         } => {
-            let search_type = resolve_search_type(album, artist, playlist);
-            let query_str = query.join(" ");
+            if next {
+                return commands::search::next(out).await;
+            }
+            // `--album`/`--artist` used to pick the section; refuse the old
+            // usage rather than quietly running a different search.
+            if !(albums || artists || playlist || all || !types.is_empty()) {
+                if let Some(flag) = filters.lone_name_filter() {
+                    return Err(fail(
+                        ErrorKind::Usage,
+                        format!(
+                            "--{flag} now filters by {flag} name. To search {flag}s use --{flag}s; \
+                             to filter tracks, add -t track"
+                        ),
+                    ));
+                }
+            }
+            let search_type = resolve_search_type(albums, artists, playlist);
+            let query_str = compile_query(&query.join(" "), &filters, verbose)?;
             if query_str.is_empty() {
-                anyhow::bail!("Search query is required. Usage: spot search <query>");
+                return Err(fail(
+                    ErrorKind::Usage,
+                    "Search query is required. Usage: spot search <query>",
+                ));
+            }
+            let types = if all {
+                SearchType::ALL.to_vec()
+            } else if types.is_empty() {
+                vec![search_type]
+            } else {
+                types
+            };
+            let limit = limit.unwrap_or_else(|| commands::search::Window::default_limit(&types));
+            let window = commands::search::Window {
+                limit,
+                offset: page.map_or(offset, |page| (page - 1).saturating_mul(limit)),
+            };
+            if queue {
+                commands::search::queue(&query_str, search_type, window, out).await?;
+            } else if interactive {
+                commands::search::pick(&play_player().await?, &query_str, search_type, window, out).await?;
+            } else {
+                commands::search::run(&query_str, &types, window, out).await?;
             }
-            commands::search::run(&query_str, search_type).await?;
         }
 
-        Command::Now => commands::now::run()?,
-        Command::Pause => commands::controls::pause()?,
-        Command::Resume => commands::controls::resume()?,
-        Command::Toggle => commands::controls::toggle()?,
-        Command::Next => commands::controls::next()?,
-        Command::Prev => commands::controls::prev()?,
-        Command::Vol { level } => commands::controls::volume(level)?,
-        Command::Shuffle { state } => commands::controls::shuffle(state)?,
-        Command::Repeat { state } => commands::controls::repeat(state)?,
-        Command::Auth { status } => commands::auth::run(status).await?,
+        Command::Now {
+            watch,
+            interval,
+            format,
+        } => {
+            let player = player().await?;
+            if watch {
+                let interval = std::time::Duration::from_secs(interval.max(1));
+                commands::now::watch(&player, interval).await?;
+            } else {
+                commands::now::run(&player, format.as_deref(), out).await?;
+            }
+        }
+        Command::Tui { interval } => {
+            let interval = std::time::Duration::from_secs(interval.max(1));
+            commands::tui::run(&play_player().await?, interval).await?;
+        }
+        Command::Pause { fade } => {
+            let fade = fade.as_deref().map(display::parse_duration).transpose()?;
+            commands::controls::pause(&player().await?, fade, out).await?;
+        }
+        Command::Resume { fade } => {
+            let fade = fade.as_deref().map(display::parse_duration).transpose()?;
+            commands::controls::resume(&player().await?, fade, out).await?;
+        }
+        Command::Toggle { fade } => {
+            let fade = fade.as_deref().map(display::parse_duration).transpose()?;
+            commands::controls::toggle(&player().await?, fade, out).await?;
+        }
+        Command::Next => commands::controls::next(&player().await?, out).await?,
+        Command::Prev => commands::controls::prev(&player().await?, out).await?,
+        Command::Vol { level, over } => {
+            let over = over.as_deref().map(display::parse_duration).transpose()?;
+            commands::controls::volume(&player().await?, level, over, out).await?;
+        }
+        Command::Mute { over } => {
+            let over = over.as_deref().map(display::parse_duration).transpose()?;
+            commands::controls::mute(&player().await?, over, out).await?;
+        }
+        Command::Unmute { over } => {
+            let over = over.as_deref().map(display::parse_duration).transpose()?;
+            commands::controls::unmute(&player().await?, over, out).await?;
+        }
+        Command::Seek { position } => {
+            commands::controls::seek(&player().await?, &position, out).await?;
+        }
+        Command::Restart => commands::controls::restart(&player().await?, out).await?,
+        Command::Shuffle { state } => commands::controls::shuffle(&player().await?, state, out).await?,
+        Command::Repeat { state } => commands::controls::repeat(&player().await?, state, out).await?,
+        Command::Auth { status, action } => match action {
+            Some(AuthAction::Login) => commands::auth::login(out).await?,
+            Some(AuthAction::Logout) => commands::auth::logout(out)?,
+            None => commands::auth::run(status, out).await?,
+        },
+        Command::Daemon { action } => match action {
+            Some(DaemonAction::Stop) => commands::daemon::stop(out).await?,
+            Some(DaemonAction::Status) => commands::daemon::status(out).await?,
+            None => commands::daemon::run(backend).await?,
+        },
+        Command::History {
+            record,
+            interval,
+            since,
+            artist,
+            limit,
+            replay,
+            pause,
+            resume,
+            purge,
+            all,
+            retention,
+        } => {
+            let filter = history::Filter {
+                since: since.as_deref().map(history::parse_since).transpose()?,
+                artist,
+                ..Default::default()
+            };
+            if record {
+                let interval = std::time::Duration::from_secs(interval.max(1));
+                commands::history::record(&player().await?, interval, out).await?;
+            } else if let Some(index) = replay {
+                commands::history::replay(&play_player().await?, &filter, index, out).await?;
+            } else if pause || resume {
+                commands::history::set_paused(pause, out)?;
+            } else if purge {
+                if filter.since.is_none() && filter.artist.is_none() && !all {
+                    return Err(fail(
+                        ErrorKind::Usage,
+                        "Narrow --purge with --since or --artist, or pass --all to delete the whole history",
+                    ));
+                }
+                commands::history::purge(&filter, out)?;
+            } else if let Some(days) = retention {
+                commands::history::set_retention(days, out)?;
+            } else {
+                commands::history::list(&filter, limit, out)?;
+            }
+        }
+        Command::Stats { since, year, top } => {
+            let (filter, period) = match (since, year) {
+                (Some(since), _) => (
+                    history::Filter {
+                        since: Some(history::parse_since(&since)?),
+                        ..Default::default()
+                    },
+                    format!("since {since}"),
+                ),
+                (None, Some(year)) => (history::year(year)?, year.to_string()),
+                (None, None) => (history::Filter::default(), "all time".to_string()),
+            };
+            commands::stats::run(&filter, &period, top, out)?;
+        }
+        Command::Scrobble { interval, action } => match action {
+            Some(ScrobbleAction::Status) => commands::scrobble::status(out)?,
+            Some(ScrobbleAction::Flush) => commands::scrobble::flush(out).await?,
+            Some(ScrobbleAction::Login) => commands::scrobble::login(out).await?,
+            None => {
+                let interval = std::time::Duration::from_secs(interval.max(1));
+                commands::scrobble::run(&player().await?, interval, out).await?;
+            }
+        },
+        Command::Hooks { action } => match action {
+            HooksAction::Run { interval } => {
+                let interval = std::time::Duration::from_secs(interval.max(1));
+                commands::hooks::run(&player().await?, interval, out).await?;
+            }
+            HooksAction::Ls => commands::hooks::list(out)?,
+            HooksAction::Test { event } => {
+                commands::hooks::test(&player().await?, event, out).await?;
+            }
+        },
+        Command::Notify { interval, once } => {
+            let interval = std::time::Duration::from_secs(interval.max(1));
+            commands::notify::run(&player().await?, interval, once, out).await?;
+        }
+        Command::Sleep {
+            duration,
+            end_of_album,
+            after,
+            fade,
+            status,
+            cancel,
+        } => {
+            if status {
+                commands::sleep::status(out)?;
+            } else if cancel {
+                commands::sleep::cancel(out)?;
+            } else {
+                let timer = match (duration, after) {
+                    // `--after 3 tracks` reads naturally; the unit is optional.
+                    (Some(unit), Some(_)) if unit == "track" || unit == "tracks" => None,
+                    (Some(_), Some(_)) => {
+                        return Err(fail(ErrorKind::Usage, "Usage: spot sleep --after N [tracks]"));
+                    }
+                    (Some(duration), None) => Some(display::parse_duration(&duration)?),
+                    (None, _) => None,
+                };
+                if timer.is_none() && after.is_none() && !end_of_album {
+                    return Err(fail(
+                        ErrorKind::Usage,
+                        "Usage: spot sleep <30m | --end-of-album | --after N>",
+                    ));
+                }
+                let fade = fade.as_deref().map(display::parse_duration).transpose()?;
+                let mut fade = commands::sleep::fade_duration(fade, &Config::load()?);
+                // `spot sleep 10s` fades over those 10s, not the default 30.
+                if let Some(timer) = timer {
+                    fade = fade.min(timer);
+                }
+                let player = player().await?;
+                let until = match (timer, after) {
+                    (Some(timer), _) => commands::sleep::Until::Time {
+                        ends_at: spotify::auth::now_secs().saturating_add(timer.as_secs()),
+                    },
+                    (None, Some(after)) => commands::sleep::Until::Tracks {
+                        remaining: after.max(1),
+                    },
+                    (None, None) => commands::sleep::Until::EndOfAlbum {
+                        album: player.get_now_playing().await?.album,
+                    },
+                };
+                commands::sleep::run(&player, until, fade, out).await?;
+            }
+        }
+        Command::At {
+            time,
+            query,
+            album,
+            song,
+            playlist,
+            daily,
+            weekdays,
+            weekends,
+            days,
+            volume,
+            ramp,
+            list,
+            rm,
+            serve,
+        } => {
+            let clock = schedule::SystemClock;
+            if list {
+                commands::at::list(&clock, out)?;
+            } else if let Some(id) = rm {
+                commands::at::remove(id, out)?;
+            } else if serve {
+                commands::at::serve(&play_player().await?, &clock, out).await?;
+            } else {
+                let query = query.join(" ");
+                let Some(time) = time.filter(|_| !query.is_empty()) else {
+                    return Err(fail(
+                        ErrorKind::Usage,
+                        "Usage: spot at <HH:MM> <query> (or --list, --rm <id>, --serve)",
+                    ));
+                };
+                use chrono::Weekday::*;
+                let days = if daily {
+                    vec![Mon, Tue, Wed, Thu, Fri, Sat, Sun]
+                } else if weekdays {
+                    vec![Mon, Tue, Wed, Thu, Fri]
+                } else if weekends {
+                    vec![Sat, Sun]
+                } else {
+                    days.as_deref().map(schedule::parse_days).transpose()?.unwrap_or_default()
+                };
+                let mode = if album {
+                    PlayMode::Album
+                } else if song {
+                    PlayMode::Track
+                } else if playlist {
+                    PlayMode::Playlist
+                } else {
+                    PlayMode::Artist
+                };
+                let request = commands::at::Request {
+                    time: schedule::parse_time(&time)?,
+                    days,
+                    query,
+                    mode,
+                    volume: volume.map(|v| v.min(100) as i32),
+                    ramp: ramp.as_deref().map(display::parse_duration).transpose()?,
+                };
+                commands::at::add(request, &clock, out)?;
+            }
+        }
+        Command::Save { name, list, rm } => match (name, rm) {
+            (_, Some(name)) => commands::snapshot::remove(&name, out)?,
+            (Some(name), None) => commands::snapshot::save(&player().await?, &name, out).await?,
+            (None, None) if list => commands::snapshot::list(out)?,
+            (None, None) => {
+                return Err(fail(ErrorKind::Usage, "Usage: spot save <name> (or --list, --rm <name>)"));
+            }
+        },
+        Command::Restore { name } => {
+            commands::snapshot::restore(&play_player().await?, &name, out).await?;
+        }
+        Command::Undo { list } => {
+            if list {
+                commands::undo::list(out)?;
+            } else {
+                commands::undo::run(&play_player().await?, out).await?;
+            }
+        }
+        Command::Queue { action } => match action {
+            Some(QueueAction::Add {
+                query,
+                album,
+                interactive,
+            }) => commands::queue::add(&query.join(" "), album, interactive, out).await?,
+            None => commands::queue::show(out).await?,
+        },
+        Command::Device { action } => match action {
+            DeviceAction::Ls => commands::device::list(out).await?,
+            DeviceAction::Use { name, play } => {
+                commands::device::use_device(&name.join(" "), play, out).await?;
+            }
+        },
         Command::Alias { action } => match action {
             AliasAction::Add { name, query } => {
-                commands::alias::add(&name, &query.join(" "))?;
+                commands::alias::add(&name, &query.join(" "), out)?;
             }
             AliasAction::Rm { name } => {
-                commands::alias::remove(&name)?;
+                commands::alias::remove(&name, out)?;
             }
             AliasAction::Ls => {
-                commands::alias::list()?;
+                commands::alias::list(out)?;
             }
         },
     }
@@ -0,0 +1,161 @@
+//! Desktop notifications: the freedesktop Notifications D-Bus interface on
+//! Linux, `display notification` through AppleScript on macOS.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+
+use crate::config::{self, Config};
+use crate::spotify::{api, applescript, auth};
+
+const BUS_NAME: &str = "org.freedesktop.Notifications";
+const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+
+pub struct Notification {
+    pub summary: String,
+    pub body: String,
+    /// Album art on disk. Not shown on macOS.
+    pub image: Option<PathBuf>,
+}
+
+/// Posts notifications, replacing the previous one where the platform
+/// allows so a busy playlist doesn't stack them up.
+pub struct Notifier {
+    connection: Option<Connection>,
+    last_id: u32,
+}
+
+impl Notifier {
+    /// Connects to the session bus at `DBUS_SESSION_BUS_ADDRESS` on Linux,
+    /// so a stub notification server on a private bus works for testing.
+    pub fn new() -> Result<Self> {
+        let connection = if cfg!(target_os = "macos") {
+            None
+        } else {
+            Some(Connection::session().context("Failed to connect to the D-Bus session bus")?)
+        };
+        Ok(Self {
+            connection,
+            last_id: 0,
+        })
+    }
+
+    /// `timeout_ms` of -1 leaves the expiry to the notification server.
+    pub fn show(&mut self, notification: &Notification, timeout_ms: i32) -> Result<()> {
+        let Some(connection) = &self.connection else {
+            return applescript::display_notification(&notification.summary, &notification.body);
+        };
+
+        let icon = notification
+            .image
+            .as_deref()
+            .map(|p| format!("file://{}", p.display()))
+            .unwrap_or_default();
+        let mut hints: HashMap<&str, Value> = HashMap::new();
+        hints.insert("category", Value::from("x-spot.track"));
+        if let Some(image) = &notification.image {
+            hints.insert("image-path", Value::from(image.display().to_string()));
+        }
+
+        let reply = connection
+            .call_method(
+                Some(BUS_NAME),
+                OBJECT_PATH,
+                Some(BUS_NAME),
+                "Notify",
+                &(
+                    "spot",
+                    self.last_id,
+                    icon.as_str(),
+                    notification.summary.as_str(),
+                    notification.body.as_str(),
+                    Vec::<&str>::new(),
+                    hints,
+                    timeout_ms,
+                ),
+            )
+            .context("Failed to send notification")?;
+        self.last_id = reply
+            .body()
+            .deserialize()
+            .context("Unexpected reply from the notification server")?;
+        Ok(())
+    }
+}
+
+fn art_dir() -> Result<PathBuf> {
+    Ok(Config::config_dir()?.join("art"))
+}
+
+/// Downloads the cover for `track_uri` into the config directory, once per
+/// album, and returns its path.
+pub async fn album_art(config: &Config, track_uri: &str) -> Result<Option<PathBuf>> {
+    let token = auth::get_token(config).await?;
+    let Some((album_id, url)) = api::album_art(&token, track_uri).await? else {
+        return Ok(None);
+    };
+    let dir = art_dir()?;
+    let path = dir.join(format!("{album_id}.jpg"));
+    if path.exists() {
+        return Ok(Some(path));
+    }
+
+    let bytes = api::client()
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .context("Failed to download album art")?
+        .bytes()
+        .await
+        .context("Failed to download album art")?;
+    config::write_atomic(&path, &bytes)?;
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{NotificationServer, env_lock};
+
+    #[tokio::test]
+    async fn posts_to_the_session_bus_and_replaces_the_last_notification() {
+        let _env = env_lock().await;
+        let server = NotificationServer::start();
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &server.bus.address);
+        let mut notifier = Notifier::new().unwrap();
+
+        let first = Notification {
+            summary: "So What".to_string(),
+            body: "Miles Davis — Kind of Blue".to_string(),
+            image: Some(PathBuf::from("/tmp/art/abc.jpg")),
+        };
+        notifier.show(&first, 5000).unwrap();
+        let second = Notification {
+            summary: "Freddie Freeloader".to_string(),
+            body: "Miles Davis — Kind of Blue".to_string(),
+            image: None,
+        };
+        notifier.show(&second, -1).unwrap();
+
+        let calls = server.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].app_name, "spot");
+        assert_eq!(calls[0].replaces_id, 0);
+        assert_eq!(calls[0].summary, "So What");
+        assert_eq!(calls[0].body, "Miles Davis — Kind of Blue");
+        assert_eq!(calls[0].app_icon, "file:///tmp/art/abc.jpg");
+        assert_eq!(calls[0].hints["image-path"], "/tmp/art/abc.jpg");
+        assert_eq!(calls[0].hints["category"], "x-spot.track");
+        assert_eq!(calls[0].expire_timeout, 5000);
+
+        // The server answered 41; the next track replaces it.
+        assert_eq!(calls[1].replaces_id, 41);
+        assert_eq!(calls[1].summary, "Freddie Freeloader");
+        assert_eq!(calls[1].app_icon, "");
+        assert!(!calls[1].hints.contains_key("image-path"));
+        assert_eq!(calls[1].expire_timeout, -1);
+    }
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+/// How commands print their results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Output {
+    /// Styled text for people
+    #[default]
+    Pretty,
+    /// Unstyled text, one record per line
+    Plain,
+    /// A stable JSON document
+    Json,
+}
+
+impl Output {
+    pub fn is_json(self) -> bool {
+        self == Self::Json
+    }
+}
+
+/// Machine-readable error categories reported by `--output json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Usage,
+    Config,
+    Auth,
+    NotFound,
+    NothingPlaying,
+    NoDevice,
+    Player,
+    Api,
+    Network,
+    Io,
+    Other,
+}
+
+/// An error tagged with its [`ErrorKind`].
+#[derive(Debug)]
+pub struct Failure {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Builds an `anyhow::Error` that `--output json` reports with `kind`.
+pub fn fail(kind: ErrorKind, message: impl Into<String>) -> anyhow::Error {
+    Failure {
+        kind,
+        message: message.into(),
+    }
+    .into()
+}
+
+/// Classifies an error, preferring an explicit [`Failure`] anywhere in its chain.
+pub fn kind_of(err: &anyhow::Error) -> ErrorKind {
+    for cause in err.chain() {
+        if let Some(failure) = cause.downcast_ref::<Failure>() {
+            return failure.kind;
+        }
+    }
+    for cause in err.chain() {
+        if cause.is::<reqwest::Error>() {
+            return ErrorKind::Network;
+        }
+        if cause.is::<zbus::Error>() {
+            return ErrorKind::Player;
+        }
+        if cause.is::<std::io::Error>() {
+            return ErrorKind::Io;
+        }
+        if cause.is::<toml::de::Error>() {
+            return ErrorKind::Config;
+        }
+    }
+    ErrorKind::Other
+}
+
+pub fn print_json<T: Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("Failed to serialize output: {e}"),
+    }
+}
+
+pub fn print_error(err: &anyhow::Error) {
+    print_json(&serde_json::json!({
+        "error": {
+            "kind": kind_of(err),
+            "message": format!("{err:#}"),
+        }
+    }));
+}
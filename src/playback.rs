@@ -0,0 +1,104 @@
+//! Starting playback from a search: what `spot play` does without a picker,
+//! and what scheduled playback runs when an alarm goes off.
+
+use anyhow::Result;
+use console::Style;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::config::Config;
+use crate::daemon;
+use crate::output::{self, ErrorKind, Output, fail};
+use crate::spotify::api::{self, SearchResult, SearchType};
+use crate::spotify::player::Player;
+use crate::undo;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayMode {
+    Track,
+    Album,
+    Artist,
+    Playlist,
+}
+
+impl PlayMode {
+    pub fn search_type(self) -> SearchType {
+        match self {
+            PlayMode::Track => SearchType::Track,
+            PlayMode::Album => SearchType::Album,
+            PlayMode::Artist => SearchType::Artist,
+            PlayMode::Playlist => SearchType::Playlist,
+        }
+    }
+}
+
+/// `query`, or what it stands for when it's an alias from `config.toml`.
+pub fn resolve_alias<'a>(config: &'a Config, query: &'a str) -> &'a str {
+    config
+        .aliases
+        .as_ref()
+        .and_then(|a| a.get(query))
+        .map_or(query, String::as_str)
+}
+
+pub fn not_found(query: &str, search_type: SearchType) -> anyhow::Error {
+    fail(
+        ErrorKind::NotFound,
+        format!("No {} found for \"{query}\"", search_type.as_str()),
+    )
+}
+
+/// Plays the best match for `query`, an alias or a search of `mode`'s type.
+pub async fn play_query(player: &Player, query: &str, mode: PlayMode, out: Output) -> Result<()> {
+    let config = Config::load()?;
+    let query = resolve_alias(&config, query);
+    let search_type = mode.search_type();
+    let results = daemon::search(&config, query, search_type, 5).await?;
+    let result = results.first().ok_or_else(|| not_found(query, search_type))?;
+    play_result(player, result, search_type, out).await
+}
+
+/// Starts `result`, returning whether repeat was turned on. Albums loop so
+/// the record keeps playing. What was playing goes on the undo stack.
+pub async fn start(player: &Player, result: &SearchResult, search_type: SearchType) -> Result<bool> {
+    undo::remember(player).await;
+    let repeat = search_type == SearchType::Album;
+    player.play_uri(&result.uri).await?;
+    player.set_repeat(repeat).await?;
+    Ok(repeat)
+}
+
+pub async fn play_result(
+    player: &Player,
+    result: &SearchResult,
+    search_type: SearchType,
+    out: Output,
+) -> Result<()> {
+    let repeat = start(player, result, search_type).await?;
+    print_playing(result, repeat, out);
+    Ok(())
+}
+
+fn print_playing(result: &api::SearchResult, repeat: bool, out: Output) {
+    match out {
+        Output::Json => output::print_json(&json!({
+            "playing": result,
+            "repeat": repeat,
+        })),
+        Output::Plain => println!("{}\t{}\t{}", result.uri, result.name, result.detail),
+        Output::Pretty => {
+            let green = Style::new().green().bold();
+            let dim = Style::new().dim();
+            println!(
+                "  {} {} {}",
+                green.apply_to("▶"),
+                green.apply_to(&result.name),
+                dim.apply_to(format!("— {}", result.detail)),
+            );
+            if repeat {
+                println!("  {} Repeat on", dim.apply_to("🔁"));
+            }
+        }
+    }
+}
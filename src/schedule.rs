@@ -0,0 +1,430 @@
+//! Scheduled playback for `spot at`. Alarms live in `schedule.json` in the
+//! config directory; `spot at --serve` re-reads it every tick and plays
+//! whatever came due since the last one. Times come from a [`Clock`] so
+//! tests can pick them.
+
+use anyhow::Result;
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::config::{self, Config};
+use crate::output::{ErrorKind, Output, fail};
+use crate::playback::{self, PlayMode};
+use crate::spotify::player::Player;
+use crate::undo;
+use crate::volume;
+
+/// How often `--serve` checks for due alarms.
+const TICK: Duration = Duration::from_secs(1);
+
+pub trait Clock {
+    /// Local wall-clock time.
+    fn now(&self) -> NaiveDateTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+/// A clock that only moves when told to.
+#[cfg(test)]
+pub struct FixedClock(std::cell::Cell<NaiveDateTime>);
+
+#[cfg(test)]
+impl FixedClock {
+    pub fn at(now: NaiveDateTime) -> Self {
+        Self(std::cell::Cell::new(now))
+    }
+
+    pub fn set(&self, now: NaiveDateTime) {
+        self.0.set(now);
+    }
+
+    pub fn advance(&self, by: chrono::TimeDelta) {
+        self.0.set(self.0.get() + by);
+    }
+}
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now(&self) -> NaiveDateTime {
+        self.0.get()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alarm {
+    pub id: u32,
+    pub time: NaiveTime,
+    /// Days it repeats on; empty for a one-off on `date`.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    #[serde(default)]
+    pub date: Option<NaiveDate>,
+    /// A search, an alias or a `spotify:` URI.
+    pub query: String,
+    pub mode: PlayMode,
+    /// Level to end up at; the current volume when unset.
+    #[serde(default)]
+    pub volume: Option<i32>,
+    /// Milliseconds to ramp up from silence.
+    #[serde(default)]
+    pub ramp_ms: Option<u64>,
+}
+
+impl Alarm {
+    /// The first time this alarm goes off strictly after `from`.
+    pub fn next_after(&self, from: NaiveDateTime) -> Option<NaiveDateTime> {
+        if self.days.is_empty() {
+            let at = self.date?.and_time(self.time);
+            return (at > from).then_some(at);
+        }
+        (0..=7)
+            .filter_map(|d| from.date().checked_add_days(chrono::Days::new(d)))
+            .map(|date| date.and_time(self.time))
+            .find(|at| *at > from && self.days.contains(&at.weekday()))
+    }
+
+    /// When it goes off in `(since, until]`, if it does.
+    pub fn due(&self, since: NaiveDateTime, until: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.next_after(since).filter(|at| *at <= until)
+    }
+
+    pub fn describe_days(&self) -> String {
+        const WEEKDAYS: [Weekday; 5] = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+        let mut days = self.days.clone();
+        days.sort_by_key(|d| d.num_days_from_monday());
+        days.dedup();
+        match days.as_slice() {
+            [] => match self.date {
+                Some(date) => format!("once, {}", date.format("%a %d %b")),
+                None => "once".to_string(),
+            },
+            d if d.len() == 7 => "daily".to_string(),
+            d if d == WEEKDAYS => "weekdays".to_string(),
+            [Weekday::Sat, Weekday::Sun] => "weekends".to_string(),
+            d => d.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", "),
+        }
+    }
+}
+
+/// The date of the next `time` after `now`: today if it hasn't passed yet.
+pub fn next_date(time: NaiveTime, now: NaiveDateTime) -> NaiveDate {
+    if now.time() < time {
+        now.date()
+    } else {
+        now.date().succ_opt().unwrap_or(now.date())
+    }
+}
+
+/// Parses `7:30` or `07:30`.
+pub fn parse_time(s: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M").map_err(|_| {
+        fail(
+            ErrorKind::Usage,
+            format!("Invalid time \"{s}\"; use 24-hour HH:MM, e.g. 07:30"),
+        )
+    })
+}
+
+/// Parses `mon,wed,fri`.
+pub fn parse_days(s: &str) -> Result<Vec<Weekday>> {
+    s.split(',')
+        .map(|d| {
+            d.trim().parse::<Weekday>().map_err(|_| {
+                fail(ErrorKind::Usage, format!("Invalid day \"{d}\"; use e.g. mon,wed,fri"))
+            })
+        })
+        .collect()
+}
+
+const FILE: &str = "schedule.json";
+
+pub fn load() -> Result<Vec<Alarm>> {
+    Ok(config::load_json(FILE)?.unwrap_or_default())
+}
+
+fn save(alarms: &[Alarm]) -> Result<()> {
+    config::save_json_atomic(FILE, alarms)
+}
+
+/// Orders `alarms` by when they next go off, those that won't go off again
+/// last.
+pub fn sort_by_next(alarms: &mut [Alarm], now: NaiveDateTime) {
+    alarms.sort_by_key(|a| {
+        let next = a.next_after(now);
+        (next.is_none(), next)
+    });
+}
+
+/// Stores `alarm` under the next free id and returns it.
+pub fn add(mut alarm: Alarm) -> Result<Alarm> {
+    let mut alarms = load()?;
+    alarm.id = alarms.iter().map(|a| a.id).max().unwrap_or(0) + 1;
+    alarms.push(alarm.clone());
+    save(&alarms)?;
+    Ok(alarm)
+}
+
+pub fn remove(id: u32) -> Result<Alarm> {
+    let mut alarms = load()?;
+    let index = alarms
+        .iter()
+        .position(|a| a.id == id)
+        .ok_or_else(|| fail(ErrorKind::NotFound, format!("No scheduled playback #{id}")))?;
+    let alarm = alarms.remove(index);
+    save(&alarms)?;
+    Ok(alarm)
+}
+
+/// Starts the alarm's music, fading in if it asks for a ramp.
+async fn fire(player: &Player, alarm: &Alarm, out: Output) -> Result<()> {
+    let ramp = alarm.ramp_ms.filter(|&ms| ms > 0).map(Duration::from_millis);
+    let target = match alarm.volume {
+        Some(volume) => volume.clamp(0, 100),
+        None => player.get_volume().await?,
+    };
+    player.set_volume(if ramp.is_some() { 0 } else { target }).await?;
+
+    let started = if alarm.query.starts_with("spotify:") {
+        undo::remember(player).await;
+        player.play_uri(&alarm.query).await
+    } else {
+        playback::play_query(player, &alarm.query, alarm.mode, out).await
+    };
+    if let Err(e) = started {
+        // Don't leave the next session silent.
+        let _ = player.set_volume(target).await;
+        return Err(e);
+    }
+
+    if let Some(ramp) = ramp {
+        let curve = Config::load()?.volume_settings().curve;
+        volume::ramp(player, 0, target, ramp, curve).await?;
+    }
+    Ok(())
+}
+
+/// The alarms due in `(since, now]`, removing one-offs from the schedule.
+/// `fired` keeps the last time each alarm went off, so when the clock falls
+/// back an hour the repeated times don't play it again.
+fn take_due(
+    since: NaiveDateTime,
+    now: NaiveDateTime,
+    fired: &mut HashMap<u32, NaiveDateTime>,
+) -> Result<Vec<Alarm>> {
+    let mut due = Vec::new();
+    for alarm in load()? {
+        let Some(at) = alarm.due(since, now) else {
+            continue;
+        };
+        if fired.get(&alarm.id).is_some_and(|last| at <= *last) {
+            continue;
+        }
+        fired.insert(alarm.id, at);
+        due.push(alarm);
+    }
+    for alarm in due.iter().filter(|a| a.days.is_empty()) {
+        if let Err(e) = remove(alarm.id) {
+            eprintln!("spot at: {e:#}");
+        }
+    }
+    Ok(due)
+}
+
+/// Plays alarms as they come due until interrupted. Alarms missed while
+/// nothing was serving are skipped, not caught up on.
+pub async fn serve(player: &Player, clock: &dyn Clock, out: Output) -> Result<()> {
+    let mut ticker = tokio::time::interval(TICK);
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let mut since = clock.now();
+    let mut fired = HashMap::new();
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+        let now = clock.now();
+        let due = match take_due(since, now, &mut fired) {
+            Ok(due) => due,
+            Err(e) => {
+                eprintln!("spot at: {e:#}");
+                continue;
+            }
+        };
+        since = now;
+
+        let firing = async {
+            for alarm in &due {
+                if let Err(e) = fire(player, alarm, out).await {
+                    eprintln!("spot at: #{} {}: {e:#}", alarm.id, alarm.query);
+                }
+            }
+        };
+        // A ramp takes minutes; stop mid-way rather than ignore ctrl-c.
+        tokio::select! {
+            _ = firing => {}
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{ConfigHome, env_lock};
+    use chrono::TimeDelta;
+    use Weekday::*;
+
+    /// `2026-10-19` is a Monday.
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{date} {time}"), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn alarm(time: &str, days: Vec<Weekday>, date: Option<&str>) -> Alarm {
+        Alarm {
+            id: 0,
+            time: parse_time(time).unwrap(),
+            days,
+            date: date.map(|d| d.parse().unwrap()),
+            query: "morning".to_string(),
+            mode: PlayMode::Artist,
+            volume: None,
+            ramp_ms: None,
+        }
+    }
+
+    #[test]
+    fn repeats_on_its_days() {
+        let weekdays = alarm("07:30", vec![Mon, Tue, Wed, Thu, Fri], None);
+        let monday = at("2026-10-19", "06:00:00");
+        assert_eq!(weekdays.next_after(monday), Some(at("2026-10-19", "07:30:00")));
+        let friday_late = at("2026-10-23", "08:00:00");
+        assert_eq!(weekdays.next_after(friday_late), Some(at("2026-10-26", "07:30:00")));
+    }
+
+    #[test]
+    fn wraps_round_to_the_same_weekday() {
+        let mondays = alarm("07:30", vec![Mon], None);
+        let just_after = at("2026-10-19", "07:30:01");
+        assert_eq!(mondays.next_after(just_after), Some(at("2026-10-26", "07:30:00")));
+        // Strictly after: from the alarm's own minute, it's next week's.
+        let exactly = at("2026-10-19", "07:30:00");
+        assert_eq!(mondays.next_after(exactly), Some(at("2026-10-26", "07:30:00")));
+        let sunday = at("2026-10-25", "23:59:59");
+        assert_eq!(mondays.next_after(sunday), Some(at("2026-10-26", "07:30:00")));
+    }
+
+    #[test]
+    fn one_offs_go_off_once() {
+        let once = alarm("22:00", vec![], Some("2026-10-19"));
+        assert_eq!(once.next_after(at("2026-10-19", "21:59:59")), Some(at("2026-10-19", "22:00:00")));
+        assert_eq!(once.next_after(at("2026-10-19", "22:00:00")), None);
+        assert_eq!(once.next_after(at("2026-10-20", "08:00:00")), None);
+        assert_eq!(alarm("22:00", vec![], None).next_after(at("2026-10-19", "08:00:00")), None);
+    }
+
+    #[test]
+    fn due_is_exclusive_of_since_and_inclusive_of_until() {
+        let daily = alarm("07:30", vec![Mon, Tue, Wed, Thu, Fri, Sat, Sun], None);
+        let due = Some(at("2026-10-19", "07:30:00"));
+        assert_eq!(daily.due(at("2026-10-19", "07:29:59"), at("2026-10-19", "07:30:00")), due);
+        assert_eq!(daily.due(at("2026-10-19", "07:29:00"), at("2026-10-19", "07:31:00")), due);
+        assert_eq!(daily.due(at("2026-10-19", "07:30:00"), at("2026-10-19", "07:30:01")), None);
+        assert_eq!(daily.due(at("2026-10-19", "07:29:58"), at("2026-10-19", "07:29:59")), None);
+    }
+
+    #[test]
+    fn next_date_is_today_until_the_time_passes() {
+        let time = parse_time("07:30").unwrap();
+        let date = |d: &str| d.parse::<NaiveDate>().unwrap();
+        assert_eq!(next_date(time, at("2026-10-19", "07:29:59")), date("2026-10-19"));
+        assert_eq!(next_date(time, at("2026-10-19", "07:30:00")), date("2026-10-20"));
+        assert_eq!(next_date(time, at("2026-12-31", "23:00:00")), date("2027-01-01"));
+    }
+
+    #[test]
+    fn describes_days() {
+        let describe = |days: Vec<Weekday>, date: Option<&str>| alarm("07:30", days, date).describe_days();
+        assert_eq!(describe(vec![Fri, Mon, Wed, Tue, Thu], None), "weekdays");
+        assert_eq!(describe(vec![Sun, Sat], None), "weekends");
+        assert_eq!(describe(vec![Mon, Tue, Wed, Thu, Fri, Sat, Sun], None), "daily");
+        assert_eq!(describe(vec![Wed, Mon, Wed], None), "Mon, Wed");
+        assert_eq!(describe(vec![], Some("2026-10-19")), "once, Mon 19 Oct");
+        assert_eq!(describe(vec![], None), "once");
+    }
+
+    #[test]
+    fn sorts_by_next_with_passed_alarms_last() {
+        let mut alarms = vec![
+            Alarm { id: 1, ..alarm("22:00", vec![], Some("2026-10-18")) },
+            Alarm { id: 2, ..alarm("09:00", vec![Tue], None) },
+            Alarm { id: 3, ..alarm("08:00", vec![], Some("2026-10-19")) },
+        ];
+        sort_by_next(&mut alarms, at("2026-10-19", "07:00:00"));
+        let ids: Vec<u32> = alarms.iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![3, 2, 1]);
+    }
+
+    /// Runs `take_due` once a second from the clock's time for `secs`, as
+    /// `serve` does, returning the ids fired.
+    fn step(clock: &FixedClock, fired: &mut HashMap<u32, NaiveDateTime>, secs: i64) -> Vec<u32> {
+        let mut ids = Vec::new();
+        for _ in 0..secs {
+            let since = clock.now();
+            clock.advance(TimeDelta::seconds(1));
+            ids.extend(take_due(since, clock.now(), fired).unwrap().iter().map(|a| a.id));
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn fires_due_alarms_and_drops_one_offs() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let daily = add(alarm("07:30", vec![Mon, Tue, Wed, Thu, Fri, Sat, Sun], None)).unwrap();
+        let once = add(alarm("07:30", vec![], Some("2026-10-19"))).unwrap();
+        let later = add(alarm("09:00", vec![Mon], None)).unwrap();
+
+        let clock = FixedClock::at(at("2026-10-19", "07:29:55"));
+        let mut fired = HashMap::new();
+        assert_eq!(step(&clock, &mut fired, 10), vec![daily.id, once.id]);
+        let left: Vec<u32> = load().unwrap().iter().map(|a| a.id).collect();
+        assert_eq!(left, vec![daily.id, later.id]);
+
+        assert!(step(&clock, &mut fired, 60).is_empty());
+        clock.set(at("2026-10-20", "07:29:59"));
+        assert_eq!(step(&clock, &mut fired, 1), vec![daily.id]);
+    }
+
+    #[tokio::test]
+    async fn fires_once_when_the_clock_falls_back() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let early = add(alarm("01:30", vec![Sun], None)).unwrap();
+
+        let clock = FixedClock::at(at("2026-10-25", "01:29:59"));
+        let mut fired = HashMap::new();
+        assert_eq!(step(&clock, &mut fired, 1), vec![early.id]);
+        // 02:00 summer time becomes 01:00 winter time.
+        clock.set(at("2026-10-25", "01:59:59"));
+        let since = clock.now();
+        clock.set(at("2026-10-25", "01:00:00"));
+        assert!(take_due(since, clock.now(), &mut fired).unwrap().is_empty());
+        assert!(step(&clock, &mut fired, 3600).is_empty());
+        // Next week's is a new occurrence.
+        clock.set(at("2026-11-01", "01:29:59"));
+        assert_eq!(step(&clock, &mut fired, 1), vec![early.id]);
+    }
+}
@@ -0,0 +1,793 @@
+//! Scrobbling to ListenBrainz and Last.fm. `spot scrobble` polls the player,
+//! sends "now playing" on each track change and a listen once the track has
+//! played for half its length or four minutes. Listens that can't be sent
+//! wait in `scrobble_queue.jsonl` in the config directory; changes to it
+//! are serialised through `scrobble_queue.lock` next to it, and sending it
+//! through `scrobble_flush.lock`. The Last.fm session key is kept in
+//! `lastfm_session.json`.
+
+use anyhow::{Context, Result};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::config::{self, Config, LastFmSettings};
+use crate::history::Listen;
+use crate::output::{self, ErrorKind, fail};
+use crate::spotify::api;
+use crate::spotify::player::Player;
+
+const LISTENBRAINZ_URL: &str = "https://api.listenbrainz.org";
+const LASTFM_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+
+/// Tracks shorter than this are never scrobbled.
+const MIN_TRACK_MS: i64 = 30_000;
+
+/// A listen counts after half the track or this long, whichever is first.
+const MAX_THRESHOLD_MS: i64 = 4 * 60 * 1000;
+
+/// Most listens either service accepts in one submission.
+const BATCH: usize = 50;
+
+/// How often queued listens are retried while running.
+const RETRY_EVERY: Duration = Duration::from_secs(5 * 60);
+
+/// A restart of the same track further back than this is a new listen.
+const RESTART_MS: i64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Service {
+    ListenBrainz,
+    LastFm,
+}
+
+impl Service {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::ListenBrainz => "ListenBrainz",
+            Self::LastFm => "Last.fm",
+        }
+    }
+
+    /// How to fix credentials the service refused.
+    fn setup_hint(self) -> &'static str {
+        match self {
+            Self::ListenBrainz => "Check the token under [scrobble.listenbrainz] in config.toml",
+            Self::LastFm => "Run: spot scrobble login",
+        }
+    }
+
+    /// The services with settings in `[scrobble]`.
+    pub fn configured(config: &Config) -> Vec<Self> {
+        let settings = config.scrobble_settings();
+        let mut services = Vec::new();
+        if settings.listenbrainz.is_some() {
+            services.push(Self::ListenBrainz);
+        }
+        if settings.lastfm.is_some() {
+            services.push(Self::LastFm);
+        }
+        services
+    }
+
+    async fn now_playing(self, config: &Config, scrobble: &Scrobble) -> Result<()> {
+        match self {
+            Self::ListenBrainz => {
+                listenbrainz_submit(config, "playing_now", &[listenbrainz_listen(scrobble, false)]).await
+            }
+            Self::LastFm => {
+                let mut params = vec![
+                    ("artist".to_string(), scrobble.artist.clone()),
+                    ("track".to_string(), scrobble.track.clone()),
+                    ("album".to_string(), scrobble.album.clone()),
+                    ("duration".to_string(), (scrobble.duration_ms / 1000).to_string()),
+                ];
+                params.retain(|(_, v)| !v.is_empty());
+                lastfm_call(lastfm_settings(config)?, "track.updateNowPlaying", params, true).await?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn submit(self, config: &Config, batch: &[Scrobble]) -> Result<()> {
+        match self {
+            Self::ListenBrainz => {
+                let listens: Vec<Value> = batch.iter().map(|s| listenbrainz_listen(s, true)).collect();
+                let listen_type = if batch.len() == 1 { "single" } else { "import" };
+                listenbrainz_submit(config, listen_type, &listens).await
+            }
+            Self::LastFm => {
+                let mut params = Vec::new();
+                for (i, s) in batch.iter().enumerate() {
+                    params.push((format!("artist[{i}]"), s.artist.clone()));
+                    params.push((format!("track[{i}]"), s.track.clone()));
+                    params.push((format!("timestamp[{i}]"), s.listened_at.to_string()));
+                    params.push((format!("duration[{i}]"), (s.duration_ms / 1000).to_string()));
+                    if !s.album.is_empty() {
+                        params.push((format!("album[{i}]"), s.album.clone()));
+                    }
+                }
+                lastfm_call(lastfm_settings(config)?, "track.scrobble", params, true).await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// One listen as submitted to the services.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scrobble {
+    pub artist: String,
+    pub track: String,
+    pub album: String,
+    pub track_id: String,
+    pub duration_ms: i64,
+    /// Unix seconds when the track started.
+    pub listened_at: i64,
+}
+
+impl Scrobble {
+    fn from_listen(listen: &Listen) -> Self {
+        Self {
+            artist: listen.info.artist.clone(),
+            track: listen.info.track_name.clone(),
+            album: listen.info.album.clone(),
+            track_id: listen.info.track_id.clone(),
+            duration_ms: listen.info.duration_ms,
+            listened_at: listen.started_at,
+        }
+    }
+}
+
+/// How long a track must play before it's scrobbled, if it ever is.
+pub fn threshold_ms(duration_ms: i64) -> Option<i64> {
+    (duration_ms >= MIN_TRACK_MS).then(|| (duration_ms / 2).min(MAX_THRESHOLD_MS))
+}
+
+/// Failures worth retrying later: the network, or the service being down.
+fn is_retryable(err: &anyhow::Error) -> bool {
+    output::kind_of(err) == ErrorKind::Network
+}
+
+/// Failures the user has to fix, such as an expired session; listens wait
+/// for them rather than being lost.
+fn needs_setup(err: &anyhow::Error) -> bool {
+    matches!(output::kind_of(err), ErrorKind::Auth | ErrorKind::Config)
+}
+
+fn env_url(var: &str, default: &str) -> String {
+    std::env::var(var)
+        .unwrap_or_else(|_| default.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn listenbrainz_listen(scrobble: &Scrobble, with_time: bool) -> Value {
+    let mut listen = json!({
+        "track_metadata": {
+            "artist_name": scrobble.artist,
+            "track_name": scrobble.track,
+            "release_name": scrobble.album,
+            "additional_info": {
+                "duration_ms": scrobble.duration_ms,
+                "spotify_id": spotify_url(&scrobble.track_id),
+                "media_player": "Spotify",
+                "submission_client": "spot",
+                "submission_client_version": env!("CARGO_PKG_VERSION"),
+            },
+        },
+    });
+    if with_time {
+        listen["listened_at"] = json!(scrobble.listened_at);
+    }
+    listen
+}
+
+/// `spotify:track:id` → `https://open.spotify.com/track/id`.
+fn spotify_url(uri: &str) -> Option<String> {
+    let mut parts = uri.split(':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("spotify"), Some(kind), Some(id)) => Some(format!("https://open.spotify.com/{kind}/{id}")),
+        _ => None,
+    }
+}
+
+/// `SPOT_LISTENBRAINZ_URL` overrides the API base, e.g. to point at a mock server.
+async fn listenbrainz_submit(config: &Config, listen_type: &str, payload: &[Value]) -> Result<()> {
+    let settings = config.scrobble_settings().listenbrainz.ok_or_else(|| {
+        fail(ErrorKind::Config, "ListenBrainz is not configured")
+    })?;
+    let resp = api::client()
+        .post(format!("{}/1/submit-listens", env_url("SPOT_LISTENBRAINZ_URL", LISTENBRAINZ_URL)))
+        .header("Authorization", format!("Token {}", settings.token))
+        .json(&json!({ "listen_type": listen_type, "payload": payload }))
+        .send()
+        .await
+        .context("Failed to reach ListenBrainz")?;
+
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = resp.text().await.unwrap_or_default();
+    let kind = if status == reqwest::StatusCode::UNAUTHORIZED {
+        ErrorKind::Auth
+    } else if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        ErrorKind::Network
+    } else {
+        ErrorKind::Api
+    };
+    Err(fail(kind, format!("ListenBrainz rejected the request ({status}): {body}")))
+}
+
+const SESSION_FILE: &str = "lastfm_session.json";
+
+#[derive(Serialize, Deserialize)]
+struct LastFmSession {
+    session_key: String,
+}
+
+/// Stores the key from [`lastfm_login`] owner-only, away from config.toml.
+pub fn save_lastfm_session(session_key: &str) -> Result<()> {
+    config::save_json_atomic(
+        SESSION_FILE,
+        &LastFmSession {
+            session_key: session_key.to_string(),
+        },
+    )
+}
+
+/// The `[scrobble.lastfm]` settings with the stored session key, if any.
+pub fn lastfm_settings(config: &Config) -> Result<LastFmSettings> {
+    let mut settings = config
+        .scrobble_settings()
+        .lastfm
+        .ok_or_else(|| fail(ErrorKind::Config, "Last.fm is not configured"))?;
+    if let Some(session) = config::load_json::<LastFmSession>(SESSION_FILE)? {
+        settings.session_key = Some(session.session_key);
+    }
+    Ok(settings)
+}
+
+/// `api_sig`: MD5 of the parameters sorted by name and concatenated, then
+/// the shared secret.
+fn lastfm_signature(params: &[(String, String)], secret: &str) -> String {
+    let mut sorted: Vec<&(String, String)> = params.iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut hasher = Md5::new();
+    for (name, value) in sorted {
+        hasher.update(name.as_bytes());
+        hasher.update(value.as_bytes());
+    }
+    hasher.update(secret.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Calls a signed Last.fm method. `SPOT_LASTFM_URL` overrides the endpoint,
+/// e.g. to point at a mock server.
+async fn lastfm_call(
+    settings: LastFmSettings,
+    method: &str,
+    mut params: Vec<(String, String)>,
+    with_session: bool,
+) -> Result<Value> {
+    params.push(("method".to_string(), method.to_string()));
+    params.push(("api_key".to_string(), settings.api_key.clone()));
+    if with_session {
+        let session_key = settings.session_key.clone().ok_or_else(|| {
+            fail(
+                ErrorKind::Auth,
+                "Not logged in to Last.fm. Run: spot scrobble login",
+            )
+        })?;
+        params.push(("sk".to_string(), session_key));
+    }
+    let signature = lastfm_signature(&params, &settings.api_secret);
+    params.push(("api_sig".to_string(), signature));
+    params.push(("format".to_string(), "json".to_string()));
+
+    let resp = api::client()
+        .post(env_url("SPOT_LASTFM_URL", LASTFM_URL))
+        .form(&params)
+        .send()
+        .await
+        .context("Failed to reach Last.fm")?;
+    let status = resp.status();
+    let body: Value = resp.json().await.unwrap_or(Value::Null);
+
+    // Last.fm reports most errors as {"error": code, "message": ...}.
+    if let Some(code) = body.get("error").and_then(Value::as_i64) {
+        let message = body.get("message").and_then(Value::as_str).unwrap_or("");
+        let kind = match code {
+            // Service offline, temporarily unavailable, rate limited.
+            11 | 16 | 29 => ErrorKind::Network,
+            4 | 9 | 10 | 14 | 26 => ErrorKind::Auth,
+            _ => ErrorKind::Api,
+        };
+        return Err(fail(kind, format!("Last.fm error {code}: {message}")));
+    }
+    if status.is_server_error() {
+        return Err(fail(ErrorKind::Network, format!("Last.fm is unavailable ({status})")));
+    }
+    if !status.is_success() {
+        return Err(fail(ErrorKind::Api, format!("Last.fm rejected the request ({status})")));
+    }
+    Ok(body)
+}
+
+/// Trades a Last.fm username and password for a session key.
+pub async fn lastfm_login(settings: LastFmSettings, username: &str, password: &str) -> Result<String> {
+    let params = vec![
+        ("username".to_string(), username.to_string()),
+        ("password".to_string(), password.to_string()),
+    ];
+    let body = lastfm_call(settings, "auth.getMobileSession", params, false).await?;
+    body["session"]["key"]
+        .as_str()
+        .map(String::from)
+        .ok_or_else(|| fail(ErrorKind::Api, "Last.fm returned no session key"))
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Queued {
+    service: Service,
+    scrobble: Scrobble,
+}
+
+const QUEUE_FILE: &str = "scrobble_queue.jsonl";
+
+fn queue_path() -> Result<PathBuf> {
+    Config::state_path(QUEUE_FILE)
+}
+
+/// Held while the queue file is appended to or replaced, so a flush can't
+/// replace it in between `enqueue` opening it and writing.
+fn lock_queue() -> Result<File> {
+    config::lock("scrobble_queue.lock")
+}
+
+/// Held for a whole flush, so a running `spot scrobble` and a manual
+/// `spot scrobble flush` never send the same listens. `None` while another
+/// flush holds it.
+fn try_lock_flush() -> Result<Option<File>> {
+    let (file, path) = config::open_lock("scrobble_flush.lock")?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+        }
+    }
+}
+
+fn load_queue() -> Result<Vec<Queued>> {
+    config::load_jsonl(QUEUE_FILE)
+}
+
+fn save_queue(queue: &[Queued]) -> Result<()> {
+    let path = queue_path()?;
+    if queue.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        return Ok(());
+    }
+    config::save_jsonl_atomic(QUEUE_FILE, queue)
+}
+
+fn enqueue(entry: &Queued) -> Result<()> {
+    let _lock = lock_queue()?;
+    let path = queue_path()?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open scrobble queue at {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+        .with_context(|| format!("Failed to write scrobble queue to {}", path.display()))?;
+    Ok(())
+}
+
+pub fn queued() -> Result<usize> {
+    Ok(load_queue()?.len())
+}
+
+/// Resends queued listens in batches. A listen the service refuses outright
+/// is dropped; when the service is down or refuses the credentials, that
+/// service's listens stay queued. Returns how many were sent, none when
+/// another flush is already sending.
+///
+/// The queue lock isn't held while sending, so the queue is read again
+/// before it's replaced and anything queued in the meantime is kept.
+pub async fn flush(config: &Config) -> Result<usize> {
+    let Some(_flushing) = try_lock_flush()? else {
+        return Ok(0);
+    };
+    let queue = {
+        let _lock = lock_queue()?;
+        load_queue()?
+    };
+    if queue.is_empty() {
+        return Ok(0);
+    }
+    let mut sent = 0;
+    let mut remaining = Vec::new();
+    for service in [Service::ListenBrainz, Service::LastFm] {
+        let pending: Vec<Queued> = queue.iter().filter(|q| q.service == service).cloned().collect();
+        let mut chunks = pending.chunks(BATCH);
+        'batches: for chunk in chunks.by_ref() {
+            let batch: Vec<Scrobble> = chunk.iter().map(|q| q.scrobble.clone()).collect();
+            let err = match service.submit(config, &batch).await {
+                Ok(()) => {
+                    sent += chunk.len();
+                    continue;
+                }
+                Err(e) => e,
+            };
+            if keep_queued(service, &err) {
+                remaining.extend_from_slice(chunk);
+                break;
+            }
+            // One bad listen fails the whole batch, so send them singly to
+            // drop only the ones refused.
+            if chunk.len() == 1 {
+                report_dropped(service, &chunk[0], &err);
+                continue;
+            }
+            for (i, queued) in chunk.iter().enumerate() {
+                match service.submit(config, std::slice::from_ref(&queued.scrobble)).await {
+                    Ok(()) => sent += 1,
+                    Err(e) if keep_queued(service, &e) => {
+                        remaining.extend_from_slice(&chunk[i..]);
+                        break 'batches;
+                    }
+                    Err(e) => report_dropped(service, queued, &e),
+                }
+            }
+        }
+        for chunk in chunks {
+            remaining.extend_from_slice(chunk);
+        }
+    }
+    let _lock = lock_queue()?;
+    remaining.extend(load_queue()?.into_iter().filter(|q| !queue.contains(q)));
+    save_queue(&remaining)?;
+    Ok(sent)
+}
+
+/// Whether `err` means `service`'s listens should wait, saying so when it
+/// takes the user to fix.
+fn keep_queued(service: Service, err: &anyhow::Error) -> bool {
+    if needs_setup(err) {
+        eprintln!(
+            "spot scrobble: {} listens stay queued: {err:#}. {}",
+            service.label(),
+            service.setup_hint()
+        );
+        return true;
+    }
+    is_retryable(err)
+}
+
+fn report_dropped(service: Service, queued: &Queued, err: &anyhow::Error) {
+    eprintln!(
+        "spot scrobble: dropping queued {} listen of \"{}\": {err:#}",
+        service.label(),
+        queued.scrobble.track
+    );
+}
+
+/// Sends a listen to every service, queueing it for those that are offline.
+async fn submit(config: &Config, services: &[Service], scrobble: &Scrobble) -> Result<()> {
+    for &service in services {
+        match service.submit(config, std::slice::from_ref(scrobble)).await {
+            Ok(()) => {}
+            Err(e) if is_retryable(&e) || needs_setup(&e) => {
+                if needs_setup(&e) {
+                    eprintln!("spot scrobble: {}, queued: {e:#}. {}", service.label(), service.setup_hint());
+                } else {
+                    eprintln!("spot scrobble: {} unavailable, queued: {e:#}", service.label());
+                }
+                enqueue(&Queued {
+                    service,
+                    scrobble: scrobble.clone(),
+                })?;
+            }
+            Err(e) => eprintln!("spot scrobble: {}: {e:#}", service.label()),
+        }
+    }
+    Ok(())
+}
+
+/// Polls the player every `interval` and scrobbles to every configured
+/// service until interrupted. `verbose` prints each listen as it is sent.
+pub async fn run(player: &Player, config: &Config, interval: Duration, verbose: bool) -> Result<()> {
+    let services = Service::configured(config);
+    if services.is_empty() {
+        return Err(fail(
+            ErrorKind::Config,
+            "No scrobbling service configured. Add [scrobble.listenbrainz] or [scrobble.lastfm] to config.toml",
+        ));
+    }
+    if let Err(e) = flush(config).await {
+        eprintln!("spot scrobble: {e:#}");
+    }
+
+    let mut ticker = tokio::time::interval(interval);
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let mut current: Option<(Listen, bool)> = None;
+    let mut retried = Instant::now();
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+
+        if let Some((listen, _)) = &mut current {
+            listen.tick();
+        }
+        let polled = match player.get_now_playing().await {
+            Ok(info) => Some(info),
+            Err(e) if output::kind_of(&e) == ErrorKind::NothingPlaying => None,
+            Err(e) => {
+                // A failed poll isn't the end of the track; try again next tick.
+                eprintln!("spot scrobble: {e:#}");
+                continue;
+            }
+        };
+        let continues = match (&current, &polled) {
+            (Some((listen, scrobbled)), Some(info)) => {
+                listen.info.track_id == info.track_id
+                    && !(*scrobbled && info.position_ms + RESTART_MS < listen.info.position_ms)
+            }
+            _ => false,
+        };
+
+        if continues {
+            if let (Some((listen, scrobbled)), Some(info)) = (&mut current, polled) {
+                listen.info = info;
+                let due = threshold_ms(listen.info.duration_ms)
+                    .is_some_and(|threshold| listen.listened_ms >= threshold);
+                if due && !*scrobbled {
+                    *scrobbled = true;
+                    let scrobble = Scrobble::from_listen(listen);
+                    // One failed listen shouldn't stop the ones after it.
+                    match submit(config, &services, &scrobble).await {
+                        Ok(()) if verbose => {
+                            println!("  ✓ {} — {}", scrobble.track, scrobble.artist);
+                        }
+                        Ok(()) => {}
+                        Err(e) => eprintln!("spot scrobble: {e:#}"),
+                    }
+                }
+            }
+        } else {
+            current = polled.map(|info| (Listen::start(info), false));
+            if let Some((listen, _)) = &current {
+                let scrobble = Scrobble::from_listen(listen);
+                for &service in &services {
+                    if let Err(e) = service.now_playing(config, &scrobble).await {
+                        eprintln!("spot scrobble: {}: {e:#}", service.label());
+                    }
+                }
+            }
+        }
+
+        if retried.elapsed() >= RETRY_EVERY {
+            if let Err(e) = flush(config).await {
+                eprintln!("spot scrobble: {e:#}");
+            }
+            retried = Instant::now();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ListenBrainzSettings, ScrobbleSettings};
+    use crate::test_support::{ConfigHome, Stub, env_lock, route};
+
+    fn listen(track: &str, listened_at: i64) -> Queued {
+        Queued {
+            service: Service::ListenBrainz,
+            scrobble: Scrobble {
+                artist: "Miles Davis".to_string(),
+                track: track.to_string(),
+                album: "Kind of Blue".to_string(),
+                track_id: format!("spotify:track:{track}"),
+                duration_ms: 300_000,
+                listened_at,
+            },
+        }
+    }
+
+    fn listenbrainz_config() -> Config {
+        Config {
+            scrobble: Some(ScrobbleSettings {
+                listenbrainz: Some(ListenBrainzSettings {
+                    token: "lb-token".to_string(),
+                }),
+                lastfm: None,
+            }),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn threshold_is_half_the_track_up_to_four_minutes() {
+        assert_eq!(threshold_ms(29_999), None);
+        assert_eq!(threshold_ms(0), None);
+        assert_eq!(threshold_ms(30_000), Some(15_000));
+        assert_eq!(threshold_ms(200_000), Some(100_000));
+        assert_eq!(threshold_ms(480_000), Some(240_000));
+        assert_eq!(threshold_ms(3_600_000), Some(240_000));
+    }
+
+    #[test]
+    fn signature_sorts_params_and_appends_the_secret() {
+        let params = vec![
+            ("track[0]".to_string(), "So What".to_string()),
+            ("method".to_string(), "track.scrobble".to_string()),
+            ("api_key".to_string(), "key".to_string()),
+        ];
+        // md5("api_keykeymethodtrack.scrobbletrack[0]So Whatsecret")
+        assert_eq!(lastfm_signature(&params, "secret"), "5e649f195b5d87396409963b4cc1ab83");
+    }
+
+    #[tokio::test]
+    async fn flush_sends_the_queue_and_keeps_what_arrives_meanwhile() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let stub = Stub::start(vec![route("POST", "/1/submit-listens", 200, "{}")]).await;
+        std::env::set_var("SPOT_LISTENBRAINZ_URL", &stub.url);
+        enqueue(&listen("a", 100)).unwrap();
+        enqueue(&listen("b", 200)).unwrap();
+
+        let config = listenbrainz_config();
+        let late = listen("c", 300);
+        let (sent, queued) = tokio::join!(flush(&config), async {
+            enqueue(&late)
+        });
+        queued.unwrap();
+
+        assert_eq!(sent.unwrap(), 2);
+        assert_eq!(load_queue().unwrap(), vec![late]);
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 1);
+        let body: Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["listen_type"], "import");
+        assert_eq!(body["payload"][1]["listened_at"], 200);
+        assert_eq!(body["payload"][0]["track_metadata"]["track_name"], "a");
+    }
+
+    #[tokio::test]
+    async fn flush_skips_while_another_is_sending() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let stub = Stub::start(vec![route("POST", "/1/submit-listens", 200, "{}")]).await;
+        std::env::set_var("SPOT_LISTENBRAINZ_URL", &stub.url);
+        let queued = listen("a", 100);
+        enqueue(&queued).unwrap();
+
+        let other = try_lock_flush().unwrap().unwrap();
+        assert_eq!(flush(&listenbrainz_config()).await.unwrap(), 0);
+        assert_eq!(load_queue().unwrap(), vec![queued]);
+        assert!(stub.requests().is_empty());
+
+        drop(other);
+        assert_eq!(flush(&listenbrainz_config()).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn the_last_fm_session_key_is_kept_out_of_config_toml() {
+        let _env = env_lock().await;
+        let home = ConfigHome::new();
+        let config = Config {
+            scrobble: Some(ScrobbleSettings {
+                listenbrainz: None,
+                lastfm: Some(LastFmSettings {
+                    api_key: "key".to_string(),
+                    api_secret: "secret".to_string(),
+                    session_key: Some("old".to_string()),
+                }),
+            }),
+            ..Config::default()
+        };
+        assert_eq!(lastfm_settings(&config).unwrap().session_key.as_deref(), Some("old"));
+
+        save_lastfm_session("sk-1").unwrap();
+        assert_eq!(lastfm_settings(&config).unwrap().session_key.as_deref(), Some("sk-1"));
+        assert!(!home.dir.join("config.toml").exists());
+        let mode = fs::metadata(home.dir.join(SESSION_FILE)).unwrap().permissions();
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&mode) & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn flush_keeps_listens_while_the_service_is_down() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let stub = Stub::start(vec![route("POST", "/1/submit-listens", 503, "down")]).await;
+        std::env::set_var("SPOT_LISTENBRAINZ_URL", &stub.url);
+        let queued = listen("a", 100);
+        enqueue(&queued).unwrap();
+
+        let config = listenbrainz_config();
+        let late = listen("b", 200);
+        let (sent, appended) = tokio::join!(flush(&config), async {
+            enqueue(&late)
+        });
+        appended.unwrap();
+
+        assert_eq!(sent.unwrap(), 0);
+        assert_eq!(load_queue().unwrap(), vec![queued, late]);
+    }
+
+    #[tokio::test]
+    async fn flush_drops_listens_the_service_refuses() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let stub = Stub::start(vec![route("POST", "/1/submit-listens", 400, "bad")]).await;
+        std::env::set_var("SPOT_LISTENBRAINZ_URL", &stub.url);
+        enqueue(&listen("a", 100)).unwrap();
+
+        assert_eq!(flush(&listenbrainz_config()).await.unwrap(), 0);
+        assert_eq!(queued().unwrap(), 0);
+        assert!(!queue_path().unwrap().exists());
+    }
+
+    #[tokio::test]
+    async fn flush_keeps_listens_when_the_token_is_refused() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let stub = Stub::start(vec![route("POST", "/1/submit-listens", 401, "bad token")]).await;
+        std::env::set_var("SPOT_LISTENBRAINZ_URL", &stub.url);
+        let queue = vec![listen("a", 100), listen("b", 200)];
+        for queued in &queue {
+            enqueue(queued).unwrap();
+        }
+
+        assert_eq!(flush(&listenbrainz_config()).await.unwrap(), 0);
+        assert_eq!(load_queue().unwrap(), queue);
+        assert_eq!(stub.requests().len(), 1, "stops at the first refusal");
+    }
+
+    #[tokio::test]
+    async fn flush_keeps_last_fm_listens_until_logged_in() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let queued = Queued {
+            service: Service::LastFm,
+            ..listen("a", 100)
+        };
+        enqueue(&queued).unwrap();
+        let config = Config {
+            scrobble: Some(ScrobbleSettings {
+                listenbrainz: None,
+                lastfm: Some(LastFmSettings {
+                    api_key: "key".to_string(),
+                    api_secret: "secret".to_string(),
+                    session_key: None,
+                }),
+            }),
+            ..Config::default()
+        };
+
+        assert_eq!(flush(&config).await.unwrap(), 0);
+        assert_eq!(load_queue().unwrap(), vec![queued]);
+    }
+}
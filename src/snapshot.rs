@@ -0,0 +1,95 @@
+//! Playback state as `spot save` and `spot undo` keep it: what's playing,
+//! where from and how, and how to put it all back.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::spotify::api;
+use crate::spotify::auth::{self, now_secs};
+use crate::spotify::player::Player;
+
+/// Everything `spot restore` puts back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub track_id: String,
+    pub track: String,
+    pub artist: String,
+    pub album: String,
+    /// The album or playlist playing from, where the backend or the Web API
+    /// says.
+    #[serde(default)]
+    pub context: Option<String>,
+    pub position_ms: i64,
+    pub duration_ms: i64,
+    pub is_playing: bool,
+    pub volume: i32,
+    pub shuffle: bool,
+    pub repeat: bool,
+    /// Unix seconds.
+    pub saved_at: u64,
+}
+
+impl Snapshot {
+    pub async fn capture(player: &Player) -> Result<Self> {
+        Self::read(player, true).await
+    }
+
+    /// Like [`capture`](Self::capture), but without asking the Web API for
+    /// the context, which would hold up every play that calls it.
+    pub async fn capture_quick(player: &Player) -> Result<Self> {
+        Self::read(player, false).await
+    }
+
+    async fn read(player: &Player, ask_web: bool) -> Result<Self> {
+        let info = player.get_now_playing().await?;
+        // Optional extra; not worth failing the save over.
+        let context = match player.get_context().await.ok().flatten() {
+            Some(context) => Some(context),
+            None if ask_web => web_context(&info.track_id).await,
+            None => None,
+        };
+        Ok(Self {
+            track_id: info.track_id,
+            track: info.track_name,
+            artist: info.artist,
+            album: info.album,
+            context,
+            position_ms: info.position_ms,
+            duration_ms: info.duration_ms,
+            is_playing: info.is_playing,
+            volume: player.get_volume().await?,
+            shuffle: player.get_shuffle().await?,
+            repeat: player.get_repeat().await?,
+            saved_at: now_secs(),
+        })
+    }
+
+    pub async fn apply(&self, player: &Player) -> Result<()> {
+        player.set_shuffle(self.shuffle).await?;
+        player.set_repeat(self.repeat).await?;
+        player.set_volume(self.volume).await?;
+        match &self.context {
+            Some(context) => player.play_in_context(context, &self.track_id).await?,
+            None => player.play_uri(&self.track_id).await?,
+        }
+        // Players ignore a seek that lands before the track has loaded.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        player.seek(self.position_ms).await?;
+        if !self.is_playing {
+            player.pause().await?;
+        }
+        Ok(())
+    }
+}
+
+/// The desktop backends don't report a context, but with a user login the
+/// Web API does for the same account.
+async fn web_context(track_uri: &str) -> Option<String> {
+    if !auth::has_user_token() {
+        return None;
+    }
+    let token = auth::get_user_token(&Config::load().ok()?).await.ok()?;
+    api::playing_context(&token, track_uri).await.ok().flatten()
+}
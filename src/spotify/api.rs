@@ -1,7 +1,26 @@
-use anyhow::{Context, Result, bail};
-use serde::Deserialize;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
 
-#[derive(Debug, Clone)]
+use crate::output::{ErrorKind, fail};
+
+const API_BASE: &str = "https://api.spotify.com/v1";
+
+/// `SPOT_API_URL` overrides the Web API base, e.g. to point at a mock server.
+pub fn base_url() -> String {
+    std::env::var("SPOT_API_URL")
+        .unwrap_or_else(|_| API_BASE.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Shared HTTP client, so a long-running process keeps its connection pool.
+pub fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub name: String,
     pub uri: String,
@@ -14,7 +33,8 @@ impl std::fmt::Display for SearchResult {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
 pub enum SearchType {
     Track,
     Album,
@@ -23,6 +43,8 @@ pub enum SearchType {
 }
 
 impl SearchType {
+    pub const ALL: [SearchType; 4] = [Self::Track, Self::Album, Self::Artist, Self::Playlist];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::Track => "track",
@@ -53,6 +75,50 @@ struct SearchResponse {
 #[derive(Deserialize)]
 struct Paging<T> {
     items: Vec<T>,
+    #[serde(default)]
+    total: u32,
+    /// Set while there are more items after this page.
+    #[serde(default)]
+    next: Option<String>,
+}
+
+impl<T> Paging<T> {
+    fn map<U>(self, f: impl FnMut(T) -> U) -> Paging<U> {
+        Paging {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            next: self.next,
+        }
+    }
+}
+
+/// Spotify's cap on `limit` for a single search request.
+const MAX_SEARCH_PAGE: u32 = 50;
+
+/// Spotify won't page past this many results, counting the offset.
+pub const MAX_SEARCH_WINDOW: u32 = 1000;
+
+/// One type's slice of a search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchPage {
+    #[serde(rename = "type")]
+    pub search_type: SearchType,
+    pub results: Vec<SearchResult>,
+    /// Position of the first result among all matches.
+    pub offset: u32,
+    /// How many matches Spotify has in all.
+    pub total: u32,
+    /// Whether Spotify linked a page after the last one fetched. Its totals
+    /// are estimates, so they alone can promise pages that never come.
+    #[serde(default)]
+    pub next: bool,
+}
+
+impl SearchPage {
+    /// Whether there are matches after this page.
+    pub fn has_more(&self) -> bool {
+        self.next && self.offset + (self.results.len() as u32) < self.total.min(MAX_SEARCH_WINDOW)
+    }
 }
 
 #[derive(Deserialize)]
@@ -112,14 +178,74 @@ pub async fn search(
     search_type: SearchType,
     limit: u32,
 ) -> Result<Vec<SearchResult>> {
-    let client = reqwest::Client::new();
-    let resp = client
-        .get("https://api.spotify.com/v1/search")
+    let mut pages = search_types(token, query, &[search_type], limit, 0).await?;
+    Ok(pages.pop().map(|page| page.results).unwrap_or_default())
+}
+
+/// Searches several types in one request, returning up to `limit` results
+/// of each starting at `offset`, in the order of `types`. Limits above what
+/// Spotify allows per request are met by following each type's `next` page.
+pub async fn search_types(
+    token: &str,
+    query: &str,
+    types: &[SearchType],
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<SearchPage>> {
+    let mut pages: Vec<SearchPage> = types
+        .iter()
+        .map(|&search_type| SearchPage {
+            search_type,
+            results: Vec::new(),
+            offset,
+            total: 0,
+            next: false,
+        })
+        .collect();
+    // Types that filled every page so far and still have a `next` one.
+    let mut pending: Vec<SearchType> = types.to_vec();
+    let mut fetched = 0;
+
+    while !pending.is_empty() && fetched < limit {
+        let page_limit = (limit - fetched).min(MAX_SEARCH_PAGE);
+        let mut data = search_request(token, query, &pending, page_limit, offset + fetched).await?;
+        pending.retain(|&search_type| {
+            let Some(paging) = data.take(search_type) else {
+                return false;
+            };
+            let full = paging.items.len() as u32 == page_limit && paging.next.is_some();
+            if let Some(page) = pages.iter_mut().find(|p| p.search_type == search_type) {
+                page.total = paging.total;
+                page.next = paging.next.is_some();
+                page.results.extend(paging.items);
+            }
+            full
+        });
+        fetched += page_limit;
+    }
+    Ok(pages)
+}
+
+async fn search_request(
+    token: &str,
+    query: &str,
+    types: &[SearchType],
+    limit: u32,
+    offset: u32,
+) -> Result<SearchResponse> {
+    let type_list = types
+        .iter()
+        .map(|t| t.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    let resp = client()
+        .get(format!("{}/search", base_url()))
         .bearer_auth(token)
         .query(&[
             ("q", query),
-            ("type", search_type.as_str()),
+            ("type", &type_list),
             ("limit", &limit.to_string()),
+            ("offset", &offset.to_string()),
         ])
         .send()
         .await
@@ -128,112 +254,596 @@ pub async fn search(
     if !resp.status().is_success() {
         let status = resp.status();
         let body = resp.text().await.unwrap_or_default();
-        bail!("Search failed ({status}): {body}");
-    }
-
-    let data: SearchResponse = resp.json().await.context("Failed to parse search response")?;
-
-    let results = match search_type {
-        SearchType::Track => data
-            .tracks
-            .map(|t| {
-                t.items
-                    .into_iter()
-                    .map(|item| {
-                        let artists = item
-                            .artists
-                            .iter()
-                            .map(|a| a.name.as_str())
-                            .collect::<Vec<_>>()
-                            .join(", ");
-                        SearchResult {
-                            name: item.name,
-                            uri: item.uri,
-                            detail: format!("{artists} • {}", item.album.name),
-                        }
-                    })
-                    .collect()
-            })
-            .unwrap_or_default(),
-
-        SearchType::Album => data
-            .albums
-            .map(|a| {
-                a.items
-                    .into_iter()
-                    .map(|item| {
-                        let artists = item
-                            .artists
-                            .iter()
-                            .map(|a| a.name.as_str())
-                            .collect::<Vec<_>>()
-                            .join(", ");
-                        let year = item
-                            .release_date
-                            .as_deref()
-                            .and_then(|d| d.split('-').next())
-                            .unwrap_or("????");
-                        SearchResult {
-                            name: item.name,
-                            uri: item.uri,
-                            detail: format!("{artists} ({year})"),
-                        }
-                    })
-                    .collect()
-            })
-            .unwrap_or_default(),
-
-        SearchType::Artist => data
-            .artists
-            .map(|a| {
-                a.items
-                    .into_iter()
-                    .map(|item| {
-                        let genres = item
-                            .genres
-                            .as_deref()
-                            .unwrap_or(&[])
-                            .iter()
-                            .take(3)
-                            .map(|s| s.as_str())
-                            .collect::<Vec<_>>()
-                            .join(", ");
-                        let detail = if genres.is_empty() {
-                            "Artist".to_string()
-                        } else {
-                            genres
-                        };
-                        SearchResult {
-                            name: item.name,
-                            uri: item.uri,
-                            detail,
-                        }
-                    })
-                    .collect()
-            })
-            .unwrap_or_default(),
-
-        SearchType::Playlist => data
-            .playlists
-            .map(|p| {
-                p.items
-                    .into_iter()
-                    .map(|item| {
-                        let owner = item
-                            .owner
-                            .display_name
-                            .as_deref()
-                            .unwrap_or("Unknown");
-                        SearchResult {
-                            name: item.name,
-                            uri: item.uri,
-                            detail: format!("by {owner} • {} tracks", item.tracks.total),
-                        }
-                    })
-                    .collect()
-            })
-            .unwrap_or_default(),
+        return Err(fail(ErrorKind::Api, format!("Search failed ({status}): {body}")));
+    }
+
+    resp.json().await.context("Failed to parse search response")
+}
+
+fn join_artists(artists: &[ArtistRef]) -> String {
+    artists
+        .iter()
+        .map(|a| a.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl SearchResponse {
+    /// Moves out the page for one type, if Spotify sent one.
+    fn take(&mut self, search_type: SearchType) -> Option<Paging<SearchResult>> {
+        match search_type {
+            SearchType::Track => self.tracks.take().map(|page| {
+                page.map(|item| SearchResult {
+                    detail: format!("{} • {}", join_artists(&item.artists), item.album.name),
+                    name: item.name,
+                    uri: item.uri,
+                })
+            }),
+
+            SearchType::Album => self.albums.take().map(|page| {
+                page.map(|item| {
+                    let year = item
+                        .release_date
+                        .as_deref()
+                        .and_then(|d| d.split('-').next())
+                        .unwrap_or("????");
+                    SearchResult {
+                        detail: format!("{} ({year})", join_artists(&item.artists)),
+                        name: item.name,
+                        uri: item.uri,
+                    }
+                })
+            }),
+
+            SearchType::Artist => self.artists.take().map(|page| {
+                page.map(|item| {
+                    let genres = item
+                        .genres
+                        .as_deref()
+                        .unwrap_or(&[])
+                        .iter()
+                        .take(3)
+                        .map(|s| s.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let detail = if genres.is_empty() {
+                        "Artist".to_string()
+                    } else {
+                        genres
+                    };
+                    SearchResult {
+                        name: item.name,
+                        uri: item.uri,
+                        detail,
+                    }
+                })
+            }),
+
+            SearchType::Playlist => self.playlists.take().map(|page| {
+                page.map(|item| {
+                    let owner = item
+                        .owner
+                        .display_name
+                        .as_deref()
+                        .unwrap_or("Unknown");
+                    SearchResult {
+                        detail: format!("by {owner} • {} tracks", item.tracks.total),
+                        name: item.name,
+                        uri: item.uri,
+                    }
+                })
+            }),
+        }
+    }
+}
+
+/// Appends a track or episode to the user's playback queue. Needs a
+/// user-scoped token.
+pub async fn add_to_queue(token: &str, uri: &str) -> Result<()> {
+    let resp = client()
+        .post(format!("{}/me/player/queue", base_url()))
+        .bearer_auth(token)
+        .query(&[("uri", uri)])
+        .header("Content-Length", "0")
+        .send()
+        .await
+        .context("Failed to add to the Spotify queue")?;
+
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        let kind = if status == reqwest::StatusCode::NOT_FOUND {
+            ErrorKind::NoDevice
+        } else {
+            ErrorKind::Api
+        };
+        return Err(fail(kind, format!("Queueing failed ({status}): {body}")));
+    }
+    Ok(())
+}
+
+/// A track or episode in the playback queue.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueItem {
+    pub name: String,
+    pub uri: String,
+    /// Artists for a track, the show for an episode.
+    pub detail: String,
+    pub duration_ms: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Queue {
+    pub currently_playing: Option<QueueItem>,
+    pub queue: Vec<QueueItem>,
+}
+
+#[derive(Deserialize)]
+struct QueueResponse {
+    currently_playing: Option<QueueEntry>,
+    #[serde(default)]
+    queue: Vec<QueueEntry>,
+}
+
+#[derive(Deserialize)]
+struct QueueEntry {
+    name: String,
+    uri: String,
+    duration_ms: i64,
+    #[serde(default)]
+    artists: Vec<ArtistRef>,
+    show: Option<ArtistRef>,
+}
+
+impl From<QueueEntry> for QueueItem {
+    fn from(entry: QueueEntry) -> Self {
+        let detail = match entry.show {
+            Some(show) => show.name,
+            None => entry
+                .artists
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        Self {
+            name: entry.name,
+            uri: entry.uri,
+            detail,
+            duration_ms: entry.duration_ms,
+        }
+    }
+}
+
+/// What's playing and what's up next. Needs a user-scoped token.
+pub async fn get_queue(token: &str) -> Result<Queue> {
+    let resp = client()
+        .get(format!("{}/me/player/queue", base_url()))
+        .bearer_auth(token)
+        .send()
+        .await
+        .context("Failed to fetch the Spotify queue")?;
+
+    let status = resp.status();
+    if status == reqwest::StatusCode::NO_CONTENT {
+        return Ok(Queue {
+            currently_playing: None,
+            queue: Vec::new(),
+        });
+    }
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        let kind = if status == reqwest::StatusCode::NOT_FOUND {
+            ErrorKind::NoDevice
+        } else {
+            ErrorKind::Api
+        };
+        return Err(fail(kind, format!("Fetching the queue failed ({status}): {body}")));
+    }
+
+    let data: QueueResponse = resp.json().await.context("Failed to parse the queue")?;
+    Ok(Queue {
+        currently_playing: data.currently_playing.map(QueueItem::from),
+        queue: data.queue.into_iter().map(QueueItem::from).collect(),
+    })
+}
+
+#[derive(Deserialize)]
+struct AlbumTracksPage {
+    items: Vec<AlbumTrack>,
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AlbumTrack {
+    name: String,
+    uri: String,
+    artists: Vec<ArtistRef>,
+}
+
+/// Every track of `album_uri`, in album order.
+pub async fn album_tracks(token: &str, album_uri: &str) -> Result<Vec<SearchResult>> {
+    const PAGE: usize = 50;
+    let id = album_uri.strip_prefix("spotify:album:").unwrap_or(album_uri);
+    let mut tracks = Vec::new();
+    loop {
+        let resp = client()
+            .get(format!("{}/albums/{id}/tracks", base_url()))
+            .bearer_auth(token)
+            .query(&[("limit", PAGE), ("offset", tracks.len())])
+            .send()
+            .await
+            .context("Failed to fetch album tracks")?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(fail(ErrorKind::Api, format!("Album lookup failed ({status}): {body}")));
+        }
+        let page: AlbumTracksPage = resp.json().await.context("Failed to parse album tracks")?;
+        let done = page.next.is_none() || page.items.is_empty();
+        tracks.extend(page.items.into_iter().map(|item| SearchResult {
+            detail: item
+                .artists
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            name: item.name,
+            uri: item.uri,
+        }));
+        if done {
+            return Ok(tracks);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TrackAlbum {
+    album: AlbumImages,
+}
+
+#[derive(Deserialize)]
+struct AlbumImages {
+    id: String,
+    images: Vec<Image>,
+}
+
+#[derive(Deserialize)]
+struct Image {
+    url: String,
+    width: Option<u32>,
+}
+
+/// Album cover for a `spotify:track:` URI as `(album_id, image_url)`,
+/// preferring the image closest to 300px. `None` for anything but tracks.
+pub async fn album_art(token: &str, track_uri: &str) -> Result<Option<(String, String)>> {
+    let Some(id) = track_uri.strip_prefix("spotify:track:") else {
+        return Ok(None);
     };
+    let resp = client()
+        .get(format!("{}/tracks/{id}", base_url()))
+        .bearer_auth(token)
+        .send()
+        .await
+        .context("Failed to fetch track details")?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        return Err(fail(ErrorKind::Api, format!("Track lookup failed ({status})")));
+    }
+    let track: TrackAlbum = resp.json().await.context("Failed to parse track details")?;
+    let image = track
+        .album
+        .images
+        .into_iter()
+        .min_by_key(|image| image.width.unwrap_or(0).abs_diff(300));
+    Ok(image.map(|image| (track.album.id, image.url)))
+}
+
+#[derive(Deserialize)]
+struct TrackPlace {
+    track_number: u32,
+    disc_number: u32,
+    album: AlbumSize,
+}
+
+#[derive(Deserialize)]
+struct AlbumSize {
+    total_tracks: u32,
+}
+
+/// Whether `track_uri` is the last track of its album. Each disc numbers
+/// its tracks from 1, so this only recognises the end of single-disc albums
+/// and is false for later discs, episodes and anything else it can't tell.
+pub async fn ends_album(token: &str, track_uri: &str) -> Result<bool> {
+    let Some(id) = track_uri.strip_prefix("spotify:track:") else {
+        return Ok(false);
+    };
+    let resp = client()
+        .get(format!("{}/tracks/{id}", base_url()))
+        .bearer_auth(token)
+        .send()
+        .await
+        .context("Failed to fetch track details")?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        return Err(fail(ErrorKind::Api, format!("Track lookup failed ({status})")));
+    }
+    let track: TrackPlace = resp.json().await.context("Failed to parse track details")?;
+    Ok(track.disc_number == 1 && track.track_number == track.album.total_tracks)
+}
+
+#[derive(Deserialize)]
+struct PlayerContext {
+    item: Option<UriRef>,
+    context: Option<UriRef>,
+}
+
+#[derive(Deserialize)]
+struct UriRef {
+    uri: String,
+}
+
+/// The album, playlist or artist the account is playing `track_uri` from,
+/// per `/me/player`. Needs a user token. `None` when nothing is playing or
+/// the account is on another track.
+pub async fn playing_context(token: &str, track_uri: &str) -> Result<Option<String>> {
+    let resp = client()
+        .get(format!("{}/me/player", base_url()))
+        .bearer_auth(token)
+        .send()
+        .await
+        .context("Failed to fetch playback state")?;
+    if resp.status() == reqwest::StatusCode::NO_CONTENT {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        let status = resp.status();
+        return Err(fail(ErrorKind::Api, format!("Playback state lookup failed ({status})")));
+    }
+    let state: PlayerContext = resp.json().await.context("Failed to parse playback state")?;
+    if state.item.is_none_or(|item| item.uri != track_uri) {
+        return Ok(None);
+    }
+    Ok(state.context.map(|c| c.uri))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Stub, env_lock, route};
+
+    /// A `tracks` or `albums` paging object of `count` items from `first` on.
+    fn paging(kind: &str, first: u32, count: u32, total: u32, next: bool) -> serde_json::Value {
+        let items: Vec<serde_json::Value> = (first..first + count)
+            .map(|i| {
+                serde_json::json!({
+                    "name": format!("{kind} {i}"),
+                    "uri": format!("spotify:{kind}:{i}"),
+                    "artists": [{ "name": "Miles Davis" }],
+                    "album": { "name": "Kind of Blue" },
+                    "release_date": "1959-08-17",
+                })
+            })
+            .collect();
+        let next = next.then_some("https://api.spotify.com/v1/search?offset=more");
+        serde_json::json!({ "items": items, "total": total, "next": next })
+    }
+
+    fn page(results: u32, offset: u32, total: u32, next: bool) -> SearchPage {
+        SearchPage {
+            search_type: SearchType::Track,
+            results: (0..results)
+                .map(|i| SearchResult {
+                    name: format!("track {i}"),
+                    uri: format!("spotify:track:{i}"),
+                    detail: String::new(),
+                })
+                .collect(),
+            offset,
+            total,
+            next,
+        }
+    }
+
+    fn paths(stub: &Stub) -> Vec<String> {
+        stub.requests().into_iter().map(|r| r.path).collect()
+    }
+
+    #[tokio::test]
+    async fn follows_next_across_pages_up_to_the_limit() {
+        let _env = env_lock().await;
+        let first = serde_json::json!({ "tracks": paging("track", 0, 50, 312, true) });
+        let second = serde_json::json!({ "tracks": paging("track", 50, 20, 312, true) });
+        let stub = Stub::serve_api(vec![
+            route("GET", "/search?q=jazz&type=track&limit=50&offset=0", 200, first.to_string()),
+            route("GET", "/search?q=jazz&type=track&limit=20&offset=50", 200, second.to_string()),
+        ])
+        .await;
+
+        let pages = search_types("t", "jazz", &[SearchType::Track], 70, 0).await.unwrap();
+        assert_eq!(paths(&stub).len(), 2, "stops once --limit results are in");
+        let [page] = pages.as_slice() else { panic!("one page per type") };
+        assert_eq!(page.results.len(), 70);
+        assert_eq!(page.results[69].uri, "spotify:track:69");
+        assert_eq!((page.offset, page.total), (0, 312));
+        assert!(page.has_more());
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_limit_without_asking_for_more() {
+        let _env = env_lock().await;
+        let body = serde_json::json!({ "tracks": paging("track", 40, 20, 312, true) });
+        let stub = Stub::serve_api(vec![route("GET", "/search", 200, body.to_string())]).await;
+
+        let pages = search_types("t", "jazz", &[SearchType::Track], 20, 40).await.unwrap();
+        assert_eq!(paths(&stub), vec!["/search?q=jazz&type=track&limit=20&offset=40"]);
+        assert_eq!(pages[0].results.len(), 20);
+        assert_eq!(pages[0].offset, 40);
+    }
+
+    #[tokio::test]
+    async fn stops_when_a_page_has_no_next() {
+        let _env = env_lock().await;
+        let body = serde_json::json!({ "tracks": paging("track", 0, 50, 400, false) });
+        let stub = Stub::serve_api(vec![route("GET", "/search", 200, body.to_string())]).await;
+
+        let pages = search_types("t", "jazz", &[SearchType::Track], 100, 0).await.unwrap();
+        assert_eq!(paths(&stub).len(), 1);
+        assert_eq!(pages[0].results.len(), 50);
+        assert!(!pages[0].has_more(), "the total alone doesn't promise a next page");
+    }
+
+    #[tokio::test]
+    async fn searches_several_types_in_one_request() {
+        let _env = env_lock().await;
+        // Albums run out on the first page; tracks carry on alone.
+        let first = serde_json::json!({
+            "tracks": paging("track", 0, 50, 312, true),
+            "albums": paging("album", 0, 8, 8, false),
+        });
+        let second = serde_json::json!({ "tracks": paging("track", 50, 10, 312, true) });
+        let stub = Stub::serve_api(vec![
+            route("GET", "/search?q=jazz&type=track%2Calbum&limit=50&offset=0", 200, first.to_string()),
+            route("GET", "/search?q=jazz&type=track&limit=10&offset=50", 200, second.to_string()),
+        ])
+        .await;
+
+        let types = [SearchType::Track, SearchType::Album];
+        let pages = search_types("t", "jazz", &types, 60, 0).await.unwrap();
+        assert_eq!(paths(&stub).len(), 2);
+        let kinds: Vec<SearchType> = pages.iter().map(|p| p.search_type).collect();
+        assert_eq!(kinds, types);
+        assert_eq!(pages[0].results.len(), 60);
+        assert_eq!(pages[0].total, 312);
+        assert!(pages[0].has_more());
+        assert_eq!(pages[1].results.len(), 8);
+        assert_eq!(pages[1].results[0].detail, "Miles Davis (1959)");
+        assert!(!pages[1].has_more());
+    }
 
-    Ok(results)
+    #[test]
+    fn has_more_needs_a_next_page_within_the_total() {
+        assert!(page(20, 20, 312, true).has_more());
+        assert!(!page(20, 292, 312, true).has_more(), "total equals offset + items");
+        assert!(!page(20, 20, 312, false).has_more(), "no next page");
+        assert!(!page(20, 980, 5000, true).has_more(), "Spotify stops at 1000");
+        assert!(!page(0, 0, 0, false).has_more());
+    }
+
+    fn track(track_number: u32, disc_number: u32, total_tracks: u32) -> String {
+        format!(
+            r#"{{"track_number": {track_number}, "disc_number": {disc_number}, "album": {{"total_tracks": {total_tracks}}}}}"#
+        )
+    }
+
+    #[tokio::test]
+    async fn spots_the_last_track_of_a_single_disc_album() {
+        let _env = env_lock().await;
+        let stub = Stub::serve_api(vec![
+            route("GET", "/tracks/last", 200, track(9, 1, 9)),
+            route("GET", "/tracks/middle", 200, track(4, 1, 9)),
+            route("GET", "/tracks/disc2", 200, track(9, 2, 18)),
+            route("GET", "/tracks/gone", 404, "{}"),
+        ])
+        .await;
+
+        assert!(ends_album("t", "spotify:track:last").await.unwrap());
+        assert!(!ends_album("t", "spotify:track:middle").await.unwrap());
+        assert!(!ends_album("t", "spotify:track:disc2").await.unwrap());
+        assert!(!ends_album("t", "spotify:episode:x").await.unwrap());
+        assert!(ends_album("t", "spotify:track:gone").await.is_err());
+        assert_eq!(stub.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn reads_the_context_of_the_same_track() {
+        let _env = env_lock().await;
+        let playing = r#"{"item": {"uri": "spotify:track:a"}, "context": {"uri": "spotify:album:lp"}}"#;
+        let _stub = Stub::serve_api(vec![route("GET", "/me/player", 200, playing)]).await;
+        let context = playing_context("t", "spotify:track:a").await.unwrap();
+        assert_eq!(context.as_deref(), Some("spotify:album:lp"));
+        assert_eq!(playing_context("t", "spotify:track:b").await.unwrap(), None);
+
+        let _stub = Stub::serve_api(vec![route("GET", "/me/player", 204, "")]).await;
+        assert_eq!(playing_context("t", "spotify:track:a").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn reads_the_queue() {
+        let _env = env_lock().await;
+        let body = r#"{
+            "currently_playing": {"name": "So What", "uri": "spotify:track:a", "duration_ms": 545000,
+                "artists": [{"name": "Miles Davis"}, {"name": "John Coltrane"}]},
+            "queue": [
+                {"name": "Freddie Freeloader", "uri": "spotify:track:b", "duration_ms": 589000,
+                    "artists": [{"name": "Miles Davis"}]},
+                {"name": "Episode 12", "uri": "spotify:episode:e", "duration_ms": 3600000,
+                    "show": {"name": "The Podcast"}}
+            ]
+        }"#;
+        let _stub = Stub::serve_api(vec![route("GET", "/me/player/queue", 200, body)]).await;
+
+        let queue = get_queue("t").await.unwrap();
+        let playing = queue.currently_playing.unwrap();
+        assert_eq!(playing.name, "So What");
+        assert_eq!(playing.detail, "Miles Davis, John Coltrane");
+        assert_eq!(playing.duration_ms, 545_000);
+        assert_eq!(queue.queue.len(), 2);
+        assert_eq!(queue.queue[0].uri, "spotify:track:b");
+        assert_eq!(queue.queue[1].detail, "The Podcast");
+    }
+
+    #[tokio::test]
+    async fn empty_and_missing_queues() {
+        let _env = env_lock().await;
+        let _stub = Stub::serve_api(vec![route("GET", "/me/player/queue", 204, "")]).await;
+        let queue = get_queue("t").await.unwrap();
+        assert!(queue.currently_playing.is_none());
+        assert!(queue.queue.is_empty());
+
+        let body = r#"{"currently_playing": null, "queue": []}"#;
+        let _stub = Stub::serve_api(vec![route("GET", "/me/player/queue", 200, body)]).await;
+        assert!(get_queue("t").await.unwrap().currently_playing.is_none());
+
+        let _stub = Stub::serve_api(vec![route("GET", "/me/player/queue", 404, "no device")]).await;
+        let err = get_queue("t").await.unwrap_err();
+        assert_eq!(crate::output::kind_of(&err), ErrorKind::NoDevice);
+    }
+
+    #[tokio::test]
+    async fn follows_album_track_pages() {
+        let _env = env_lock().await;
+        let first = r#"{"items": [
+            {"name": "One", "uri": "spotify:track:1", "artists": [{"name": "A"}, {"name": "B"}]},
+            {"name": "Two", "uri": "spotify:track:2", "artists": [{"name": "A"}]}
+        ], "next": "https://api.spotify.com/v1/albums/lp/tracks?offset=2&limit=50"}"#;
+        let last = r#"{"items": [
+            {"name": "Three", "uri": "spotify:track:3", "artists": [{"name": "A"}]}
+        ], "next": null}"#;
+        let stub = Stub::serve_api(vec![
+            route("GET", "/albums/lp/tracks?limit=50&offset=0", 200, first),
+            route("GET", "/albums/lp/tracks?limit=50&offset=2", 200, last),
+        ])
+        .await;
+
+        let tracks = album_tracks("t", "spotify:album:lp").await.unwrap();
+        let names: Vec<&str> = tracks.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["One", "Two", "Three"]);
+        assert_eq!(tracks[0].detail, "A, B");
+        assert_eq!(tracks[2].uri, "spotify:track:3");
+        assert_eq!(stub.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn stops_on_an_empty_album_page() {
+        let _env = env_lock().await;
+        let empty = r#"{"items": [], "next": "https://api.spotify.com/v1/albums/lp/tracks?offset=0"}"#;
+        let stub = Stub::serve_api(vec![route("GET", "/albums/lp/tracks", 200, empty)]).await;
+        assert!(album_tracks("t", "lp").await.unwrap().is_empty());
+        assert_eq!(stub.requests().len(), 1);
+
+        let _stub = Stub::serve_api(vec![route("GET", "/albums/lp/tracks", 404, "nope")]).await;
+        let err = album_tracks("t", "lp").await.unwrap_err();
+        assert_eq!(crate::output::kind_of(&err), ErrorKind::Api);
+    }
 }
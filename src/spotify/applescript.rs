@@ -1,6 +1,9 @@
 use anyhow::{Context, Result, bail};
 use std::process::Command;
 
+use super::player::NowPlayingInfo;
+use crate::output::{ErrorKind, fail};
+
 fn run_osascript(script: &str) -> Result<String> {
     let output = Command::new("osascript")
         .arg("-e")
@@ -12,7 +15,10 @@ fn run_osascript(script: &str) -> Result<String> {
         Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        bail!("osascript failed: {}", stderr.trim());
+        Err(fail(
+            ErrorKind::Player,
+            format!("osascript failed: {}", stderr.trim()),
+        ))
     }
 }
 
@@ -20,11 +26,15 @@ fn tell_spotify(command: &str) -> Result<String> {
     run_osascript(&format!("tell application \"Spotify\" to {command}"))
 }
 
-pub fn ensure_running() -> Result<()> {
+pub fn is_running() -> Result<bool> {
     let running = run_osascript(
         "tell application \"System Events\" to (name of processes) contains \"Spotify\"",
     )?;
-    if running != "true" {
+    Ok(running == "true")
+}
+
+pub fn ensure_running() -> Result<()> {
+    if !is_running()? {
         run_osascript("tell application \"Spotify\" to activate")?;
         std::thread::sleep(std::time::Duration::from_secs(2));
     }
@@ -37,6 +47,12 @@ pub fn play_uri(uri: &str) -> Result<()> {
     Ok(())
 }
 
+pub fn play_in_context(context: &str, track: &str) -> Result<()> {
+    ensure_running()?;
+    tell_spotify(&format!("play track \"{track}\" in context \"{context}\""))?;
+    Ok(())
+}
+
 pub fn pause() -> Result<()> {
     tell_spotify("pause")?;
     Ok(())
@@ -73,6 +89,12 @@ pub fn set_volume(vol: i32) -> Result<()> {
     Ok(())
 }
 
+pub fn seek(position_ms: i64) -> Result<()> {
+    let secs = position_ms as f64 / 1000.0;
+    tell_spotify(&format!("set player position to {secs:.3}"))?;
+    Ok(())
+}
+
 pub fn get_shuffle() -> Result<bool> {
     let val = tell_spotify("shuffling")?;
     Ok(val == "true")
@@ -93,17 +115,6 @@ pub fn set_repeat(on: bool) -> Result<()> {
     Ok(())
 }
 
-#[allow(dead_code)]
-pub struct NowPlayingInfo {
-    pub track_name: String,
-    pub artist: String,
-    pub album: String,
-    pub track_id: String,
-    pub duration_ms: i64,
-    pub position_ms: i64,
-    pub is_playing: bool,
-}
-
 pub fn get_now_playing() -> Result<NowPlayingInfo> {
     let script = r#"
 tell application "Spotify"
@@ -123,7 +134,7 @@ end tell
     let output = run_osascript(script)?;
 
     if output == "STOPPED" {
-        bail!("No track is currently playing");
+        return Err(fail(ErrorKind::NothingPlaying, "No track is currently playing"));
     }
 
     let parts: Vec<&str> = output.split("|||").collect();
@@ -148,3 +159,17 @@ end tell
         is_playing: parts[6] == "playing",
     })
 }
+
+/// Escapes a string for an AppleScript string literal.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+pub fn display_notification(title: &str, body: &str) -> Result<()> {
+    run_osascript(&format!(
+        "display notification {} with title {}",
+        quote(body),
+        quote(title)
+    ))?;
+    Ok(())
+}
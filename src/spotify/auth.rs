@@ -1,14 +1,47 @@
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand::Rng;
+use rand::distr::Alphanumeric;
+use reqwest::Url;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
 
 use crate::config::Config;
+use crate::output::{ErrorKind, fail};
+
+const ACCOUNTS_URL: &str = "https://accounts.spotify.com";
+
+/// How long `spot auth login` waits for the browser to come back.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Redirect URI used by `spot auth login`; it must be registered on the app
+/// in the Spotify dashboard.
+pub const DEFAULT_REDIRECT_URI: &str = "http://127.0.0.1:8888/callback";
+
+/// Scopes requested by `spot auth login`.
+const SCOPES: &[&str] = &[
+    "user-read-playback-state",
+    "user-modify-playback-state",
+    "user-read-currently-playing",
+    "user-library-read",
+    "playlist-read-private",
+];
 
 #[derive(Debug, Deserialize)]
 struct TokenResponse {
     access_token: String,
     expires_in: u64,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    scope: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -17,26 +50,56 @@ struct CachedToken {
     expires_at: u64,
 }
 
+/// A user session from the Authorization Code + PKCE grant.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct UserToken {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: u64,
+    #[serde(default)]
+    pub scope: String,
+}
+
+impl UserToken {
+    fn is_fresh(&self) -> bool {
+        now_secs() < self.expires_at.saturating_sub(60)
+    }
+}
+
+/// `SPOT_ACCOUNTS_URL` overrides the accounts service, e.g. to point at a mock server.
+fn accounts_url() -> String {
+    std::env::var("SPOT_ACCOUNTS_URL")
+        .unwrap_or_else(|_| ACCOUNTS_URL.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn missing_credentials(field: &str) -> anyhow::Error {
+    fail(
+        ErrorKind::Config,
+        format!("No {field} configured. Run `spot auth` to set up credentials."),
+    )
+}
+
 fn token_cache_path() -> Result<std::path::PathBuf> {
     Ok(Config::config_dir()?.join("token.json"))
 }
 
-fn now_secs() -> u64 {
+fn user_token_path() -> Result<std::path::PathBuf> {
+    Ok(Config::config_dir()?.join("user_token.json"))
+}
+
+pub fn now_secs() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs()
 }
 
-fn load_cached_token() -> Option<String> {
+fn load_cached_token() -> Option<CachedToken> {
     let path = token_cache_path().ok()?;
     let contents = fs::read_to_string(path).ok()?;
-    let cached: CachedToken = serde_json::from_str(&contents).ok()?;
-    if now_secs() < cached.expires_at.saturating_sub(60) {
-        Some(cached.access_token)
-    } else {
-        None
-    }
+    serde_json::from_str(&contents).ok()
 }
 
 fn save_cached_token(token: &str, expires_in: u64) -> Result<()> {
@@ -53,25 +116,63 @@ fn save_cached_token(token: &str, expires_in: u64) -> Result<()> {
     Ok(())
 }
 
-pub async fn get_token(config: &Config) -> Result<String> {
-    if let Some(token) = load_cached_token() {
-        return Ok(token);
+/// Expiry (unix seconds) of the cached client_credentials token, if any.
+pub fn cached_token_expiry() -> Option<u64> {
+    load_cached_token().map(|t| t.expires_at)
+}
+
+pub fn load_user_token() -> Option<UserToken> {
+    let path = user_token_path().ok()?;
+    let contents = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Writes the session owner-only, since the refresh token stays valid
+/// until revoked, and via a rename so a crash can't leave half a file.
+fn save_user_token(token: &UserToken) -> Result<()> {
+    let path = user_token_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
     }
+    let json = serde_json::to_string(token)?;
+    let tmp = path.with_extension("json.tmp");
+    // A leftover from an earlier crash would keep its old permissions.
+    let _ = fs::remove_file(&tmp);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&tmp)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, json.as_bytes()))
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
 
-    let client_id = config
-        .client_id
-        .as_deref()
-        .context("No client_id configured. Run `spot auth` to set up credentials.")?;
-    let client_secret = config
-        .client_secret
-        .as_deref()
-        .context("No client_secret configured. Run `spot auth` to set up credentials.")?;
+/// Forgets the stored user session. Returns whether there was one.
+pub fn remove_user_token() -> Result<bool> {
+    let path = user_token_path()?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    fs::remove_file(&path)
+        .with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(true)
+}
 
+async fn request_token(
+    form: &[(&str, &str)],
+    basic_auth: Option<(&str, &str)>,
+) -> Result<TokenResponse> {
     let client = reqwest::Client::new();
-    let resp = client
-        .post("https://accounts.spotify.com/api/token")
-        .form(&[("grant_type", "client_credentials")])
-        .basic_auth(client_id, Some(client_secret))
+    let mut req = client
+        .post(format!("{}/api/token", accounts_url()))
+        .form(form);
+    if let Some((id, secret)) = basic_auth {
+        req = req.basic_auth(id, Some(secret));
+    }
+    let resp = req
         .send()
         .await
         .context("Failed to request access token from Spotify")?;
@@ -79,15 +180,442 @@ pub async fn get_token(config: &Config) -> Result<String> {
     if !resp.status().is_success() {
         let status = resp.status();
         let body = resp.text().await.unwrap_or_default();
-        bail!("Token request failed ({status}): {body}. Check your credentials with `spot auth`.");
+        return Err(fail(
+            ErrorKind::Auth,
+            format!("Token request failed ({status}): {body}. Check your credentials with `spot auth`."),
+        ));
     }
 
-    let token_resp: TokenResponse = resp
-        .json()
-        .await
-        .context("Failed to parse token response")?;
+    resp.json().await.context("Failed to parse token response")
+}
+
+/// Returns an access token for the Web API. A logged-in user session is
+/// preferred; otherwise the app's client_credentials grant is used.
+pub async fn get_token(config: &Config) -> Result<String> {
+    Ok(get_token_expiring(config).await?.0)
+}
+
+/// Like [`get_token`], along with when the token expires (unix seconds), for
+/// callers that keep it around.
+pub async fn get_token_expiring(config: &Config) -> Result<(String, u64)> {
+    if load_user_token().is_some() {
+        match get_user_token_expiring(config).await {
+            Ok(token) => return Ok(token),
+            // Without an app secret there's no fallback, and the login's
+            // own error says to log in again.
+            Err(e) if config.client_secret.is_none() => return Err(e),
+            Err(_) => {}
+        }
+    }
+    app_token(config).await
+}
+
+/// Keeps an access token in memory until a minute before it expires, so
+/// long-running processes don't go back to disk for every request.
+#[derive(Default)]
+pub struct TokenCache {
+    /// With its expiry in unix seconds.
+    token: Mutex<Option<(String, u64)>>,
+}
+
+impl TokenCache {
+    /// The cached token while it's fresh; otherwise fetches and keeps a new one.
+    pub async fn get<F>(&self, fetch: impl FnOnce() -> F) -> Result<String>
+    where
+        F: Future<Output = Result<(String, u64)>>,
+    {
+        if let Some((token, expires_at)) = &*self.token.lock().unwrap() {
+            if now_secs() < expires_at.saturating_sub(60) {
+                return Ok(token.clone());
+            }
+        }
+        let (token, expires_at) = fetch().await?;
+        *self.token.lock().unwrap() = Some((token.clone(), expires_at));
+        Ok(token)
+    }
+
+    /// Drops the cached token, e.g. once Spotify has rejected it.
+    pub fn clear(&self) {
+        *self.token.lock().unwrap() = None;
+    }
+}
+
+/// Returns an app-only token from the client_credentials grant.
+pub async fn get_app_token(config: &Config) -> Result<String> {
+    Ok(app_token(config).await?.0)
+}
+
+async fn app_token(config: &Config) -> Result<(String, u64)> {
+    if let Some(cached) = load_cached_token() {
+        if now_secs() < cached.expires_at.saturating_sub(60) {
+            return Ok((cached.access_token, cached.expires_at));
+        }
+    }
+
+    let client_id = config
+        .client_id
+        .as_deref()
+        .ok_or_else(|| missing_credentials("client_id"))?;
+    let client_secret = config
+        .client_secret
+        .as_deref()
+        .ok_or_else(|| missing_credentials("client_secret"))?;
+
+    let token_resp = request_token(
+        &[("grant_type", "client_credentials")],
+        Some((client_id, client_secret)),
+    )
+    .await?;
 
     save_cached_token(&token_resp.access_token, token_resp.expires_in)?;
 
-    Ok(token_resp.access_token)
+    Ok((token_resp.access_token, now_secs() + token_resp.expires_in))
+}
+
+/// Whether a user login (or `SPOT_ACCESS_TOKEN`) is available, without
+/// refreshing anything.
+pub fn has_user_token() -> bool {
+    std::env::var_os("SPOT_ACCESS_TOKEN").is_some() || load_user_token().is_some()
+}
+
+/// Returns a user-scoped access token, refreshing it silently when it has
+/// expired. `SPOT_ACCESS_TOKEN` takes precedence when set.
+pub async fn get_user_token(config: &Config) -> Result<String> {
+    Ok(get_user_token_expiring(config).await?.0)
+}
+
+/// Like [`get_user_token`], along with when the token expires (unix seconds).
+pub async fn get_user_token_expiring(config: &Config) -> Result<(String, u64)> {
+    if let Ok(token) = std::env::var("SPOT_ACCESS_TOKEN") {
+        // Managed outside spot, so never considered stale.
+        return Ok((token, u64::MAX));
+    }
+
+    let token = load_user_token().ok_or_else(|| {
+        fail(
+            ErrorKind::Auth,
+            "This needs a Spotify login. Run `spot auth login` first.",
+        )
+    })?;
+    let token = if token.is_fresh() {
+        token
+    } else {
+        refresh_user_token(config, token).await?
+    };
+    Ok((token.access_token, token.expires_at))
+}
+
+async fn refresh_user_token(config: &Config, token: UserToken) -> Result<UserToken> {
+    let client_id = config
+        .client_id
+        .as_deref()
+        .ok_or_else(|| missing_credentials("client_id"))?;
+
+    let resp = request_token(
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &token.refresh_token),
+            ("client_id", client_id),
+        ],
+        None,
+    )
+    .await
+    .context("Failed to refresh the Spotify login. Run `spot auth login` again.")?;
+
+    let refreshed = UserToken {
+        access_token: resp.access_token,
+        // Spotify may rotate the refresh token; keep the old one otherwise.
+        refresh_token: resp.refresh_token.unwrap_or(token.refresh_token),
+        expires_at: now_secs() + resp.expires_in,
+        scope: resp.scope.unwrap_or(token.scope),
+    };
+    save_user_token(&refreshed)?;
+    Ok(refreshed)
+}
+
+fn random_string(len: usize) -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// Runs the Authorization Code + PKCE flow: prints and opens the authorize
+/// URL, waits for the redirect on the loopback listener, exchanges the code
+/// and stores the resulting session.
+pub async fn login(config: &Config) -> Result<UserToken> {
+    let client_id = config
+        .client_id
+        .as_deref()
+        .ok_or_else(|| missing_credentials("client_id"))?;
+    let redirect_uri = config
+        .redirect_uri
+        .as_deref()
+        .unwrap_or(DEFAULT_REDIRECT_URI);
+    let redirect = Url::parse(redirect_uri)
+        .with_context(|| format!("Invalid redirect URI: {redirect_uri}"))?;
+    let port = redirect
+        .port_or_known_default()
+        .context("Redirect URI has no port")?;
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .with_context(|| format!("Failed to listen on 127.0.0.1:{port}"))?;
+
+    let verifier = random_string(64);
+    let state = random_string(16);
+    let scopes = SCOPES.join(" ");
+    let mut authorize = Url::parse(&format!("{}/authorize", accounts_url()))?;
+    authorize
+        .query_pairs_mut()
+        .append_pair("client_id", client_id)
+        .append_pair("response_type", "code")
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("code_challenge_method", "S256")
+        .append_pair("code_challenge", &code_challenge(&verifier))
+        .append_pair("state", &state)
+        .append_pair("scope", &scopes);
+
+    // stderr, so `--output json` keeps stdout clean.
+    eprintln!("  Opening {authorize}");
+    open_browser(authorize.as_str());
+
+    let code = wait_for_code(&listener, redirect.path(), &state, LOGIN_TIMEOUT).await?;
+
+    let resp = request_token(
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", client_id),
+            ("code_verifier", &verifier),
+        ],
+        None,
+    )
+    .await?;
+
+    let token = UserToken {
+        access_token: resp.access_token,
+        refresh_token: resp
+            .refresh_token
+            .context("Spotify did not return a refresh token")?,
+        expires_at: now_secs() + resp.expires_in,
+        scope: resp.scope.unwrap_or(scopes),
+    };
+    save_user_token(&token)?;
+    Ok(token)
+}
+
+fn open_browser(url: &str) {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    // Best effort: the URL is printed too, so a missing opener is fine.
+    let _ = std::process::Command::new(opener)
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+}
+
+/// Accepts connections until one hits `path` with a matching `state`, then
+/// returns its authorization code. Gives up after `timeout`.
+async fn wait_for_code(
+    listener: &TcpListener,
+    path: &str,
+    state: &str,
+    timeout: Duration,
+) -> Result<String> {
+    tokio::time::timeout(timeout, accept_code(listener, path, state))
+        .await
+        .map_err(|_| {
+            fail(
+                ErrorKind::Auth,
+                format!(
+                    "Timed out after {}s waiting for the browser to finish logging in. Run `spot auth login` again.",
+                    timeout.as_secs()
+                ),
+            )
+        })?
+}
+
+/// Answers anything that isn't the redirect and keeps waiting, so a stray
+/// request can't end the login. Failed replies are ignored: a browser that
+/// gave up on a favicon fetch has nothing to do with the login.
+async fn accept_code(listener: &TcpListener, path: &str, state: &str) -> Result<String> {
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .context("Failed to accept redirect connection")?;
+        let mut stream = BufReader::new(stream);
+        let mut request_line = String::new();
+        // A connection that never sends its request shouldn't hang the login.
+        let read = tokio::time::timeout(Duration::from_secs(10), stream.read_line(&mut request_line)).await;
+        if !matches!(read, Ok(Ok(_))) {
+            continue;
+        }
+        let stream = stream.get_mut();
+
+        // e.g. "GET /callback?code=...&state=... HTTP/1.1"
+        let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+        let Ok(url) = Url::parse(&format!("http://127.0.0.1{target}")) else {
+            let _ = respond(stream, "400 Bad Request", "Bad request.").await;
+            continue;
+        };
+        if url.path() != path {
+            let _ = respond(stream, "404 Not Found", "Not found.").await;
+            continue;
+        }
+
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+
+        if param("state").as_deref() != Some(state) {
+            let _ = respond(stream, "400 Bad Request", "State mismatch. Please try again.").await;
+            continue;
+        }
+        if let Some(error) = param("error") {
+            let _ = respond(stream, "400 Bad Request", "Login was cancelled.").await;
+            return Err(fail(ErrorKind::Auth, format!("Login failed: {error}")));
+        }
+        let code = param("code").context("Redirect did not include an authorization code")?;
+        let _ = respond(stream, "200 OK", "Logged in to spot. You can close this tab.").await;
+        return Ok(code);
+    }
+}
+
+async fn respond(stream: &mut (impl AsyncWrite + Unpin), status: &str, message: &str) -> Result<()> {
+    let body = format!("<!doctype html><p>{message}</p>");
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::kind_of;
+    use crate::test_support::{ConfigHome, Stub, env_lock, route};
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    async fn listener() -> TcpListener {
+        TcpListener::bind("127.0.0.1:0").await.unwrap()
+    }
+
+    /// Sends `GET target` to `listener`, as the browser would, and reads the
+    /// response on another thread. The request is sent before returning, so
+    /// connections are accepted in the order they're made; the read timeout
+    /// keeps a connection that's never answered from hanging the test.
+    fn redirect(listener: &TcpListener, target: &str) -> std::thread::JoinHandle<String> {
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(stream, "GET {target} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n").unwrap();
+        std::thread::spawn(move || {
+            let mut response = String::new();
+            let _ = std::io::Read::read_to_string(&mut stream, &mut response);
+            response
+        })
+    }
+
+    #[tokio::test]
+    async fn returns_the_code_from_the_redirect() {
+        let listener = listener().await;
+        let stray = redirect(&listener, "/favicon.ico");
+        let browser = redirect(&listener, "/callback?code=abc&state=s1");
+        let code = wait_for_code(&listener, "/callback", "s1", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(code, "abc");
+        assert!(browser.join().unwrap().starts_with("HTTP/1.1 200 OK"));
+        assert!(stray.join().unwrap().starts_with("HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn stray_requests_are_refused_without_ending_the_login() {
+        let listener = listener().await;
+        let forged = redirect(&listener, "/callback?code=evil&state=other");
+        let garbled = redirect(&listener, ":99999/callback");
+        let browser = redirect(&listener, "/callback?code=abc&state=s1");
+        let code = wait_for_code(&listener, "/callback", "s1", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(code, "abc");
+        assert!(forged.join().unwrap().starts_with("HTTP/1.1 400"));
+        assert!(garbled.join().unwrap().starts_with("HTTP/1.1 400"));
+        assert!(browser.join().unwrap().starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_login_is_an_auth_error() {
+        let listener = listener().await;
+        let browser = redirect(&listener, "/callback?error=access_denied&state=s1");
+        let err = wait_for_code(&listener, "/callback", "s1", Duration::from_secs(5)).await.unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::Auth);
+        assert!(browser.join().unwrap().starts_with("HTTP/1.1 400"));
+    }
+
+    #[tokio::test]
+    async fn token_cache_fetches_again_only_when_stale_or_cleared() {
+        let cache = TokenCache::default();
+        let fetches = std::cell::Cell::new(0);
+        let fetch = |expires_at: u64| {
+            fetches.set(fetches.get() + 1);
+            async move { Ok((format!("token-{expires_at}"), expires_at)) }
+        };
+        let fresh = now_secs() + 3600;
+        assert_eq!(cache.get(|| fetch(fresh)).await.unwrap(), format!("token-{fresh}"));
+        assert_eq!(cache.get(|| fetch(0)).await.unwrap(), format!("token-{fresh}"));
+        assert_eq!(fetches.get(), 1);
+
+        cache.clear();
+        assert_eq!(cache.get(|| fetch(30)).await.unwrap(), "token-30");
+        // Expiring within the minute of slack, so the next call fetches.
+        assert_eq!(cache.get(|| fetch(fresh)).await.unwrap(), format!("token-{fresh}"));
+        assert_eq!(fetches.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_the_browser_never_returns() {
+        let listener = listener().await;
+        let err = wait_for_code(&listener, "/callback", "s1", Duration::from_millis(200)).await.unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::Auth);
+    }
+
+    fn expired_login() {
+        let token = UserToken {
+            access_token: "old".to_string(),
+            refresh_token: "refresh".to_string(),
+            expires_at: 0,
+            scope: String::new(),
+        };
+        save_user_token(&token).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_failed_refresh_without_an_app_secret_asks_to_log_in_again() {
+        let _env = env_lock().await;
+        let _home = ConfigHome::new();
+        let stub = Stub::start(vec![route("POST", "/api/token", 400, r#"{"error":"invalid_grant"}"#)]).await;
+        std::env::set_var("SPOT_ACCOUNTS_URL", &stub.url);
+        expired_login();
+
+        let config = Config {
+            client_id: Some("id".to_string()),
+            ..Config::default()
+        };
+        let err = get_token(&config).await.unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::Auth);
+        assert!(format!("{err:#}").contains("spot auth login"), "{err:#}");
+    }
 }
@@ -0,0 +1,416 @@
+use anyhow::{Context, Result};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{api, auth, device};
+use super::player::NowPlayingInfo;
+use crate::config::Config;
+use crate::output::{ErrorKind, fail};
+
+/// Drives playback on any Spotify Connect device through the Web API
+/// `/me/player` endpoints.
+pub struct Connect {
+    client: reqwest::Client,
+    base_url: String,
+    config: Config,
+    /// Name or id of the device to start playback on, instead of the
+    /// active one.
+    device: Option<String>,
+    token: auth::TokenCache,
+}
+
+#[derive(Deserialize)]
+struct PlaybackState {
+    device: Option<Device>,
+    // Absent from /me/player/currently-playing, which shares this shape.
+    #[serde(default)]
+    shuffle_state: bool,
+    #[serde(default)]
+    repeat_state: String,
+    progress_ms: Option<i64>,
+    is_playing: bool,
+    item: Option<PlayingItem>,
+    context: Option<PlaybackContext>,
+}
+
+#[derive(Deserialize)]
+struct PlaybackContext {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct Device {
+    volume_percent: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct PlayingItem {
+    name: String,
+    uri: String,
+    duration_ms: i64,
+    #[serde(default)]
+    artists: Vec<NameRef>,
+    album: Option<NameRef>,
+    // Episodes carry the show instead of artists/album.
+    show: Option<NameRef>,
+}
+
+#[derive(Deserialize)]
+struct NameRef {
+    name: String,
+}
+
+fn no_device() -> anyhow::Error {
+    fail(
+        ErrorKind::NoDevice,
+        "No active Spotify device. Start playback on a device first.",
+    )
+}
+
+fn nothing_playing() -> anyhow::Error {
+    fail(ErrorKind::NothingPlaying, "No track is currently playing")
+}
+
+impl Connect {
+    pub fn new(config: Config) -> Self {
+        Self {
+            client: api::client().clone(),
+            base_url: api::base_url(),
+            config,
+            device: None,
+            token: auth::TokenCache::default(),
+        }
+    }
+
+    pub fn with_device(config: Config, device: String) -> Self {
+        Self {
+            device: Some(device),
+            ..Self::new(config)
+        }
+    }
+
+    /// `/me/player/play`, aimed at the chosen device if there is one.
+    async fn play_path(&self) -> Result<String> {
+        let Some(device) = &self.device else {
+            return Ok("/me/player/play".to_string());
+        };
+        let token = self.token().await?;
+        let id = device::resolve(&token, device).await?;
+        Ok(format!("/me/player/play?device_id={id}"))
+    }
+
+    /// Kept until it expires or Spotify rejects it, so a long-running
+    /// process such as the daemon doesn't re-read the login every request.
+    async fn token(&self) -> Result<String> {
+        self.token.get(|| auth::get_user_token_expiring(&self.config)).await
+    }
+
+    async fn request(&self, method: Method, path: &str) -> Result<RequestBuilder> {
+        let token = self.token().await?;
+        Ok(self
+            .client
+            .request(method, format!("{}{path}", self.base_url))
+            .bearer_auth(token))
+    }
+
+    async fn send(&self, req: RequestBuilder) -> Result<Response> {
+        let resp = req.send().await.context("Failed to reach the Spotify player API")?;
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+        let body = resp.text().await.unwrap_or_default();
+        if status == StatusCode::UNAUTHORIZED {
+            self.token.clear();
+        }
+        Err(match status {
+            StatusCode::NOT_FOUND => no_device(),
+            StatusCode::UNAUTHORIZED => fail(
+                ErrorKind::Auth,
+                format!("Spotify rejected the access token ({status}). Log in again with `spot auth login`."),
+            ),
+            StatusCode::FORBIDDEN => fail(
+                ErrorKind::Api,
+                format!("Spotify refused the request ({status}): {body}. Connect playback requires Premium."),
+            ),
+            _ => fail(
+                ErrorKind::Api,
+                format!("Player request failed ({status}): {body}"),
+            ),
+        })
+    }
+
+    async fn command(&self, method: Method, path: &str) -> Result<()> {
+        // The player endpoints reject bodiless PUT/POST without a length.
+        self.send(self.request(method, path).await?.header("Content-Length", "0"))
+            .await?;
+        Ok(())
+    }
+
+    async fn state(&self) -> Result<PlaybackState> {
+        let resp = self.send(self.request(Method::GET, "/me/player").await?).await?;
+        if resp.status() == StatusCode::NO_CONTENT {
+            return Err(no_device());
+        }
+        resp.json().await.context("Failed to parse playback state")
+    }
+
+    pub async fn play_uri(&self, uri: &str) -> Result<()> {
+        // Tracks and episodes are played as a list; everything else is a context.
+        let body = if uri.starts_with("spotify:track:") || uri.starts_with("spotify:episode:") {
+            json!({ "uris": [uri] })
+        } else {
+            json!({ "context_uri": uri })
+        };
+        self.send(self.request(Method::PUT, &self.play_path().await?).await?.json(&body))
+            .await?;
+        Ok(())
+    }
+
+    /// Plays `track` inside `context`, so the album or playlist carries on
+    /// after it.
+    pub async fn play_in_context(&self, context: &str, track: &str) -> Result<()> {
+        let body = json!({ "context_uri": context, "offset": { "uri": track } });
+        self.send(self.request(Method::PUT, &self.play_path().await?).await?.json(&body))
+            .await?;
+        Ok(())
+    }
+
+    /// The album, playlist or artist playback is coming from.
+    pub async fn get_context(&self) -> Result<Option<String>> {
+        Ok(self.state().await?.context.map(|c| c.uri))
+    }
+
+    pub async fn pause(&self) -> Result<()> {
+        self.command(Method::PUT, "/me/player/pause").await
+    }
+
+    pub async fn resume(&self) -> Result<()> {
+        self.command(Method::PUT, "/me/player/play").await
+    }
+
+    pub async fn toggle(&self) -> Result<()> {
+        if self.state().await?.is_playing {
+            self.pause().await
+        } else {
+            self.resume().await
+        }
+    }
+
+    pub async fn next_track(&self) -> Result<()> {
+        self.command(Method::POST, "/me/player/next").await
+    }
+
+    pub async fn prev_track(&self) -> Result<()> {
+        self.command(Method::POST, "/me/player/previous").await
+    }
+
+    pub async fn get_volume(&self) -> Result<i32> {
+        self.state()
+            .await?
+            .device
+            .and_then(|d| d.volume_percent)
+            .context("The active device does not report its volume")
+    }
+
+    pub async fn set_volume(&self, vol: i32) -> Result<()> {
+        let path = format!("/me/player/volume?volume_percent={}", vol.clamp(0, 100));
+        self.command(Method::PUT, &path).await
+    }
+
+    pub async fn seek(&self, position_ms: i64) -> Result<()> {
+        let path = format!("/me/player/seek?position_ms={}", position_ms.max(0));
+        self.command(Method::PUT, &path).await
+    }
+
+    pub async fn get_shuffle(&self) -> Result<bool> {
+        Ok(self.state().await?.shuffle_state)
+    }
+
+    pub async fn set_shuffle(&self, on: bool) -> Result<()> {
+        self.command(Method::PUT, &format!("/me/player/shuffle?state={on}"))
+            .await
+    }
+
+    pub async fn get_repeat(&self) -> Result<bool> {
+        Ok(matches!(self.state().await?.repeat_state.as_str(), "track" | "context"))
+    }
+
+    pub async fn set_repeat(&self, on: bool) -> Result<()> {
+        let state = if on { "context" } else { "off" };
+        self.command(Method::PUT, &format!("/me/player/repeat?state={state}"))
+            .await
+    }
+
+    pub async fn get_now_playing(&self) -> Result<NowPlayingInfo> {
+        let resp = self
+            .send(
+                self.request(Method::GET, "/me/player/currently-playing")
+                    .await?
+                    .query(&[("additional_types", "track,episode")]),
+            )
+            .await?;
+        if resp.status() == StatusCode::NO_CONTENT {
+            return Err(nothing_playing());
+        }
+        let state: PlaybackState = resp
+            .json()
+            .await
+            .context("Failed to parse currently playing response")?;
+        let item = state.item.ok_or_else(nothing_playing)?;
+
+        let artist = if item.artists.is_empty() {
+            item.show.as_ref().map(|s| s.name.clone()).unwrap_or_default()
+        } else {
+            item.artists
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        Ok(NowPlayingInfo {
+            track_name: item.name,
+            artist,
+            album: item.album.map(|a| a.name).unwrap_or_default(),
+            track_id: item.uri,
+            duration_ms: item.duration_ms,
+            position_ms: state.progress_ms.unwrap_or(0),
+            is_playing: state.is_playing,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::kind_of;
+    use crate::test_support::{Stub, env_lock, route};
+
+    const STATE: &str = r#"{
+        "device": { "volume_percent": 40 },
+        "shuffle_state": true,
+        "repeat_state": "track",
+        "progress_ms": 1000,
+        "is_playing": false,
+        "item": null,
+        "context": { "uri": "spotify:album:kob" }
+    }"#;
+
+    const EPISODE: &str = r#"{
+        "progress_ms": 61000,
+        "is_playing": true,
+        "item": {
+            "name": "Episode 12",
+            "uri": "spotify:episode:e12",
+            "duration_ms": 3600000,
+            "show": { "name": "The Show" }
+        }
+    }"#;
+
+    fn connect(stub: &Stub) -> Connect {
+        // Safe while `env_lock` is held.
+        std::env::set_var("SPOT_ACCESS_TOKEN", "test-token");
+        Connect {
+            client: reqwest::Client::new(),
+            base_url: stub.url.clone(),
+            config: Config::default(),
+            device: None,
+            token: auth::TokenCache::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn commands_hit_the_player_endpoints() {
+        let _env = env_lock().await;
+        let stub = Stub::start(vec![
+            route("PUT", "/me/player/", 204, ""),
+            route("POST", "/me/player/", 204, ""),
+        ])
+        .await;
+        let player = connect(&stub);
+
+        player.play_uri("spotify:track:t1").await.unwrap();
+        player.play_uri("spotify:album:a1").await.unwrap();
+        player.pause().await.unwrap();
+        player.next_track().await.unwrap();
+        player.set_volume(130).await.unwrap();
+        player.seek(-5).await.unwrap();
+        player.seek(90_000).await.unwrap();
+
+        let requests = stub.requests();
+        let calls: Vec<String> = requests
+            .iter()
+            .map(|r| format!("{} {}", r.method, r.path))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                "PUT /me/player/play",
+                "PUT /me/player/play",
+                "PUT /me/player/pause",
+                "POST /me/player/next",
+                "PUT /me/player/volume?volume_percent=100",
+                "PUT /me/player/seek?position_ms=0",
+                "PUT /me/player/seek?position_ms=90000",
+            ]
+        );
+        assert_eq!(requests[0].body, r#"{"uris":["spotify:track:t1"]}"#);
+        assert_eq!(requests[1].body, r#"{"context_uri":"spotify:album:a1"}"#);
+    }
+
+    #[tokio::test]
+    async fn reads_playback_state() {
+        let _env = env_lock().await;
+        let stub = Stub::start(vec![route("GET", "/me/player", 200, STATE)]).await;
+        let player = connect(&stub);
+
+        assert_eq!(player.get_volume().await.unwrap(), 40);
+        assert!(player.get_shuffle().await.unwrap());
+        assert!(player.get_repeat().await.unwrap());
+        assert_eq!(
+            player.get_context().await.unwrap().as_deref(),
+            Some("spotify:album:kob")
+        );
+    }
+
+    #[tokio::test]
+    async fn episodes_use_the_show_as_artist() {
+        let _env = env_lock().await;
+        let stub = Stub::start(vec![route("GET", "/me/player/currently-playing", 200, EPISODE)]).await;
+        let info = connect(&stub).get_now_playing().await.unwrap();
+        assert_eq!(info.track_name, "Episode 12");
+        assert_eq!(info.artist, "The Show");
+        assert_eq!(info.track_id, "spotify:episode:e12");
+        assert_eq!(info.position_ms, 61_000);
+        assert!(info.is_playing);
+    }
+
+    #[tokio::test]
+    async fn no_active_device() {
+        let _env = env_lock().await;
+        let stub = Stub::start(vec![
+            route("GET", "/me/player/currently-playing", 204, ""),
+            route("GET", "/me/player", 204, ""),
+            route("PUT", "/me/player/pause", 404, r#"{"error":{"status":404}}"#),
+        ])
+        .await;
+        let player = connect(&stub);
+
+        let err = player.get_volume().await.unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::NoDevice);
+        let err = player.pause().await.unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::NoDevice);
+        let err = player.get_now_playing().await.unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::NothingPlaying);
+    }
+
+    #[tokio::test]
+    async fn rejected_token_is_an_auth_error() {
+        let _env = env_lock().await;
+        let stub = Stub::start(vec![route("PUT", "/me/player/pause", 401, "")]).await;
+        let err = connect(&stub).pause().await.unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::Auth);
+    }
+}
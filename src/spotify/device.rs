@@ -0,0 +1,202 @@
+//! Spotify Connect devices: listing, fuzzy lookup by name, and moving
+//! playback between them. Needs a user-scoped token.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::api::{base_url, client};
+use crate::output::{ErrorKind, fail};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Device {
+    /// Missing for devices the Web API can't control.
+    pub id: Option<String>,
+    pub name: String,
+    /// Computer, Smartphone, Speaker, …
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub is_active: bool,
+    #[serde(default)]
+    pub is_restricted: bool,
+    pub volume_percent: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct DevicesResponse {
+    devices: Vec<Device>,
+}
+
+async fn check(resp: reqwest::Response, what: &str) -> Result<reqwest::Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let body = resp.text().await.unwrap_or_default();
+    let kind = if status == reqwest::StatusCode::NOT_FOUND {
+        ErrorKind::NoDevice
+    } else {
+        ErrorKind::Api
+    };
+    Err(fail(kind, format!("{what} failed ({status}): {body}")))
+}
+
+pub async fn list(token: &str) -> Result<Vec<Device>> {
+    let resp = client()
+        .get(format!("{}/me/player/devices", base_url()))
+        .bearer_auth(token)
+        .send()
+        .await
+        .context("Failed to list Spotify devices")?;
+    let data: DevicesResponse = check(resp, "Listing devices")
+        .await?
+        .json()
+        .await
+        .context("Failed to parse the device list")?;
+    Ok(data.devices)
+}
+
+/// Moves playback to `device_id`, starting it if `play` is set; otherwise
+/// the device keeps the current play/pause state.
+pub async fn transfer(token: &str, device_id: &str, play: bool) -> Result<()> {
+    let resp = client()
+        .put(format!("{}/me/player", base_url()))
+        .bearer_auth(token)
+        .json(&json!({ "device_ids": [device_id], "play": play }))
+        .send()
+        .await
+        .context("Failed to transfer playback")?;
+    check(resp, "Transferring playback").await?;
+    Ok(())
+}
+
+/// How well `query` matches `device`; higher is better.
+fn score(device: &Device, query: &str) -> Option<u32> {
+    if device.id.as_deref() == Some(query) {
+        return Some(4);
+    }
+    let name = device.name.to_lowercase();
+    let query = query.to_lowercase();
+    if name == query {
+        return Some(4);
+    }
+    if name.starts_with(&query) {
+        return Some(3);
+    }
+    if query.split_whitespace().all(|word| name.contains(word)) {
+        return Some(2);
+    }
+    // Letters in order, so "lvrm" finds "Living Room".
+    let mut letters = name.chars();
+    query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .all(|c| letters.any(|n| n == c))
+        .then_some(1)
+}
+
+/// The device `query` names: an id, or a name matched loosely. A tie
+/// between several devices is an error rather than a guess.
+pub fn find<'a>(devices: &'a [Device], query: &str) -> Result<&'a Device> {
+    let scored: Vec<(u32, &Device)> = devices
+        .iter()
+        .filter_map(|d| score(d, query).map(|s| (s, d)))
+        .collect();
+    let Some(best) = scored.iter().map(|(s, _)| *s).max() else {
+        return Err(fail(
+            ErrorKind::NotFound,
+            format!("No device matches \"{query}\". See: spot device ls"),
+        ));
+    };
+    let matches: Vec<&Device> = scored
+        .into_iter()
+        .filter(|(s, _)| *s == best)
+        .map(|(_, d)| d)
+        .collect();
+    if let [device] = matches.as_slice() {
+        return Ok(device);
+    }
+    let names: Vec<&str> = matches.iter().map(|d| d.name.as_str()).collect();
+    Err(fail(
+        ErrorKind::Usage,
+        format!("\"{query}\" matches {}; be more specific", names.join(", ")),
+    ))
+}
+
+/// The id of the device `query` names, for use in `device_id=`.
+pub async fn resolve(token: &str, query: &str) -> Result<String> {
+    let devices = list(token).await?;
+    let device = find(&devices, query)?;
+    device.id.clone().ok_or_else(|| {
+        fail(
+            ErrorKind::Player,
+            format!("{} can't be controlled through the Web API", device.name),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::kind_of;
+
+    fn device(id: &str, name: &str) -> Device {
+        Device {
+            id: Some(id.to_string()),
+            name: name.to_string(),
+            kind: "Speaker".to_string(),
+            is_active: false,
+            is_restricted: false,
+            volume_percent: None,
+        }
+    }
+
+    #[test]
+    fn scores_closer_matches_higher() {
+        let room = device("7f3A9c", "Living Room");
+        assert_eq!(score(&room, "7f3A9c"), Some(4));
+        assert_eq!(score(&room, "living room"), Some(4));
+        assert_eq!(score(&room, "Liv"), Some(3));
+        assert_eq!(score(&room, "room living"), Some(2));
+        assert_eq!(score(&room, "lvrm"), Some(1));
+        assert_eq!(score(&room, "kitchen"), None);
+        // Ids are matched exactly, not loosely.
+        assert_eq!(score(&room, "7F3A9C"), None);
+    }
+
+    #[test]
+    fn finds_the_best_match() {
+        let devices = [
+            device("a1", "Living Room"),
+            device("b2", "Living Room Speaker"),
+            device("c3", "Kitchen"),
+        ];
+        assert_eq!(find(&devices, "living room").unwrap().name, "Living Room");
+        assert_eq!(find(&devices, "speaker").unwrap().name, "Living Room Speaker");
+        assert_eq!(find(&devices, "ktch").unwrap().name, "Kitchen");
+    }
+
+    #[test]
+    fn an_id_beats_a_name() {
+        let devices = [device("kitchen", "Study"), device("d4", "Kitchen Radio")];
+        assert_eq!(find(&devices, "kitchen").unwrap().name, "Study");
+    }
+
+    #[test]
+    fn refuses_to_guess_between_ties() {
+        let devices = [device("a1", "Desk Left"), device("b2", "Desk Right")];
+        let err = find(&devices, "desk").unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::Usage);
+        assert_eq!(
+            err.to_string(),
+            "\"desk\" matches Desk Left, Desk Right; be more specific"
+        );
+    }
+
+    #[test]
+    fn reports_no_match() {
+        let devices = [device("a1", "Kitchen")];
+        let err = find(&devices, "garage").unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::NotFound);
+    }
+}
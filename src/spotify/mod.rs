@@ -1,3 +1,8 @@
 pub mod api;
 pub mod applescript;
 pub mod auth;
+pub mod connect;
+pub mod device;
+pub mod mpris;
+pub mod player;
+pub mod query;
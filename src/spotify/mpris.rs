@@ -0,0 +1,464 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::process::Command;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue};
+use zbus::{Connection, Proxy};
+
+use super::player::NowPlayingInfo;
+use crate::output::{ErrorKind, fail};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.spotify";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// Talks to the Spotify desktop client over the MPRIS D-Bus interface.
+pub struct Mpris {
+    connection: Connection,
+}
+
+impl Mpris {
+    /// Connects to the bus at `DBUS_SESSION_BUS_ADDRESS`, so pointing that at
+    /// a private dbus-daemon is enough to run against a fake player.
+    pub async fn session() -> Result<Self> {
+        let connection = Connection::session()
+            .await
+            .context("Failed to connect to the D-Bus session bus")?;
+        Ok(Self { connection })
+    }
+
+    async fn player(&self) -> Result<Proxy<'_>> {
+        Proxy::new(
+            &self.connection,
+            BUS_NAME,
+            OBJECT_PATH,
+            PLAYER_INTERFACE,
+        )
+        .await
+        .context("Failed to create MPRIS player proxy")
+    }
+
+    async fn call(&self, method: &str) -> Result<()> {
+        self.player()
+            .await?
+            .call_method(method, &())
+            .await
+            .with_context(|| format!("MPRIS call {method} failed"))?;
+        Ok(())
+    }
+
+    async fn get<T>(&self, property: &str) -> Result<T>
+    where
+        T: TryFrom<OwnedValue>,
+        T::Error: Into<zbus::Error>,
+    {
+        self.player()
+            .await?
+            .get_property(property)
+            .await
+            .with_context(|| format!("Failed to read MPRIS property {property}"))
+    }
+
+    async fn set<T>(&self, property: &str, value: T) -> Result<()>
+    where
+        T: Into<zbus::zvariant::Value<'static>> + 'static,
+    {
+        self.player()
+            .await?
+            .set_property(property, value)
+            .await
+            .with_context(|| format!("Failed to set MPRIS property {property}"))?;
+        Ok(())
+    }
+
+    pub async fn is_running(&self) -> Result<bool> {
+        let dbus = zbus::fdo::DBusProxy::new(&self.connection)
+            .await
+            .context("Failed to create D-Bus proxy")?;
+        Ok(dbus.name_has_owner(BUS_NAME.try_into()?).await?)
+    }
+
+    pub async fn ensure_running(&self) -> Result<()> {
+        if !self.is_running().await? {
+            Command::new("spotify")
+                .spawn()
+                .context("Spotify is not running and could not be launched")?;
+            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        }
+        Ok(())
+    }
+
+    pub async fn play_uri(&self, uri: &str) -> Result<()> {
+        self.ensure_running().await?;
+        self.player()
+            .await?
+            .call_method("OpenUri", &(uri,))
+            .await
+            .context("MPRIS call OpenUri failed")?;
+        Ok(())
+    }
+
+    pub async fn pause(&self) -> Result<()> {
+        self.call("Pause").await
+    }
+
+    pub async fn resume(&self) -> Result<()> {
+        self.call("Play").await
+    }
+
+    pub async fn toggle(&self) -> Result<()> {
+        self.call("PlayPause").await
+    }
+
+    pub async fn next_track(&self) -> Result<()> {
+        self.call("Next").await
+    }
+
+    pub async fn prev_track(&self) -> Result<()> {
+        self.call("Previous").await
+    }
+
+    pub async fn get_volume(&self) -> Result<i32> {
+        let vol: f64 = self.get("Volume").await?;
+        Ok((vol * 100.0).round() as i32)
+    }
+
+    pub async fn set_volume(&self, vol: i32) -> Result<()> {
+        self.set("Volume", f64::from(vol.clamp(0, 100)) / 100.0).await
+    }
+
+    /// SetPosition needs the current track's object path; players that
+    /// don't expose one get a relative Seek from the current position.
+    pub async fn seek(&self, position_ms: i64) -> Result<()> {
+        let metadata: HashMap<String, OwnedValue> = self.get("Metadata").await?;
+        let track = metadata
+            .get("mpris:trackid")
+            .and_then(|v| OwnedObjectPath::try_from(v.try_clone().ok()?).ok());
+        let player = self.player().await?;
+        match track {
+            Some(track) => player
+                .call_method("SetPosition", &(track, position_ms * 1000))
+                .await
+                .context("MPRIS call SetPosition failed")?,
+            None => {
+                let position_us: i64 = self.get("Position").await?;
+                player
+                    .call_method("Seek", &(position_ms * 1000 - position_us,))
+                    .await
+                    .context("MPRIS call Seek failed")?
+            }
+        };
+        Ok(())
+    }
+
+    pub async fn get_shuffle(&self) -> Result<bool> {
+        self.get("Shuffle").await
+    }
+
+    pub async fn set_shuffle(&self, on: bool) -> Result<()> {
+        self.set("Shuffle", on).await
+    }
+
+    pub async fn get_repeat(&self) -> Result<bool> {
+        let status: String = self.get("LoopStatus").await?;
+        Ok(status != "None")
+    }
+
+    pub async fn set_repeat(&self, on: bool) -> Result<()> {
+        self.set("LoopStatus", if on { "Playlist" } else { "None" }).await
+    }
+
+    pub async fn get_now_playing(&self) -> Result<NowPlayingInfo> {
+        let status: String = match self.get("PlaybackStatus").await {
+            Ok(status) => status,
+            // Quitting Spotify stops playback too.
+            Err(_) if !self.is_running().await.unwrap_or(true) => {
+                return Err(fail(ErrorKind::NothingPlaying, "Spotify is not running"));
+            }
+            Err(e) => return Err(e),
+        };
+        if status == "Stopped" {
+            return Err(fail(ErrorKind::NothingPlaying, "No track is currently playing"));
+        }
+
+        let metadata: HashMap<String, OwnedValue> = self.get("Metadata").await?;
+        // Position is optional in the spec; Spotify has been known to omit it.
+        let position_us: i64 = self.get("Position").await.unwrap_or(0);
+        Ok(now_playing_from(&status, &metadata, position_us))
+    }
+}
+
+/// Maps MPRIS `Metadata` onto the backend-neutral view. Missing keys come
+/// out empty rather than failing.
+fn now_playing_from(
+    status: &str,
+    metadata: &HashMap<String, OwnedValue>,
+    position_us: i64,
+) -> NowPlayingInfo {
+    let text = |key: &str| -> String {
+        metadata
+            .get(key)
+            .and_then(|v| String::try_from(v.try_clone().ok()?).ok())
+            .unwrap_or_default()
+    };
+    let artist = metadata
+        .get("xesam:artist")
+        .and_then(|v| Vec::<String>::try_from(v.try_clone().ok()?).ok())
+        .map(|a| a.join(", "))
+        .unwrap_or_default();
+    let length_us = metadata
+        .get("mpris:length")
+        .and_then(|v| {
+            i64::try_from(v.try_clone().ok()?)
+                .ok()
+                .or_else(|| u64::try_from(v.try_clone().ok()?).ok().map(|n| n as i64))
+        })
+        .unwrap_or(0);
+    let track_id = metadata
+        .get("mpris:trackid")
+        .and_then(|v| {
+            OwnedObjectPath::try_from(v.try_clone().ok()?)
+                .ok()
+                .map(|p| track_id_from_path(&p))
+                .or_else(|| String::try_from(v.try_clone().ok()?).ok())
+        })
+        .unwrap_or_default();
+
+    NowPlayingInfo {
+        track_name: text("xesam:title"),
+        artist,
+        album: text("xesam:album"),
+        track_id,
+        duration_ms: length_us / 1000,
+        position_ms: position_us / 1000,
+        is_playing: status == "Playing",
+    }
+}
+
+/// Spotify exposes track ids as object paths like
+/// `/com/spotify/track/4uLU6hMCjMI75M1A2tKUQC`; turn them back into URIs.
+fn track_id_from_path(path: &ObjectPath<'_>) -> String {
+    let segments: Vec<&str> = path.as_str().trim_start_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["com", "spotify", kind, id] => format!("spotify:{kind}:{id}"),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::kind_of;
+    use crate::test_support::PrivateBus;
+    use std::sync::{Arc, Mutex};
+    use zbus::zvariant::Value;
+
+    fn owned(value: impl Into<Value<'static>>) -> OwnedValue {
+        OwnedValue::try_from(value.into()).unwrap()
+    }
+
+    fn metadata() -> HashMap<String, OwnedValue> {
+        HashMap::from([
+            (
+                "mpris:trackid".to_string(),
+                owned(ObjectPath::try_from("/com/spotify/track/4uLU6hMCjMI75M1A2tKUQC").unwrap()),
+            ),
+            ("mpris:length".to_string(), owned(354_000_000u64)),
+            ("xesam:title".to_string(), owned("Bohemian Rhapsody")),
+            ("xesam:album".to_string(), owned("A Night at the Opera")),
+            (
+                "xesam:artist".to_string(),
+                owned(vec!["Queen".to_string(), "Freddie Mercury".to_string()]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn track_paths_become_uris() {
+        let path = ObjectPath::try_from("/com/spotify/track/4uLU6hMCjMI75M1A2tKUQC").unwrap();
+        assert_eq!(track_id_from_path(&path), "spotify:track:4uLU6hMCjMI75M1A2tKUQC");
+        let episode = ObjectPath::try_from("/com/spotify/episode/abc").unwrap();
+        assert_eq!(track_id_from_path(&episode), "spotify:episode:abc");
+    }
+
+    #[test]
+    fn other_paths_are_kept() {
+        let path = ObjectPath::try_from("/org/mpris/MediaPlayer2/TrackList/NoTrack").unwrap();
+        assert_eq!(track_id_from_path(&path), "/org/mpris/MediaPlayer2/TrackList/NoTrack");
+    }
+
+    #[test]
+    fn metadata_maps_to_now_playing() {
+        let info = now_playing_from("Playing", &metadata(), 83_500_000);
+        assert_eq!(info.track_name, "Bohemian Rhapsody");
+        assert_eq!(info.artist, "Queen, Freddie Mercury");
+        assert_eq!(info.album, "A Night at the Opera");
+        assert_eq!(info.track_id, "spotify:track:4uLU6hMCjMI75M1A2tKUQC");
+        assert_eq!(info.duration_ms, 354_000);
+        assert_eq!(info.position_ms, 83_500);
+        assert!(info.is_playing);
+    }
+
+    #[test]
+    fn metadata_tolerates_missing_and_odd_keys() {
+        let metadata = HashMap::from([
+            ("mpris:trackid".to_string(), owned("spotify:track:xyz")),
+            ("mpris:length".to_string(), owned(1_000_000i64)),
+        ]);
+        let info = now_playing_from("Paused", &metadata, 0);
+        assert_eq!(info.track_id, "spotify:track:xyz");
+        assert_eq!(info.duration_ms, 1000);
+        assert_eq!(info.track_name, "");
+        assert_eq!(info.artist, "");
+        assert!(!info.is_playing);
+    }
+
+    /// Just enough of Spotify's MPRIS player to drive [`Mpris`].
+    struct FakePlayer {
+        calls: Arc<Mutex<Vec<String>>>,
+        status: String,
+        volume: f64,
+    }
+
+    impl FakePlayer {
+        fn record(&self, call: String) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    #[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl FakePlayer {
+        fn play(&self) {
+            self.record("Play".to_string());
+        }
+
+        fn pause(&self) {
+            self.record("Pause".to_string());
+        }
+
+        fn next(&self) {
+            self.record("Next".to_string());
+        }
+
+        fn open_uri(&self, uri: String) {
+            self.record(format!("OpenUri {uri}"));
+        }
+
+        fn set_position(&self, track: OwnedObjectPath, position: i64) {
+            self.record(format!("SetPosition {} {position}", track.as_str()));
+        }
+
+        #[zbus(property)]
+        fn playback_status(&self) -> String {
+            self.status.clone()
+        }
+
+        #[zbus(property)]
+        fn position(&self) -> i64 {
+            83_000_000
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<String, OwnedValue> {
+            metadata()
+        }
+
+        #[zbus(property)]
+        fn volume(&self) -> f64 {
+            self.volume
+        }
+
+        #[zbus(property)]
+        fn set_volume(&mut self, volume: f64) {
+            self.volume = volume;
+        }
+
+        #[zbus(property)]
+        fn loop_status(&self) -> String {
+            "Playlist".to_string()
+        }
+    }
+
+    /// An [`Mpris`] talking to a [`FakePlayer`] on a private bus. Fields
+    /// drop in order, so the bus goes last.
+    struct Fixture {
+        mpris: Mpris,
+        calls: Arc<Mutex<Vec<String>>>,
+        _server: zbus::blocking::Connection,
+        _bus: PrivateBus,
+    }
+
+    async fn fake(status: &str) -> Fixture {
+        let bus = PrivateBus::start();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let player = FakePlayer {
+            calls: calls.clone(),
+            status: status.to_string(),
+            volume: 0.5,
+        };
+        let server = zbus::blocking::connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name(BUS_NAME)
+            .unwrap()
+            .serve_at(OBJECT_PATH, player)
+            .unwrap()
+            .build()
+            .unwrap();
+        Fixture {
+            mpris: Mpris {
+                connection: bus.connect().await,
+            },
+            calls,
+            _server: server,
+            _bus: bus,
+        }
+    }
+
+    #[tokio::test]
+    async fn drives_a_fake_player() {
+        let fixture = fake("Playing").await;
+        let (mpris, calls) = (&fixture.mpris, &fixture.calls);
+        assert!(mpris.is_running().await.unwrap());
+
+        mpris.play_uri("spotify:album:abc").await.unwrap();
+        mpris.pause().await.unwrap();
+        mpris.resume().await.unwrap();
+        mpris.next_track().await.unwrap();
+        mpris.seek(1500).await.unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "OpenUri spotify:album:abc",
+                "Pause",
+                "Play",
+                "Next",
+                "SetPosition /com/spotify/track/4uLU6hMCjMI75M1A2tKUQC 1500000",
+            ]
+        );
+
+        assert_eq!(mpris.get_volume().await.unwrap(), 50);
+        mpris.set_volume(120).await.unwrap();
+        assert_eq!(mpris.get_volume().await.unwrap(), 100);
+        assert!(mpris.get_repeat().await.unwrap());
+
+        let info = mpris.get_now_playing().await.unwrap();
+        assert_eq!(info.track_name, "Bohemian Rhapsody");
+        assert_eq!(info.position_ms, 83_000);
+    }
+
+    #[tokio::test]
+    async fn stopped_player_is_nothing_playing() {
+        let fixture = fake("Stopped").await;
+        let err = fixture.mpris.get_now_playing().await.unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::NothingPlaying);
+    }
+
+    #[tokio::test]
+    async fn closed_player_is_nothing_playing() {
+        let bus = PrivateBus::start();
+        let mpris = Mpris {
+            connection: bus.connect().await,
+        };
+        let err = mpris.get_now_playing().await.unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::NothingPlaying);
+    }
+}
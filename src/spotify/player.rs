@@ -0,0 +1,368 @@
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use super::applescript;
+use super::connect::Connect;
+use super::mpris::Mpris;
+use crate::config::Config;
+use crate::daemon;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NowPlayingInfo {
+    pub track_name: String,
+    pub artist: String,
+    pub album: String,
+    pub track_id: String,
+    pub duration_ms: i64,
+    pub position_ms: i64,
+    pub is_playing: bool,
+}
+
+/// Which mechanism is used to drive the Spotify client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Spotify desktop app on macOS
+    #[value(name = "applescript")]
+    AppleScript,
+    /// Spotify desktop app on Linux (D-Bus)
+    Mpris,
+    /// Any Spotify Connect device, via the Web API
+    Connect,
+}
+
+impl Backend {
+    /// Picks the native backend for the platform spot was built for.
+    pub fn detect() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::AppleScript
+        } else {
+            Self::Mpris
+        }
+    }
+
+    /// An explicit choice wins over `config.toml`, which wins over detection.
+    pub fn resolve(backend: Option<Backend>, config: &Config) -> Self {
+        backend.or(config.backend).unwrap_or_else(Self::detect)
+    }
+}
+
+async fn desktop_running(backend: Backend) -> bool {
+    match backend {
+        Backend::AppleScript => osascript(applescript::is_running).await.unwrap_or(false),
+        Backend::Mpris => match Mpris::session().await {
+            Ok(mpris) => mpris.is_running().await.unwrap_or(false),
+            Err(_) => false,
+        },
+        Backend::Connect => false,
+    }
+}
+
+/// Runs an AppleScript call on the blocking pool, so waiting on `osascript`
+/// doesn't stall the runtime.
+async fn osascript<T: Send + 'static>(call: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(call)
+        .await
+        .context("AppleScript call panicked")?
+}
+
+pub enum Player {
+    AppleScript,
+    Mpris(Mpris),
+    Connect(Box<Connect>),
+    /// Forwards every call to a running `spot daemon`.
+    Remote(Box<Remote>),
+}
+
+/// A `spot daemon` along with the backend to use when it can't answer.
+pub struct Remote {
+    client: daemon::Client,
+    backend: Backend,
+    config: Config,
+    /// Opened the first time the daemon fails to answer, and used for every
+    /// call after that.
+    direct: OnceCell<Player>,
+}
+
+impl Remote {
+    /// The daemon's answer, or `None` once a request has failed to reach it.
+    async fn call<T: DeserializeOwned>(&self, request: daemon::Request) -> Option<Result<T>> {
+        if self.direct.initialized() {
+            return None;
+        }
+        self.client.try_call(request).await
+    }
+
+    async fn direct(&self) -> Result<&Player> {
+        self.direct
+            .get_or_try_init(|| Player::open(self.backend, &self.config))
+            .await
+    }
+}
+
+impl Player {
+    /// Opens `backend` directly. Connect targets `default_device` when one
+    /// is set, and otherwise whichever device is active.
+    pub async fn open(backend: Backend, config: &Config) -> Result<Self> {
+        Ok(match backend {
+            Backend::AppleScript => Self::AppleScript,
+            Backend::Mpris => Self::Mpris(Mpris::session().await?),
+            Backend::Connect => Self::Connect(Box::new(match &config.default_device {
+                Some(device) => Connect::with_device(config.clone(), device.clone()),
+                None => Connect::new(config.clone()),
+            })),
+        })
+    }
+
+    /// Uses the daemon when one is running and no backend was forced;
+    /// otherwise opens the backend resolved by [`Backend::resolve`].
+    pub async fn from_config(backend: Option<Backend>, config: &Config) -> Result<Self> {
+        if backend.is_none() {
+            if let Some(client) = daemon::Client::connect() {
+                return Ok(Self::remote(client, Backend::resolve(None, config), config));
+            }
+        }
+        Self::open(Backend::resolve(backend, config), config).await
+    }
+
+    /// Goes through `client`, falling back to `backend` when the daemon
+    /// can't be reached or doesn't answer in time.
+    pub fn remote(client: daemon::Client, backend: Backend, config: &Config) -> Self {
+        Self::Remote(Box::new(Remote {
+            client,
+            backend,
+            config: config.clone(),
+            direct: OnceCell::new(),
+        }))
+    }
+
+    /// [`Player::from_config`] for commands that start playback. With a
+    /// `default_device` set and the desktop app closed, plays there over
+    /// Connect instead of launching the app, daemon or not. Only these
+    /// commands pay for asking whether the app is running.
+    pub async fn for_playback(backend: Option<Backend>, config: &Config) -> Result<Self> {
+        let player = Self::from_config(backend, config).await?;
+        let desktop = match &player {
+            Self::AppleScript => Backend::AppleScript,
+            Self::Mpris(_) => Backend::Mpris,
+            Self::Remote(remote) if remote.backend != Backend::Connect => remote.backend,
+            Self::Connect(_) | Self::Remote(_) => return Ok(player),
+        };
+        if backend.is_none() && config.default_device.is_some() && !desktop_running(desktop).await {
+            return Self::open(Backend::Connect, config).await;
+        }
+        Ok(player)
+    }
+
+    pub async fn play_uri(&self, uri: &str) -> Result<()> {
+        match self {
+            Self::AppleScript => {
+                let uri = uri.to_string();
+                osascript(move || applescript::play_uri(&uri)).await
+            }
+            Self::Mpris(m) => m.play_uri(uri).await,
+            Self::Connect(c) => c.play_uri(uri).await,
+            Self::Remote(r) => {
+                let request = daemon::Request::PlayUri { uri: uri.to_string() };
+                match r.call(request).await {
+                    Some(result) => result,
+                    None => Box::pin(r.direct().await?.play_uri(uri)).await,
+                }
+            }
+        }
+    }
+
+    /// Plays `track` within `context`. MPRIS can't address a context, so
+    /// there only the track plays.
+    pub async fn play_in_context(&self, context: &str, track: &str) -> Result<()> {
+        match self {
+            Self::AppleScript => {
+                let (context, track) = (context.to_string(), track.to_string());
+                osascript(move || applescript::play_in_context(&context, &track)).await
+            }
+            Self::Mpris(m) => m.play_uri(track).await,
+            Self::Connect(c) => c.play_in_context(context, track).await,
+            Self::Remote(r) => {
+                let request = daemon::Request::PlayInContext {
+                    context: context.to_string(),
+                    track: track.to_string(),
+                };
+                match r.call(request).await {
+                    Some(result) => result,
+                    None => Box::pin(r.direct().await?.play_in_context(context, track)).await,
+                }
+            }
+        }
+    }
+
+    /// The context URI playback is coming from. Only Connect reports one.
+    pub async fn get_context(&self) -> Result<Option<String>> {
+        match self {
+            Self::AppleScript | Self::Mpris(_) => Ok(None),
+            Self::Connect(c) => c.get_context().await,
+            Self::Remote(r) => match r.call(daemon::Request::GetContext).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.get_context()).await,
+            },
+        }
+    }
+
+    pub async fn pause(&self) -> Result<()> {
+        match self {
+            Self::AppleScript => osascript(applescript::pause).await,
+            Self::Mpris(m) => m.pause().await,
+            Self::Connect(c) => c.pause().await,
+            Self::Remote(r) => match r.call(daemon::Request::Pause).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.pause()).await,
+            },
+        }
+    }
+
+    pub async fn resume(&self) -> Result<()> {
+        match self {
+            Self::AppleScript => osascript(applescript::resume).await,
+            Self::Mpris(m) => m.resume().await,
+            Self::Connect(c) => c.resume().await,
+            Self::Remote(r) => match r.call(daemon::Request::Resume).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.resume()).await,
+            },
+        }
+    }
+
+    pub async fn toggle(&self) -> Result<()> {
+        match self {
+            Self::AppleScript => osascript(applescript::toggle).await,
+            Self::Mpris(m) => m.toggle().await,
+            Self::Connect(c) => c.toggle().await,
+            Self::Remote(r) => match r.call(daemon::Request::Toggle).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.toggle()).await,
+            },
+        }
+    }
+
+    pub async fn next_track(&self) -> Result<()> {
+        match self {
+            Self::AppleScript => osascript(applescript::next_track).await,
+            Self::Mpris(m) => m.next_track().await,
+            Self::Connect(c) => c.next_track().await,
+            Self::Remote(r) => match r.call(daemon::Request::NextTrack).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.next_track()).await,
+            },
+        }
+    }
+
+    pub async fn prev_track(&self) -> Result<()> {
+        match self {
+            Self::AppleScript => osascript(applescript::prev_track).await,
+            Self::Mpris(m) => m.prev_track().await,
+            Self::Connect(c) => c.prev_track().await,
+            Self::Remote(r) => match r.call(daemon::Request::PrevTrack).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.prev_track()).await,
+            },
+        }
+    }
+
+    pub async fn get_volume(&self) -> Result<i32> {
+        match self {
+            Self::AppleScript => osascript(applescript::get_volume).await,
+            Self::Mpris(m) => m.get_volume().await,
+            Self::Connect(c) => c.get_volume().await,
+            Self::Remote(r) => match r.call(daemon::Request::GetVolume).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.get_volume()).await,
+            },
+        }
+    }
+
+    pub async fn set_volume(&self, vol: i32) -> Result<()> {
+        match self {
+            Self::AppleScript => osascript(move || applescript::set_volume(vol)).await,
+            Self::Mpris(m) => m.set_volume(vol).await,
+            Self::Connect(c) => c.set_volume(vol).await,
+            Self::Remote(r) => match r.call(daemon::Request::SetVolume { volume: vol }).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.set_volume(vol)).await,
+            },
+        }
+    }
+
+    /// Jumps to `position_ms` in the current track.
+    pub async fn seek(&self, position_ms: i64) -> Result<()> {
+        match self {
+            Self::AppleScript => osascript(move || applescript::seek(position_ms)).await,
+            Self::Mpris(m) => m.seek(position_ms).await,
+            Self::Connect(c) => c.seek(position_ms).await,
+            Self::Remote(r) => match r.call(daemon::Request::Seek { position_ms }).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.seek(position_ms)).await,
+            },
+        }
+    }
+
+    pub async fn get_shuffle(&self) -> Result<bool> {
+        match self {
+            Self::AppleScript => osascript(applescript::get_shuffle).await,
+            Self::Mpris(m) => m.get_shuffle().await,
+            Self::Connect(c) => c.get_shuffle().await,
+            Self::Remote(r) => match r.call(daemon::Request::GetShuffle).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.get_shuffle()).await,
+            },
+        }
+    }
+
+    pub async fn set_shuffle(&self, on: bool) -> Result<()> {
+        match self {
+            Self::AppleScript => osascript(move || applescript::set_shuffle(on)).await,
+            Self::Mpris(m) => m.set_shuffle(on).await,
+            Self::Connect(c) => c.set_shuffle(on).await,
+            Self::Remote(r) => match r.call(daemon::Request::SetShuffle { on }).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.set_shuffle(on)).await,
+            },
+        }
+    }
+
+    pub async fn get_repeat(&self) -> Result<bool> {
+        match self {
+            Self::AppleScript => osascript(applescript::get_repeat).await,
+            Self::Mpris(m) => m.get_repeat().await,
+            Self::Connect(c) => c.get_repeat().await,
+            Self::Remote(r) => match r.call(daemon::Request::GetRepeat).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.get_repeat()).await,
+            },
+        }
+    }
+
+    pub async fn set_repeat(&self, on: bool) -> Result<()> {
+        match self {
+            Self::AppleScript => osascript(move || applescript::set_repeat(on)).await,
+            Self::Mpris(m) => m.set_repeat(on).await,
+            Self::Connect(c) => c.set_repeat(on).await,
+            Self::Remote(r) => match r.call(daemon::Request::SetRepeat { on }).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.set_repeat(on)).await,
+            },
+        }
+    }
+
+    pub async fn get_now_playing(&self) -> Result<NowPlayingInfo> {
+        match self {
+            Self::AppleScript => osascript(applescript::get_now_playing).await,
+            Self::Mpris(m) => m.get_now_playing().await,
+            Self::Connect(c) => c.get_now_playing().await,
+            Self::Remote(r) => match r.call(daemon::Request::NowPlaying).await {
+                Some(result) => result,
+                None => Box::pin(r.direct().await?.get_now_playing()).await,
+            },
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Search filters that compile to Spotify's field syntax, e.g.
+//! `artist:"Miles Davis" year:1955-1960 genre:jazz`.
+
+use anyhow::Result;
+
+use crate::output::{ErrorKind, fail};
+
+/// Album filters Spotify supports with `tag:`.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Tag {
+    /// Released in the past two weeks
+    New,
+    /// In the least popular 10%
+    Hipster,
+}
+
+impl Tag {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::New => "new",
+            Self::Hipster => "hipster",
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Filters {
+    /// Only matches by this artist
+    #[arg(long, value_name = "NAME")]
+    pub artist: Option<String>,
+
+    /// Only matches from this album
+    #[arg(long, value_name = "NAME")]
+    pub album: Option<String>,
+
+    /// Only matches with this track name
+    #[arg(long, value_name = "NAME")]
+    pub track: Option<String>,
+
+    /// Release year, or a range like 1990-1999
+    #[arg(long)]
+    pub year: Option<String>,
+
+    /// Only artists and tracks in this genre
+    #[arg(long)]
+    pub genre: Option<String>,
+
+    /// The track with this ISRC
+    #[arg(long)]
+    pub isrc: Option<String>,
+
+    /// The album with this UPC
+    #[arg(long)]
+    pub upc: Option<String>,
+
+    /// Only new or little-known albums
+    #[arg(long, value_enum)]
+    pub tag: Option<Tag>,
+
+    /// Leave out matches containing this (repeatable)
+    #[arg(long, value_name = "TERM")]
+    pub exclude: Vec<String>,
+}
+
+impl Filters {
+    /// The `q` parameter for `text` narrowed by these filters.
+    pub fn compile(&self, text: &str) -> Result<String> {
+        let mut parts = Vec::new();
+        if !text.trim().is_empty() {
+            parts.push(text.trim().to_string());
+        }
+        let fields = [
+            ("artist", &self.artist),
+            ("album", &self.album),
+            ("track", &self.track),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                parts.push(format!("{field}:{}", quote(field, value)?));
+            }
+        }
+        if let Some(year) = &self.year {
+            parts.push(format!("year:{}", parse_year(year)?));
+        }
+        let fields = [
+            ("genre", &self.genre),
+            ("isrc", &self.isrc),
+            ("upc", &self.upc),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                parts.push(format!("{field}:{}", quote(field, value)?));
+            }
+        }
+        if let Some(tag) = self.tag {
+            parts.push(format!("tag:{}", tag.as_str()));
+        }
+        for term in &self.exclude {
+            parts.push(format!("NOT {}", quote("exclude", term)?));
+        }
+        Ok(parts.join(" "))
+    }
+
+    /// `"album"` or `"artist"` when that's the only filter given, which is
+    /// how `spot search --album`/`--artist` used to pick a section.
+    pub fn lone_name_filter(&self) -> Option<&'static str> {
+        let others = self.track.is_some()
+            || self.year.is_some()
+            || self.genre.is_some()
+            || self.isrc.is_some()
+            || self.upc.is_some()
+            || self.tag.is_some()
+            || !self.exclude.is_empty();
+        match (&self.artist, &self.album) {
+            _ if others => None,
+            (Some(_), None) => Some("artist"),
+            (None, Some(_)) => Some("album"),
+            _ => None,
+        }
+    }
+}
+
+/// Quotes `value` when it has spaces or colons. Spotify has no way to
+/// escape a double quote, so those are refused.
+fn quote(flag: &str, value: &str) -> Result<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(fail(ErrorKind::Usage, format!("--{flag} needs a value")));
+    }
+    if value.contains('"') {
+        return Err(fail(
+            ErrorKind::Usage,
+            format!("--{flag} can't contain double quotes"),
+        ));
+    }
+    if value.contains(|c: char| c.is_whitespace() || c == ':') {
+        Ok(format!("\"{value}\""))
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// Checks `1994` or `1990-1999` and returns it tidied.
+fn parse_year(s: &str) -> Result<String> {
+    let invalid = || {
+        fail(
+            ErrorKind::Usage,
+            format!("Invalid year \"{s}\"; use e.g. 1994 or 1990-1999"),
+        )
+    };
+    let year = |part: &str| -> Result<u16> {
+        let part = part.trim();
+        if part.len() != 4 || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        part.parse().map_err(|_| invalid())
+    };
+    match s.split_once('-') {
+        Some((from, to)) => {
+            let (from, to) = (year(from)?, year(to)?);
+            if from > to {
+                return Err(invalid());
+            }
+            Ok(format!("{from}-{to}"))
+        }
+        None => Ok(year(s)?.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::kind_of;
+
+    fn no_filters() -> Filters {
+        Filters {
+            artist: None,
+            album: None,
+            track: None,
+            year: None,
+            genre: None,
+            isrc: None,
+            upc: None,
+            tag: None,
+            exclude: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn compiles_filters_after_the_text_in_a_fixed_order() {
+        let filters = Filters {
+            exclude: vec!["live".to_string(), "remastered 2009".to_string()],
+            tag: Some(Tag::Hipster),
+            upc: Some("0602".to_string()),
+            genre: Some("jazz".to_string()),
+            year: Some("1955 - 1960".to_string()),
+            track: Some("So What".to_string()),
+            album: Some("Kind of Blue".to_string()),
+            artist: Some("Miles Davis".to_string()),
+            isrc: Some("USSM15900113".to_string()),
+        };
+        assert_eq!(
+            filters.compile("  modal ").unwrap(),
+            "modal artist:\"Miles Davis\" album:\"Kind of Blue\" track:\"So What\" \
+             year:1955-1960 genre:jazz isrc:USSM15900113 upc:0602 tag:hipster \
+             NOT live NOT \"remastered 2009\""
+        );
+    }
+
+    #[test]
+    fn compiles_filters_without_text() {
+        let filters = Filters {
+            genre: Some("jazz".to_string()),
+            ..no_filters()
+        };
+        assert_eq!(filters.compile(" ").unwrap(), "genre:jazz");
+        assert_eq!(no_filters().compile("so what").unwrap(), "so what");
+        assert_eq!(no_filters().compile("").unwrap(), "");
+    }
+
+    #[test]
+    fn spots_a_lone_artist_or_album_filter() {
+        let album = || Filters {
+            album: Some("Abbey Road".to_string()),
+            ..no_filters()
+        };
+        assert_eq!(album().lone_name_filter(), Some("album"));
+        let artist = Filters {
+            artist: Some("Kendrick Lamar".to_string()),
+            ..no_filters()
+        };
+        assert_eq!(artist.lone_name_filter(), Some("artist"));
+        let both = Filters {
+            artist: Some("The Beatles".to_string()),
+            ..album()
+        };
+        assert_eq!(both.lone_name_filter(), None);
+        let dated = Filters {
+            year: Some("1969".to_string()),
+            ..album()
+        };
+        assert_eq!(dated.lone_name_filter(), None);
+        assert_eq!(no_filters().lone_name_filter(), None);
+    }
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(quote("artist", " Coltrane ").unwrap(), "Coltrane");
+        assert_eq!(quote("artist", "John Coltrane").unwrap(), "\"John Coltrane\"");
+        assert_eq!(quote("track", "Interlude: Two").unwrap(), "\"Interlude: Two\"");
+        assert_eq!(quote("track", "a\tb").unwrap(), "\"a\tb\"");
+    }
+
+    #[test]
+    fn refuses_empty_values_and_double_quotes() {
+        let err = quote("album", "  ").unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::Usage);
+        assert_eq!(err.to_string(), "--album needs a value");
+        let err = quote("exclude", "say \"hi\"").unwrap_err();
+        assert_eq!(kind_of(&err), ErrorKind::Usage);
+        assert_eq!(err.to_string(), "--exclude can't contain double quotes");
+
+        let filters = Filters {
+            exclude: vec![String::new()],
+            ..no_filters()
+        };
+        assert_eq!(
+            filters.compile("x").unwrap_err().to_string(),
+            "--exclude needs a value"
+        );
+    }
+
+    #[test]
+    fn parses_years_and_ranges() {
+        assert_eq!(parse_year("1994").unwrap(), "1994");
+        assert_eq!(parse_year(" 1994 ").unwrap(), "1994");
+        assert_eq!(parse_year("1990-1999").unwrap(), "1990-1999");
+        assert_eq!(parse_year("1990 - 1999").unwrap(), "1990-1999");
+        assert_eq!(parse_year("1994-1994").unwrap(), "1994-1994");
+    }
+
+    #[test]
+    fn rejects_malformed_years() {
+        for bad in ["", "94", "19945", "199x", "+994", "1999-1990", "1990-", "-1990", "1990-1995-1999"] {
+            let err = parse_year(bad).unwrap_err();
+            assert_eq!(kind_of(&err), ErrorKind::Usage, "{bad:?}");
+            assert_eq!(
+                err.to_string(),
+                format!("Invalid year \"{bad}\"; use e.g. 1994 or 1990-1999")
+            );
+        }
+    }
+}
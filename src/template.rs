@@ -0,0 +1,283 @@
+//! Output templates for `spot now --format`.
+//!
+//! `{field}` inserts a field and `{field:N}` truncates it to N characters.
+//! `{?field}…{/field}` renders its body only when the field is set (or true),
+//! `{!field}…{/field}` only when it isn't. `{{` and `}}` are literal braces.
+
+use anyhow::Result;
+
+use crate::display;
+use crate::output::{ErrorKind, fail};
+use crate::spotify::player::NowPlayingInfo;
+
+const FIELDS: &[&str] = &[
+    "track",
+    "artist",
+    "album",
+    "track_id",
+    "position",
+    "duration",
+    "position_ms",
+    "duration_ms",
+    "percent",
+    "progress",
+    "state",
+    "playing",
+    "paused",
+];
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Field { name: String, width: Option<usize> },
+    Section { name: String, negate: bool, body: Vec<Node> },
+}
+
+#[derive(Debug)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut pos = 0;
+        let nodes = parse_nodes(&chars, &mut pos, None)?;
+        Ok(Self { nodes })
+    }
+
+    pub fn render(&self, info: &NowPlayingInfo) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, info, &mut out);
+        out
+    }
+}
+
+fn parse_nodes(chars: &[char], pos: &mut usize, closing: Option<&str>) -> Result<Vec<Node>> {
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+
+    while *pos < chars.len() {
+        let c = chars[*pos];
+        if (c == '{' || c == '}') && chars.get(*pos + 1) == Some(&c) {
+            text.push(c);
+            *pos += 2;
+            continue;
+        }
+        if c == '}' {
+            return Err(malformed(format!("Unmatched '}}' at position {} in format", *pos)));
+        }
+        if c != '{' {
+            text.push(c);
+            *pos += 1;
+            continue;
+        }
+
+        let start = *pos;
+        let end = chars[start..]
+            .iter()
+            .position(|&c| c == '}')
+            .map(|i| start + i)
+            .ok_or_else(|| malformed(format!("Unclosed '{{' at position {start} in format")))?;
+        let tag: String = chars[start + 1..end].iter().collect();
+        *pos = end + 1;
+
+        if !text.is_empty() {
+            nodes.push(Node::Text(std::mem::take(&mut text)));
+        }
+
+        if let Some(name) = tag.strip_prefix('/') {
+            if closing == Some(name) {
+                return Ok(nodes);
+            }
+            return Err(malformed(format!("Unexpected {{/{name}}} at position {start} in format")));
+        } else if let Some(name) = tag.strip_prefix('?').or_else(|| tag.strip_prefix('!')) {
+            check_field(name)?;
+            let body = parse_nodes(chars, pos, Some(name))?;
+            nodes.push(Node::Section {
+                name: name.to_string(),
+                negate: tag.starts_with('!'),
+                body,
+            });
+        } else {
+            let (name, width) = match tag.split_once(':') {
+                Some((name, width)) => {
+                    let width = width.parse::<usize>().map_err(|_| {
+                        malformed(format!("Invalid width \"{width}\" for {{{name}}} in format"))
+                    })?;
+                    (name, Some(width))
+                }
+                None => (tag.as_str(), None),
+            };
+            check_field(name)?;
+            nodes.push(Node::Field {
+                name: name.to_string(),
+                width,
+            });
+        }
+    }
+
+    if let Some(name) = closing {
+        return Err(malformed(format!("Missing {{/{name}}} in format")));
+    }
+    if !text.is_empty() {
+        nodes.push(Node::Text(text));
+    }
+    Ok(nodes)
+}
+
+fn check_field(name: &str) -> Result<()> {
+    if !FIELDS.contains(&name) {
+        return Err(malformed(format!(
+            "Unknown field {{{name}}} in format. Available: {}",
+            FIELDS.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// A mistake in a `--format` string, which the user typed.
+fn malformed(message: String) -> anyhow::Error {
+    fail(ErrorKind::Usage, message)
+}
+
+fn field(info: &NowPlayingInfo, name: &str) -> String {
+    match name {
+        "track" => info.track_name.clone(),
+        "artist" => info.artist.clone(),
+        "album" => info.album.clone(),
+        "track_id" => info.track_id.clone(),
+        "position" => display::format_duration(info.position_ms),
+        "duration" => display::format_duration(info.duration_ms),
+        "position_ms" => info.position_ms.to_string(),
+        "duration_ms" => info.duration_ms.to_string(),
+        "percent" => match info.duration_ms {
+            0 => "0".to_string(),
+            d => (info.position_ms * 100 / d).clamp(0, 100).to_string(),
+        },
+        "progress" => display::progress_bar(info.position_ms, info.duration_ms, 20),
+        "state" => if info.is_playing { "playing" } else { "paused" }.to_string(),
+        "playing" => info.is_playing.to_string(),
+        "paused" => (!info.is_playing).to_string(),
+        _ => String::new(),
+    }
+}
+
+fn is_set(info: &NowPlayingInfo, name: &str) -> bool {
+    match name {
+        "playing" => info.is_playing,
+        "paused" => !info.is_playing,
+        _ => !field(info, name).is_empty(),
+    }
+}
+
+fn render_nodes(nodes: &[Node], info: &NowPlayingInfo, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Field { name, width } => {
+                let value = field(info, name);
+                match width {
+                    Some(width) => out.push_str(&display::truncate(&value, *width)),
+                    None => out.push_str(&value),
+                }
+            }
+            Node::Section { name, negate, body } => {
+                if is_set(info, name) != *negate {
+                    render_nodes(body, info, out);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> NowPlayingInfo {
+        NowPlayingInfo {
+            track_name: "So What".to_string(),
+            artist: "Miles Davis".to_string(),
+            album: "Kind of Blue".to_string(),
+            track_id: "spotify:track:abc".to_string(),
+            duration_ms: 545_000,
+            position_ms: 65_000,
+            is_playing: true,
+        }
+    }
+
+    fn render(source: &str, info: &NowPlayingInfo) -> String {
+        Template::parse(source).unwrap().render(info)
+    }
+
+    fn parse_error(source: &str) -> String {
+        let err = Template::parse(source).unwrap_err();
+        assert_eq!(crate::output::kind_of(&err), ErrorKind::Usage, "{source}");
+        err.to_string()
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        let info = info();
+        assert_eq!(render("{artist} - {track}", &info), "Miles Davis - So What");
+        assert_eq!(render("{position}/{duration}", &info), "1:05/9:05");
+        assert_eq!(render("{position_ms} {percent}% {state}", &info), "65000 11% playing");
+        assert_eq!(render("{{{track}}}", &info), "{So What}");
+        assert_eq!(render("no fields", &info), "no fields");
+        assert_eq!(render("", &info), "");
+    }
+
+    #[test]
+    fn renders_sections_by_field() {
+        let mut info = info();
+        let source = "{?playing}▶{/playing}{!playing}⏸{/playing} {track}{?album} ({album}){/album}";
+        assert_eq!(render(source, &info), "▶ So What (Kind of Blue)");
+
+        info.is_playing = false;
+        info.album.clear();
+        assert_eq!(render(source, &info), "⏸ So What");
+        assert_eq!(render("{?paused}[{state}]{/paused}", &info), "[paused]");
+    }
+
+    #[test]
+    fn nests_sections() {
+        let mut info = info();
+        let source = "{?track}{?artist}{artist}: {/artist}{track}{/track}";
+        assert_eq!(render(source, &info), "Miles Davis: So What");
+        info.artist.clear();
+        assert_eq!(render(source, &info), "So What");
+        info.track_name.clear();
+        assert_eq!(render(source, &info), "");
+    }
+
+    #[test]
+    fn truncates_to_width() {
+        let info = info();
+        assert_eq!(render("{album:6}", &info), "Kind …");
+        assert_eq!(render("{album:12}", &info), "Kind of Blue");
+        assert_eq!(render("{album:40}", &info), "Kind of Blue");
+        assert_eq!(render("{album:1}", &info), "…");
+        assert_eq!(render("[{album:0}]", &info), "[]");
+    }
+
+    #[test]
+    fn truncates_by_character_not_byte() {
+        let mut info = info();
+        info.artist = "Sigur Rós Ólafur".to_string();
+        assert_eq!(render("{artist:10}", &info), "Sigur Rós…");
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert!(parse_error("{nope}").starts_with("Unknown field {nope}"));
+        assert!(parse_error("{?nope}x{/nope}").starts_with("Unknown field {nope}"));
+        assert!(parse_error("{track").starts_with("Unclosed '{'"));
+        assert!(parse_error("track}").starts_with("Unmatched '}'"));
+        assert_eq!(parse_error("{?track}x"), "Missing {/track} in format");
+        assert!(parse_error("{?track}x{/artist}").starts_with("Unexpected {/artist}"));
+        assert!(parse_error("x{/track}").starts_with("Unexpected {/track}"));
+        assert!(parse_error("{track:wide}").starts_with("Invalid width \"wide\""));
+        assert!(parse_error("{track:-1}").starts_with("Invalid width"));
+    }
+}
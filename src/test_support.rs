@@ -0,0 +1,351 @@
+//! Shared fixtures for unit tests.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use zbus::zvariant::OwnedValue;
+
+/// A dbus-daemon of our own, so tests never touch the real session bus.
+pub struct PrivateBus {
+    pub address: String,
+    daemon: Child,
+}
+
+impl PrivateBus {
+    /// Panics when `dbus-daemon` isn't installed, so the D-Bus tests fail
+    /// rather than pass without having run.
+    pub fn start() -> Self {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("start dbus-daemon, which the D-Bus tests need installed");
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .expect("read the private bus address");
+        Self {
+            address: address.trim().to_string(),
+            daemon,
+        }
+    }
+
+    pub async fn connect(&self) -> zbus::Connection {
+        zbus::connection::Builder::address(self.address.as_str())
+            .expect("parse the private bus address")
+            .build()
+            .await
+            .expect("connect to the private bus")
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+/// One `Notify` call as the fake notification server saw it.
+#[derive(Debug, Clone)]
+pub struct NotifyCall {
+    pub app_name: String,
+    pub replaces_id: u32,
+    pub app_icon: String,
+    pub summary: String,
+    pub body: String,
+    /// The string-valued hints.
+    pub hints: HashMap<String, String>,
+    pub expire_timeout: i32,
+}
+
+struct FakeNotifications {
+    calls: Arc<Mutex<Vec<NotifyCall>>>,
+    next_id: u32,
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl FakeNotifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &mut self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        _actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32 {
+        let hints = hints
+            .into_iter()
+            .filter_map(|(k, v)| Some((k, String::try_from(v).ok()?)))
+            .collect();
+        self.calls.lock().unwrap().push(NotifyCall {
+            app_name,
+            replaces_id,
+            app_icon,
+            summary,
+            body,
+            hints,
+            expire_timeout,
+        });
+        if replaces_id != 0 {
+            return replaces_id;
+        }
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+/// `org.freedesktop.Notifications` on a private bus, recording each call.
+/// Fields drop in order, so the bus goes last.
+pub struct NotificationServer {
+    calls: Arc<Mutex<Vec<NotifyCall>>>,
+    _server: zbus::blocking::Connection,
+    pub bus: PrivateBus,
+}
+
+impl NotificationServer {
+    /// Panics when `dbus-daemon` isn't installed, like [`PrivateBus::start`].
+    pub fn start() -> Self {
+        let bus = PrivateBus::start();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let server = zbus::blocking::connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name("org.freedesktop.Notifications")
+            .unwrap()
+            .serve_at(
+                "/org/freedesktop/Notifications",
+                FakeNotifications {
+                    calls: calls.clone(),
+                    next_id: 40,
+                },
+            )
+            .unwrap()
+            .build()
+            .unwrap();
+        Self {
+            calls,
+            _server: server,
+            bus,
+        }
+    }
+
+    pub fn calls(&self) -> Vec<NotifyCall> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+/// Variables tests point at stubs; [`EnvGuard`] puts them back.
+const TEST_VARS: &[&str] = &[
+    "SPOT_API_URL",
+    "SPOT_ACCOUNTS_URL",
+    "SPOT_LISTENBRAINZ_URL",
+    "SPOT_ACCESS_TOKEN",
+    "SPOT_CONFIG_DIR",
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+/// Held while a test changes process-wide environment variables. Dropping
+/// it restores them, so nothing leaks into the next test.
+pub struct EnvGuard {
+    saved: Vec<(&'static str, Option<std::ffi::OsString>)>,
+    _lock: tokio::sync::MutexGuard<'static, ()>,
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for (name, value) in &self.saved {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+    }
+}
+
+/// Serialises tests that set process-wide environment variables such as
+/// `SPOT_API_URL`. Async so it can be held across a test's awaits.
+pub async fn env_lock() -> EnvGuard {
+    static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let lock = LOCK.lock().await;
+    EnvGuard {
+        saved: TEST_VARS.iter().map(|&name| (name, std::env::var_os(name))).collect(),
+        _lock: lock,
+    }
+}
+
+/// Points `Config::config_dir` at a fresh directory until dropped, via
+/// `SPOT_CONFIG_DIR` so it holds on every platform. Hold `env_lock` while
+/// it's alive.
+pub struct ConfigHome {
+    pub dir: PathBuf,
+}
+
+impl ConfigHome {
+    pub fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "spot-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("SPOT_CONFIG_DIR", &dir);
+        Self { dir }
+    }
+}
+
+impl Drop for ConfigHome {
+    fn drop(&mut self) {
+        std::env::remove_var("SPOT_CONFIG_DIR");
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A request as the stub saw it.
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    /// Including the query string.
+    pub path: String,
+    pub body: String,
+}
+
+pub struct Route {
+    method: &'static str,
+    prefix: String,
+    status: u16,
+    body: String,
+}
+
+/// Answers `method` requests whose path starts with `prefix`.
+pub fn route(method: &'static str, prefix: impl Into<String>, status: u16, body: impl Into<String>) -> Route {
+    Route {
+        method,
+        prefix: prefix.into(),
+        status,
+        body: body.into(),
+    }
+}
+
+/// A canned HTTP server on the test's runtime. Each request gets the first
+/// matching route, or a 500 naming what was missing.
+pub struct Stub {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+    /// What `SPOT_API_URL` was before [`serve_api`](Self::serve_api), to
+    /// put back on drop.
+    replaced_api_url: Option<Option<std::ffi::OsString>>,
+}
+
+impl Stub {
+    pub async fn start(routes: Vec<Route>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let routes = Arc::new(routes);
+        let log = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (routes, log) = (routes.clone(), log.clone());
+                tokio::spawn(async move {
+                    let _ = serve(stream, &routes, &log).await;
+                });
+            }
+        });
+        Self {
+            url,
+            requests,
+            replaced_api_url: None,
+        }
+    }
+
+    /// Starts a stub and points the Web API at it through `SPOT_API_URL`
+    /// until dropped. Hold `env_lock` while it's alive; a second one in the
+    /// same test takes over until it goes out of scope.
+    pub async fn serve_api(routes: Vec<Route>) -> Self {
+        let mut stub = Self::start(routes).await;
+        stub.replaced_api_url = Some(std::env::var_os("SPOT_API_URL"));
+        std::env::set_var("SPOT_API_URL", &stub.url);
+        stub
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for Stub {
+    fn drop(&mut self) {
+        match self.replaced_api_url.take() {
+            Some(Some(url)) => std::env::set_var("SPOT_API_URL", url),
+            Some(None) => std::env::remove_var("SPOT_API_URL"),
+            None => {}
+        }
+    }
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    routes: &[Route],
+    log: &Mutex<Vec<Recorded>>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let head_end = loop {
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let length: usize = head
+        .lines()
+        .filter_map(|l| l.split_once(':'))
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.trim().parse().ok())
+        .unwrap_or(0);
+    while buf.len() < head_end + length {
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let body = String::from_utf8_lossy(&buf[head_end..]).to_string();
+    log.lock().unwrap().push(Recorded {
+        method: method.clone(),
+        path: path.clone(),
+        body,
+    });
+
+    let (status, body) = routes
+        .iter()
+        .find(|r| r.method == method && path.starts_with(&r.prefix))
+        .map(|r| (r.status, r.body.clone()))
+        .unwrap_or_else(|| (500, format!("no stub route for {method} {path}")));
+    let response = format!(
+        "HTTP/1.1 {status} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
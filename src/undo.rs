@@ -0,0 +1,48 @@
+//! What was playing before each play command, newest last, in `undo.json`
+//! in the config directory. `spot undo` pops it back.
+
+use anyhow::Result;
+
+use crate::config;
+use crate::snapshot::Snapshot;
+use crate::spotify::player::Player;
+
+/// Older entries drop off the bottom.
+const MAX_ENTRIES: usize = 20;
+
+const FILE: &str = "undo.json";
+
+pub fn load() -> Result<Vec<Snapshot>> {
+    Ok(config::load_json(FILE)?.unwrap_or_default())
+}
+
+fn save(stack: &[Snapshot]) -> Result<()> {
+    config::save_json_atomic(FILE, stack)
+}
+
+/// Pushes the current playback state, if anything is loaded. Never fails
+/// the play command that called it.
+pub async fn remember(player: &Player) {
+    let Ok(snapshot) = Snapshot::capture_quick(player).await else {
+        return;
+    };
+    let result = load().and_then(|mut stack| {
+        stack.push(snapshot);
+        let overflow = stack.len().saturating_sub(MAX_ENTRIES);
+        stack.drain(..overflow);
+        save(&stack)
+    });
+    if let Err(e) = result {
+        eprintln!("spot: could not save undo state: {e:#}");
+    }
+}
+
+/// Removes and returns the newest entry.
+pub fn pop() -> Result<Option<Snapshot>> {
+    let mut stack = load()?;
+    let top = stack.pop();
+    if top.is_some() {
+        save(&stack)?;
+    }
+    Ok(top)
+}
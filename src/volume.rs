@@ -0,0 +1,201 @@
+//! Volume changes beyond a plain set: relative steps, timed ramps along the
+//! `[volume] curve`, and the pre-mute level kept in `mute.json` in the
+//! config directory so `spot unmute` works from any terminal.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::Duration;
+
+use crate::config::{self, Config, VolumeCurve, VolumeSettings};
+use crate::output::{ErrorKind, fail};
+use crate::spotify::player::Player;
+
+/// Step for `spot vol up`, `+`/`-` keys and the like when unset.
+pub const DEFAULT_STEP: i32 = 5;
+
+/// Time between volume changes during a ramp. Each one is a request on the
+/// Connect backend, so not much finer than this.
+const RAMP_TICK: Duration = Duration::from_millis(100);
+
+impl VolumeSettings {
+    /// The configured step, kept within 1 to 100 so `up` always goes up.
+    pub fn step(&self) -> i32 {
+        self.step.unwrap_or(DEFAULT_STEP).clamp(1, 100)
+    }
+
+    /// Fade for pause and resume, if configured.
+    pub fn pause_fade(&self) -> Option<Duration> {
+        self.pause_fade_ms
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis)
+    }
+}
+
+impl VolumeCurve {
+    /// The level `progress` (0 to 1) of the way from `from` to `to`.
+    pub fn level(self, from: i32, to: i32, progress: f64) -> i32 {
+        let p = progress.clamp(0.0, 1.0);
+        let eased = match self {
+            Self::Linear => p,
+            Self::Smooth => p * p * (3.0 - 2.0 * p),
+            // Loudness is roughly logarithmic, so change slowly near silence.
+            Self::Exponential if to > from => p * p,
+            Self::Exponential => 1.0 - (1.0 - p) * (1.0 - p),
+        };
+        from + (f64::from(to - from) * eased).round() as i32
+    }
+}
+
+/// Resolves a `spot vol` argument against the current level: `30`, `+10`,
+/// `-5`, or `up`/`down` (also `+`/`-`) for one configured step.
+pub fn parse_level(arg: &str, current: i32, step: i32) -> Result<i32> {
+    let usage = || {
+        fail(
+            ErrorKind::Usage,
+            format!("Invalid volume \"{arg}\"; use e.g. 30, +10, -5, up or down"),
+        )
+    };
+    // Digits only. Anything past 100 moves the volume as far as 100 does,
+    // however many digits it has.
+    let amount = |digits: &str| -> Result<i32> {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(usage());
+        }
+        Ok(digits.parse::<u64>().map_or(100, |n| n.min(100)) as i32)
+    };
+    let level = match arg {
+        "up" | "+" => current.saturating_add(step),
+        "down" | "-" => current.saturating_sub(step),
+        _ => match (arg.strip_prefix('+'), arg.strip_prefix('-')) {
+            (Some(delta), _) => current.saturating_add(amount(delta)?),
+            (_, Some(delta)) => current.saturating_sub(amount(delta)?),
+            _ => amount(arg)?,
+        },
+    };
+    Ok(level.clamp(0, 100))
+}
+
+/// Moves the volume from `from` to `to` over `over` along `curve`.
+pub async fn ramp(player: &Player, from: i32, to: i32, over: Duration, curve: VolumeCurve) -> Result<()> {
+    let steps = (over.as_millis() / RAMP_TICK.as_millis()).max(1) as u32;
+    let mut last = from;
+    for i in 1..=steps {
+        tokio::time::sleep(over / steps).await;
+        let level = curve.level(from, to, f64::from(i) / f64::from(steps));
+        if level != last {
+            player.set_volume(level).await?;
+            last = level;
+        }
+    }
+    if last != to {
+        player.set_volume(to).await?;
+    }
+    Ok(())
+}
+
+/// Sets the volume, ramping when `over` is given.
+pub async fn set(player: &Player, from: i32, to: i32, over: Option<Duration>, curve: VolumeCurve) -> Result<()> {
+    match over {
+        Some(over) if !over.is_zero() => ramp(player, from, to, over, curve).await,
+        _ => player.set_volume(to).await,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MuteState {
+    volume: i32,
+}
+
+const MUTE_FILE: &str = "mute.json";
+
+/// The level saved by `spot mute`, if muted.
+pub fn muted_volume() -> Option<i32> {
+    let state: MuteState = config::load_json(MUTE_FILE).ok().flatten()?;
+    Some(state.volume)
+}
+
+pub fn save_muted(volume: i32) -> Result<()> {
+    config::save_json_atomic(MUTE_FILE, &MuteState { volume })
+}
+
+pub fn clear_muted() {
+    if let Ok(path) = Config::state_path(MUTE_FILE) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::kind_of;
+
+    #[test]
+    fn absolute_levels() {
+        assert_eq!(parse_level("30", 80, 5).unwrap(), 30);
+        assert_eq!(parse_level("0", 80, 5).unwrap(), 0);
+        assert_eq!(parse_level("150", 80, 5).unwrap(), 100);
+        assert_eq!(parse_level("99999999999999999999999", 80, 5).unwrap(), 100);
+    }
+
+    #[test]
+    fn relative_levels() {
+        assert_eq!(parse_level("+10", 50, 5).unwrap(), 60);
+        assert_eq!(parse_level("-5", 50, 5).unwrap(), 45);
+        assert_eq!(parse_level("+60", 50, 5).unwrap(), 100);
+        assert_eq!(parse_level("-60", 50, 5).unwrap(), 0);
+        assert_eq!(parse_level("+99999999999", 50, 5).unwrap(), 100);
+        assert_eq!(parse_level("-99999999999", 50, 5).unwrap(), 0);
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(parse_level("up", 50, 5).unwrap(), 55);
+        assert_eq!(parse_level("+", 50, 5).unwrap(), 55);
+        assert_eq!(parse_level("down", 50, 5).unwrap(), 45);
+        assert_eq!(parse_level("-", 3, 5).unwrap(), 0);
+        assert_eq!(parse_level("up", 50, i32::MAX).unwrap(), 100);
+        assert_eq!(parse_level("down", 50, i32::MAX).unwrap(), 0);
+    }
+
+    #[test]
+    fn configured_steps_stay_in_range() {
+        let step = |step| VolumeSettings { step, ..Default::default() }.step();
+        assert_eq!(step(None), DEFAULT_STEP);
+        assert_eq!(step(Some(10)), 10);
+        assert_eq!(step(Some(0)), 1);
+        assert_eq!(step(Some(-20)), 1);
+        assert_eq!(step(Some(i32::MIN)), 1);
+        assert_eq!(step(Some(500)), 100);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for bad in ["", "loud", "+-5", "-+5", "5%", "1e3", " 30", "++"] {
+            let err = parse_level(bad, 50, 5).unwrap_err();
+            assert_eq!(kind_of(&err), ErrorKind::Usage, "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn curves_start_and_end_where_asked() {
+        for curve in [VolumeCurve::Linear, VolumeCurve::Smooth, VolumeCurve::Exponential] {
+            for (from, to) in [(0, 100), (100, 0), (20, 65), (40, 40)] {
+                assert_eq!(curve.level(from, to, 0.0), from, "{curve:?} {from}→{to}");
+                assert_eq!(curve.level(from, to, 1.0), to, "{curve:?} {from}→{to}");
+                assert_eq!(curve.level(from, to, -1.0), from, "{curve:?} {from}→{to}");
+                assert_eq!(curve.level(from, to, 2.0), to, "{curve:?} {from}→{to}");
+            }
+        }
+    }
+
+    #[test]
+    fn curves_differ_in_between() {
+        assert_eq!(VolumeCurve::Linear.level(0, 100, 0.25), 25);
+        assert_eq!(VolumeCurve::Smooth.level(0, 100, 0.25), 16);
+        assert_eq!(VolumeCurve::Smooth.level(0, 100, 0.5), 50);
+        // Slow near silence whichever way it's going.
+        assert_eq!(VolumeCurve::Exponential.level(0, 100, 0.5), 25);
+        assert_eq!(VolumeCurve::Exponential.level(100, 0, 0.5), 25);
+    }
+}